@@ -50,6 +50,8 @@ public class Application
             };
 
         virtualLayer = new VirtualLayer(configuration);
+        virtualLayer.Disabled += reason =>
+            ColorPrinter.WriteError("Disabled the virtual layer: " + reason);
     }
 
     private void Update(string path)
@@ -58,8 +60,8 @@ public class Application
         {
             try
             {
-                var newConfiguration = AklConfiguration.FromString(
-                    File.ReadAllText(path)
+                var newConfiguration = AklConfiguration.FromFile(
+                    new FileInfo(path)
                 );
 
                 virtualLayer.Configuration = newConfiguration;
@@ -91,6 +93,25 @@ public class Application
         }
     }
 
+    public void RunScenario(FileInfo scenarioFile)
+    {
+        if (!scenarioFile.Exists)
+        {
+            ColorPrinter.WriteError("The scenario option only accepts existing files.");
+            Environment.Exit(1);
+        }
+
+        var failure = virtualLayer.RunScenario(File.ReadAllText(scenarioFile.FullName));
+
+        if (failure != null)
+        {
+            ColorPrinter.WriteError(failure);
+            Environment.Exit(1);
+        }
+
+        ColorPrinter.WriteSuccessful("All responses match the scenario.");
+    }
+
     public void Run()
     {
         if (SingleInstanceChecker.IsOtherAlreadyRunning("akl-application"))
@@ -45,6 +45,17 @@ public class ApplicationBuilder
         );
     }
 
+    public static void ConfigureLogging(string sink, AKL.Core.FfiLogLevel level)
+    {
+        var error = VirtualLayer.ConfigureLogging(sink, level);
+
+        if (error != null)
+        {
+            ColorPrinter.WriteError($"Couldn't configure logging: {error}");
+            Environment.Exit(1);
+        }
+    }
+
     private static FileInfo GetDefaultConfig()
     {
         var configDirectory = Environment.GetEnvironmentVariable("XDG_CONFIG_HOME");
@@ -27,6 +27,26 @@ var hideWindowOption = new Option<bool>("--hide-window");
 hideWindowOption.Description =
 @"Hides the console window this application was started in. Used for autostart.";
 
+var scenarioOption = new Option<FileInfo?>("--scenario");
+scenarioOption.AddAlias("-s");
+
+scenarioOption.Description =
+@"Checks the configuration against the events and expected responses in the
+scenario file instead of starting another keyboard layer. The program will exit
+with a non zero code if any response doesn't match.";
+
+var logOption = new Option<string?>("--log");
+
+logOption.Description =
+@"Write the log of another keyboard layer to the sink which is one of stderr,
+file:<path> or tcp:<address>. Logging is turned off by default.";
+
+var logLevelOption = new Option<AKL.Core.FfiLogLevel>("--log-level", () => AKL.Core.FfiLogLevel.Info);
+
+logLevelOption.Description =
+@"Most verbose level that is written to the log sink. Trace includes every
+processed key event.";
+
 // Command definition
 
 var command = new RootCommand("Run another keyboard layer from the terminal.")
@@ -34,14 +54,26 @@ var command = new RootCommand("Run another keyboard layer from the terminal.")
     configFileOption,
     liveReloadOption,
     hideWindowOption,
+    scenarioOption,
+    logOption,
+    logLevelOption,
 };
 
 // Executing the command
 
 command.SetHandler(
-    (configFile, liveReload, hideWindow) =>
-        ApplicationBuilder.Build(configFile, liveReload, hideWindow).Run(),
-    configFileOption, liveReloadOption, hideWindowOption
+    (configFile, liveReload, hideWindow, scenarioFile, logSink, logLevel) =>
+    {
+        if (logSink != null)
+            ApplicationBuilder.ConfigureLogging(logSink, logLevel);
+
+        if (scenarioFile != null)
+            ApplicationBuilder.Build(configFile, false, false).RunScenario(scenarioFile);
+        else
+            ApplicationBuilder.Build(configFile, liveReload, hideWindow).Run();
+    },
+    configFileOption, liveReloadOption, hideWindowOption, scenarioOption,
+    logOption, logLevelOption
 );
 
 command.Invoke(args);
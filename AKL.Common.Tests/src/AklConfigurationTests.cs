@@ -43,6 +43,83 @@ public class AklConfigurationTests
         Assert.AreEqual(originalHash, fromSerializationHash);
     }
 
+    [TestMethod]
+    public void TestPauseCombination()
+    {
+        var configuration = "start_with_system = false\nswitch_key = \"CapsLock\"\ndefault_simulation_combination = \"\"\n";
+
+        Assert.IsNull(AklConfiguration.FromString(configuration + "[mappings]\n").PauseCombination);
+
+        var paused = AklConfiguration.FromString(configuration + "pause_combination = \"LControl+LAlt+p\"\n[mappings]\n");
+
+        Assert.AreEqual(KeyCombination.TryParse("LControl+LAlt+p"), paused.PauseCombination);
+        Assert.AreEqual(paused, AklConfiguration.FromString(paused.ToString()));
+    }
+
+    [TestMethod]
+    public void TestProfiles()
+    {
+        var configuration = AklConfiguration.FromString(
+            "start_with_system = false\n" +
+            "switch_key = \"CapsLock\"\n" +
+            "default_simulation_combination = \"Escape\"\n" +
+            "[mappings]\n" +
+            "[profile_mappings]\n" +
+            "\"LAlt+2\" = \"gaming\"\n" +
+            "[profiles.gaming]\n" +
+            "switch_key = \"Tab\"\n" +
+            "[profiles.gaming.profile_mappings]\n" +
+            "\"LAlt+1\" = \"default\"\n"
+        );
+
+        var gaming = configuration.Profiles["gaming"];
+
+        Assert.AreEqual(Key.TryParse("Tab"), gaming.SwitchKey);
+        Assert.IsNull(gaming.DefaultCombination);
+        Assert.AreEqual("gaming", configuration.ProfileMappings[KeyCombination.TryParse("LAlt+2")]);
+        Assert.AreEqual(configuration, AklConfiguration.FromString(configuration.ToString()));
+
+        var mappings = "start_with_system = false\nswitch_key = \"CapsLock\"\ndefault_simulation_combination = \"\"\n[mappings]\n";
+
+        // Unknown profile
+        Assert.ThrowsException<AklConfigurationParsingException>(() => AklConfiguration.FromString(mappings + "[profile_mappings]\n\"LAlt+2\" = \"gaming\"\n"));
+        // Profile without switch key
+        Assert.ThrowsException<AklConfigurationParsingException>(() => AklConfiguration.FromString(mappings + "[profiles.gaming]\ntext_output = \"layout\"\n"));
+        // Reserved name
+        Assert.ThrowsException<AklConfigurationParsingException>(() => AklConfiguration.FromString(mappings + "[profiles.default]\nswitch_key = \"Tab\"\n"));
+    }
+
+    [TestMethod]
+    public void TestApplicationMappings()
+    {
+        var configuration = AklConfiguration.FromString(
+            "start_with_system = false\n" +
+            "switch_key = \"CapsLock\"\n" +
+            "default_simulation_combination = \"Escape\"\n" +
+            "[mappings]\n" +
+            "\"h\" = \"LeftArrow\"\n" +
+            "[[application_mappings]]\n" +
+            "window_class = \"Chrome_WidgetWin_1\"\n" +
+            "window_title = \"Visual Studio Code\"\n" +
+            "[application_mappings.mappings]\n" +
+            "\"h\" = \"Home\"\n" +
+            "[[application_mappings]]\n" +
+            "window_class = \"Notepad\"\n" +
+            "[application_mappings.mappings]\n" +
+            "\"h\" = \"LControl+Home\"\n"
+        );
+
+        Assert.AreEqual(2, configuration.ApplicationMappings.Count);
+
+        var editor = configuration.ApplicationMappings[0];
+
+        Assert.AreEqual("Chrome_WidgetWin_1", editor.WindowClass);
+        Assert.AreEqual("Visual Studio Code", editor.WindowTitle);
+        Assert.AreEqual(KeyCombination.TryParse("Home"), editor.Mappings[KeyCombination.TryParse("h")]);
+        Assert.IsNull(configuration.ApplicationMappings[1].WindowTitle);
+        Assert.AreEqual(configuration, AklConfiguration.FromString(configuration.ToString()));
+    }
+
     [TestMethod]
     public void TestEndToEndWithProvider()
     {
@@ -26,6 +26,16 @@ public class KeyTests
         Assert.AreEqual(expectedKey, parsedKey);
         Assert.AreEqual(expectedKey.GetHashCode(), parsedKey.GetHashCode());
 
+        expectedKey = new Key(VirtualKeyCode.LControl, null, KeyKind.Virtual);
+
+        Assert.AreEqual(expectedKey, Key.TryParse("Strg"));
+        Assert.AreEqual(expectedKey, Key.TryParse("lcontrol"));
+
+        expectedKey = new Key(0xBA);
+
+        Assert.AreEqual(expectedKey, Key.TryParse("raw:186"));
+        Assert.AreEqual("vk:0xBA", expectedKey.ToString());
+
         Assert.ThrowsException<ArgumentException>(() => Key.TryParse("With Whitespace"));
         Assert.ThrowsException<ArgumentException>(() => Key.TryParse("This key doesn't exist"));
     }
@@ -1,5 +1,6 @@
 namespace AKL.Common;
 
+using AKL.Core;
 using Tomlyn;
 using Tomlyn.Model;
 
@@ -17,6 +18,12 @@ public class AklConfigurationParsingException : Exception
 public class AklConfiguration
 {
 
+    /// <summary>
+    ///     Name of the profile that holds the top level configuration, so that
+    ///     profile mappings can switch back to it.
+    /// </summary>
+    public const string DefaultProfile = "default";
+
     private readonly TomlAklConfiguration origin;
 
     public bool Autostart { get; set; }
@@ -24,8 +31,53 @@ public class AklConfiguration
     public Key SwitchKey { get; set; }
     public KeyCombination? DefaultCombination { get; set; }
 
+    /// <summary>
+    ///     Combination that immediately disables the virtual layer, the core
+    ///     library uses <c>LControl+LAlt+LShift+Escape</c> if it is null.
+    /// </summary>
+    public KeyCombination? KillSwitch { get; set; }
+
+    /// <summary>
+    ///     Combination that pauses the virtual layer and resumes it when
+    ///     pressed again, null if there is none. Like the kill switch it's
+    ///     only read from the top level and shared by all profiles.
+    /// </summary>
+    public KeyCombination? PauseCombination { get; set; }
+
     public Dictionary<KeyCombination, KeyCombination> Mappings { get; set; } = new Dictionary<KeyCombination, KeyCombination>();
 
+    /// <summary>
+    ///     How replacements simulate text keys, see <c>text_output</c> in the
+    ///     default configuration.
+    /// </summary>
+    public FfiTextOutput TextOutput { get; set; } = FfiTextOutput.Unicode;
+
+    /// <summary>
+    ///     Mappings whose target moves the pointer, clicks or scrolls instead
+    ///     of simulating a key combination.
+    /// </summary>
+    public Dictionary<KeyCombination, FfiMouseAction> MouseMappings { get; set; } = new Dictionary<KeyCombination, FfiMouseAction>();
+
+    /// <summary>
+    ///     Mappings whose target switches to the profile with the name instead
+    ///     of simulating a key combination.
+    /// </summary>
+    public Dictionary<KeyCombination, string> ProfileMappings { get; set; } = new Dictionary<KeyCombination, string>();
+
+    /// <summary>
+    ///     Mappings that take precedence over <see cref="Mappings"/> while a
+    ///     matching window is in the foreground, the first matching set wins.
+    /// </summary>
+    public List<ApplicationMappingSet> ApplicationMappings { get; set; } = new List<ApplicationMappingSet>();
+
+    /// <summary>
+    ///     Named configurations the virtual layer can switch between while it
+    ///     is running. Each profile has its own switch key, default
+    ///     combination, text output and mappings, everything else is shared
+    ///     with this configuration.
+    /// </summary>
+    public Dictionary<string, AklConfiguration> Profiles { get; set; } = new Dictionary<string, AklConfiguration>();
+
     /// <summary>
     ///     Parses the raw toml configuration and deserializes it's values.
     /// 
@@ -59,17 +111,56 @@ public class AklConfiguration
     /// </exception>
     public static AklConfiguration FromString(string raw)
     {
-        TomlAklConfiguration model;
+        return FromModel(ParseModel(raw));
+    }
+
+    /// <summary>
+    ///     Like <see cref="AklConfiguration.FromString(string)"/> but the
+    ///     configuration file can also include other files and import mapping
+    ///     groups, see <c>include</c> in the default configuration.
+    ///
+    ///     The core library composes such files. Only the file itself sets
+    ///     <c>start_with_system</c> and the <c>kill_switch</c>.
+    /// </summary>
+    /// <param name="file">The toml configuration file.</param>
+    /// <returns>
+    ///     A fully parsed AklConfiguration ready for use with the virtual layer.
+    /// </returns>
+    /// <exception cref="AklConfigurationParsingException">
+    ///     For the same reasons as <see cref="AklConfiguration.FromString(string)"/>
+    ///     and if the included files can't be read, include each other or
+    ///     contain conflicting mappings. The message names the file and line
+    ///     of each conflicting mapping.
+    /// </exception>
+    public static AklConfiguration FromFile(FileInfo file)
+    {
+        var model = ParseModel(File.ReadAllText(file.FullName));
+
+        var isComposed = model.Include != null
+            || model.Import != null
+            || model.MappingGroups != null
+            || (model.Profiles?.Values.Any((profile) => profile.Import != null) ?? false);
+
+        if (isComposed)
+            Compose(model, file);
 
+        return FromModel(model);
+    }
+
+    private static TomlAklConfiguration ParseModel(string raw)
+    {
         try
         {
-            model = Toml.ToModel<TomlAklConfiguration>(raw);
+            return Toml.ToModel<TomlAklConfiguration>(raw);
         }
         catch (TomlException exception)
         {
             throw new AklConfigurationParsingException("Can't parse toml akl configuration: " + exception.Message);
         }
+    }
 
+    private static AklConfiguration FromModel(TomlAklConfiguration model)
+    {
         AklConfiguration configuration;
 
         try
@@ -84,6 +175,77 @@ public class AklConfiguration
         return configuration;
     }
 
+    /// <summary>
+    ///     Replaces the values of the model with the ones the core library
+    ///     composed out of the file and every file it includes.
+    /// </summary>
+    private static void Compose(TomlAklConfiguration model, FileInfo file)
+    {
+        TomlComposedConfiguration composed;
+
+        try
+        {
+            composed = Toml.ToModel<TomlComposedConfiguration>(ComposeNative(file));
+        }
+        catch (TomlException exception)
+        {
+            throw new AklConfigurationParsingException("Can't parse the composed akl configuration: " + exception.Message);
+        }
+
+        model.SwitchKey = composed.SwitchKey;
+        // The core library doesn't distinguish a disabled default combination
+        // from a missing one.
+        model.DefaultSimulationCombination = composed.DefaultCombination ?? "";
+        model.PauseCombination = composed.PauseCombination;
+        model.TextOutput = composed.TextOutput;
+        model.Mappings = composed.Mappings ?? new Dictionary<string, string>();
+        model.MouseMappings = composed.MouseMappings;
+        model.ProfileMappings = composed.ProfileMappings;
+        model.ApplicationMappings = composed.ApplicationMappings;
+        model.Profiles = composed.Profiles?.ToDictionary((kvp) => kvp.Key, (kvp) => new TomlAklProfile
+        {
+            SwitchKey = kvp.Value.SwitchKey,
+            DefaultSimulationCombination = kvp.Value.DefaultCombination,
+            TextOutput = kvp.Value.TextOutput,
+            Mappings = kvp.Value.Mappings,
+            MouseMappings = kvp.Value.MouseMappings,
+            ProfileMappings = kvp.Value.ProfileMappings,
+            ApplicationMappings = kvp.Value.ApplicationMappings,
+        });
+    }
+
+    private static unsafe string ComposeNative(FileInfo file)
+    {
+        var path = System.Text.Encoding.UTF8.GetBytes(file.FullName);
+        byte[] raw;
+        nuint length = 0;
+
+        fixed (byte* pathPointer = path)
+        {
+            ThrowOnError(AklCoreNativeInterface.compose_configuration(pathPointer, (nuint) path.Length, null, 0, &length));
+
+            raw = new byte[(int) length];
+
+            fixed (byte* rawPointer = raw)
+            {
+                ThrowOnError(AklCoreNativeInterface.compose_configuration(pathPointer, (nuint) path.Length, rawPointer, length, &length));
+            }
+        }
+
+        return System.Text.Encoding.UTF8.GetString(raw);
+    }
+
+    private static unsafe void ThrowOnError(FfiResult result)
+    {
+        if (!result.has_error)
+            return;
+
+        var message = new string(result.error_message);
+        AklCoreNativeInterface.destroy_error_message(result.error_message);
+
+        throw new AklConfigurationParsingException(message);
+    }
+
     /// <summary>
     ///     Creates a valid akl configuration from the specified origin.
     /// </summary>
@@ -123,7 +285,118 @@ public class AklConfiguration
             DefaultCombination = KeyCombination.TryParse(origin.DefaultSimulationCombination ?? "Can't be null!");
         }
 
+        // Optional because the core library has a sensible default.
+        if (!string.IsNullOrEmpty(origin.KillSwitch))
+            KillSwitch = KeyCombination.TryParse(origin.KillSwitch);
+
+        if (!string.IsNullOrEmpty(origin.PauseCombination))
+            PauseCombination = KeyCombination.TryParse(origin.PauseCombination);
+
         Mappings = origin.Mappings.ToDictionary((kvp) => KeyCombination.TryParse(kvp.Key), (kvp) => KeyCombination.TryParse(kvp.Value));
+
+        // Optional because unicode output is the previous behavior.
+        if (!string.IsNullOrEmpty(origin.TextOutput))
+            TextOutput = ParseTextOutput(origin.TextOutput);
+
+        // Optional because older configuration files don't have it.
+        if (origin.MouseMappings != null)
+            MouseMappings = origin.MouseMappings.ToDictionary((kvp) => KeyCombination.TryParse(kvp.Key), (kvp) => ParseMouseAction(kvp.Value));
+
+        if (origin.ProfileMappings != null)
+            ProfileMappings = origin.ProfileMappings.ToDictionary((kvp) => KeyCombination.TryParse(kvp.Key), (kvp) => kvp.Value);
+
+        if (origin.ApplicationMappings != null)
+            ApplicationMappings = origin.ApplicationMappings.Select(ParseApplicationMappings).ToList();
+
+        if (origin.Profiles != null)
+        {
+            if (origin.Profiles.ContainsKey(DefaultProfile))
+                throw new AklConfigurationParsingException($"The profile name \"{DefaultProfile}\" is reserved for the top level configuration.");
+
+            Profiles = origin.Profiles.ToDictionary((kvp) => kvp.Key, (kvp) => new AklConfiguration(origin, kvp.Key, kvp.Value));
+        }
+
+        var unknownProfile = Profiles.Values
+            .Prepend(this)
+            .SelectMany((profile) => profile.ProfileMappings.Values)
+            .FirstOrDefault((name) => name != DefaultProfile && !Profiles.ContainsKey(name));
+
+        if (unknownProfile != null)
+            throw new AklConfigurationParsingException($"There is no profile named \"{unknownProfile}\".");
+    }
+
+    /// <summary>
+    ///     Creates the profile with the name from its section in the origin.
+    ///     Only the switch key is required.
+    /// </summary>
+    private AklConfiguration(TomlAklConfiguration origin, string name, TomlAklProfile profile)
+    {
+        this.origin = origin;
+
+        if (profile.SwitchKey == null)
+            throw new AklConfigurationParsingException($"No switch key in profile \"{name}\".");
+
+        SwitchKey = Key.TryParse(profile.SwitchKey);
+
+        if (!string.IsNullOrEmpty(profile.DefaultSimulationCombination))
+            DefaultCombination = KeyCombination.TryParse(profile.DefaultSimulationCombination);
+
+        if (!string.IsNullOrEmpty(profile.TextOutput))
+            TextOutput = ParseTextOutput(profile.TextOutput);
+
+        if (profile.Mappings != null)
+            Mappings = profile.Mappings.ToDictionary((kvp) => KeyCombination.TryParse(kvp.Key), (kvp) => KeyCombination.TryParse(kvp.Value));
+
+        if (profile.MouseMappings != null)
+            MouseMappings = profile.MouseMappings.ToDictionary((kvp) => KeyCombination.TryParse(kvp.Key), (kvp) => ParseMouseAction(kvp.Value));
+
+        if (profile.ProfileMappings != null)
+            ProfileMappings = profile.ProfileMappings.ToDictionary((kvp) => KeyCombination.TryParse(kvp.Key), (kvp) => kvp.Value);
+
+        if (profile.ApplicationMappings != null)
+            ApplicationMappings = profile.ApplicationMappings.Select(ParseApplicationMappings).ToList();
+    }
+
+    private static ApplicationMappingSet ParseApplicationMappings(TomlApplicationMappings raw)
+    {
+        return new ApplicationMappingSet
+        {
+            WindowClass = string.IsNullOrEmpty(raw.WindowClass) ? null : raw.WindowClass,
+            WindowTitle = string.IsNullOrEmpty(raw.WindowTitle) ? null : raw.WindowTitle,
+            Mappings = raw.Mappings?.ToDictionary((kvp) => KeyCombination.TryParse(kvp.Key), (kvp) => KeyCombination.TryParse(kvp.Value))
+                ?? new Dictionary<KeyCombination, KeyCombination>(),
+        };
+    }
+
+    private static List<TomlApplicationMappings>? ToTomlApplicationMappings(List<ApplicationMappingSet> applicationMappings)
+    {
+        if (applicationMappings.Count == 0)
+            return null;
+
+        return applicationMappings.Select((set) => new TomlApplicationMappings
+        {
+            WindowClass = set.WindowClass,
+            WindowTitle = set.WindowTitle,
+            Mappings = set.Mappings.ToDictionary((kvp) => kvp.Key.ToString() ?? "", (kvp) => kvp.Value.ToString() ?? ""),
+        }).ToList();
+    }
+
+    private static FfiTextOutput ParseTextOutput(string raw)
+    {
+        return raw switch
+        {
+            "unicode" => FfiTextOutput.Unicode,
+            "layout" => FfiTextOutput.Layout,
+            _ => throw new AklConfigurationParsingException($"Unknown text output \"{raw}\" (valid: unicode, layout)."),
+        };
+    }
+
+    private static FfiMouseAction ParseMouseAction(string raw)
+    {
+        if (Enum.TryParse(raw, false, out FfiMouseAction mouseAction) && mouseAction != FfiMouseAction.None)
+            return mouseAction;
+
+        throw new AklConfigurationParsingException($"Unknown mouse action \"{raw}\".");
     }
 
     public override bool Equals(object? obj)
@@ -152,9 +425,40 @@ public class AklConfiguration
                     && this.Mappings[key].Equals(other.Mappings[key])
             );
 
+        bool mouseMappingsEqual =
+            this.MouseMappings.Keys.Count == other.MouseMappings.Keys.Count &&
+            this.MouseMappings.Keys.All(
+                key => other.MouseMappings.ContainsKey(key)
+                    && this.MouseMappings[key] == other.MouseMappings[key]
+            );
+
+        bool profileMappingsEqual =
+            this.ProfileMappings.Keys.Count == other.ProfileMappings.Keys.Count &&
+            this.ProfileMappings.Keys.All(
+                key => other.ProfileMappings.ContainsKey(key)
+                    && this.ProfileMappings[key] == other.ProfileMappings[key]
+            );
+
+        bool applicationMappingsEqual =
+            this.ApplicationMappings.SequenceEqual(other.ApplicationMappings);
+
+        bool profilesEqual =
+            this.Profiles.Keys.Count == other.Profiles.Keys.Count &&
+            this.Profiles.Keys.All(
+                name => other.Profiles.ContainsKey(name)
+                    && this.Profiles[name].Equals(other.Profiles[name])
+            );
+
         return this.Autostart == other.Autostart &&
             this.SwitchKey.Equals(other.SwitchKey) &&
-            mappingsEqual;
+            Equals(this.KillSwitch, other.KillSwitch) &&
+            Equals(this.PauseCombination, other.PauseCombination) &&
+            this.TextOutput == other.TextOutput &&
+            mappingsEqual &&
+            mouseMappingsEqual &&
+            profileMappingsEqual &&
+            applicationMappingsEqual &&
+            profilesEqual;
     }
 
     public override int GetHashCode()
@@ -173,11 +477,83 @@ public class AklConfiguration
         origin.StartWithSystem = this.Autostart;
         origin.SwitchKey = this.SwitchKey.ToString();
         origin.DefaultSimulationCombination = this.DefaultCombination?.ToString();
+        origin.KillSwitch = this.KillSwitch?.ToString();
+        origin.PauseCombination = this.PauseCombination?.ToString();
+        origin.TextOutput = this.TextOutput.ToString().ToLowerInvariant();
         origin.Mappings = this.Mappings.ToDictionary((kvp) => kvp.Key.ToString() ?? "", (kvp) => kvp.Value.ToString() ?? "");
+        origin.MouseMappings = this.MouseMappings.Count == 0
+            ? null
+            : this.MouseMappings.ToDictionary((kvp) => kvp.Key.ToString() ?? "", (kvp) => kvp.Value.ToString());
+        origin.ProfileMappings = this.ProfileMappings.Count == 0
+            ? null
+            : this.ProfileMappings.ToDictionary((kvp) => kvp.Key.ToString() ?? "", (kvp) => kvp.Value);
+        origin.ApplicationMappings = ToTomlApplicationMappings(this.ApplicationMappings);
+        origin.Profiles = this.Profiles.Count == 0
+            ? null
+            : this.Profiles.ToDictionary((kvp) => kvp.Key, (kvp) => kvp.Value.ToTomlProfile());
 
         return Toml.FromModel(origin);
     }
 
+    private TomlAklProfile ToTomlProfile()
+    {
+        return new TomlAklProfile
+        {
+            SwitchKey = this.SwitchKey.ToString(),
+            DefaultSimulationCombination = this.DefaultCombination?.ToString(),
+            TextOutput = this.TextOutput.ToString().ToLowerInvariant(),
+            Mappings = this.Mappings.ToDictionary((kvp) => kvp.Key.ToString() ?? "", (kvp) => kvp.Value.ToString() ?? ""),
+            MouseMappings = this.MouseMappings.Count == 0
+                ? null
+                : this.MouseMappings.ToDictionary((kvp) => kvp.Key.ToString() ?? "", (kvp) => kvp.Value.ToString()),
+            ProfileMappings = this.ProfileMappings.Count == 0
+                ? null
+                : this.ProfileMappings.ToDictionary((kvp) => kvp.Key.ToString() ?? "", (kvp) => kvp.Value),
+            ApplicationMappings = ToTomlApplicationMappings(this.ApplicationMappings),
+        };
+    }
+
+}
+
+/// <summary>
+///     Mappings that only apply while a matching window is in the foreground.
+///     A set without class and title matches every window.
+/// </summary>
+public class ApplicationMappingSet
+{
+
+    /// <summary>
+    ///     Class name the window has to have, ignoring case, for example
+    ///     <c>Chrome_WidgetWin_1</c>.
+    /// </summary>
+    public string? WindowClass { get; set; }
+
+    /// <summary>
+    ///     Text the title of the window has to contain, ignoring case.
+    /// </summary>
+    public string? WindowTitle { get; set; }
+
+    public Dictionary<KeyCombination, KeyCombination> Mappings { get; set; } = new Dictionary<KeyCombination, KeyCombination>();
+
+    public override bool Equals(object? obj)
+    {
+        if (obj is not ApplicationMappingSet other)
+            return false;
+
+        return this.WindowClass == other.WindowClass &&
+            this.WindowTitle == other.WindowTitle &&
+            this.Mappings.Count == other.Mappings.Count &&
+            this.Mappings.All(
+                (kvp) => other.Mappings.TryGetValue(kvp.Key, out var replacement)
+                    && kvp.Value.Equals(replacement)
+            );
+    }
+
+    public override int GetHashCode()
+    {
+        return (this.WindowClass, this.WindowTitle, this.Mappings.Count).GetHashCode();
+    }
+
 }
 
 internal class TomlAklConfiguration : ITomlMetadataProvider
@@ -186,10 +562,73 @@ internal class TomlAklConfiguration : ITomlMetadataProvider
     public bool? StartWithSystem { get; set; }
     public string? SwitchKey { get; set; }
     public string? DefaultSimulationCombination { get; set; }
+    public string? KillSwitch { get; set; }
+    public string? PauseCombination { get; set; }
+    public string? TextOutput { get; set; }
     public Dictionary<string, string>? Mappings { get; set; }
+    public Dictionary<string, string>? MouseMappings { get; set; }
+    public Dictionary<string, string>? ProfileMappings { get; set; }
+    public List<TomlApplicationMappings>? ApplicationMappings { get; set; }
+    public Dictionary<string, TomlAklProfile>? Profiles { get; set; }
+
+    // Only read by the core library when the file is composed, see
+    // AklConfiguration.FromFile.
+    public List<string>? Include { get; set; }
+    public List<string>? Import { get; set; }
+    public Dictionary<string, Dictionary<string, string>>? MappingGroups { get; set; }
 
     // Storage for comments in the configuration file so that they can be saved
     // back to file when the in memory configuration gets updated.
     TomlPropertiesMetadata? ITomlMetadataProvider.PropertiesMetadata { get; set; }
 
 }
+
+/// <summary>
+///     Section of a single profile in <c>[profiles.&lt;name&gt;]</c>, only the
+///     switch key is required.
+/// </summary>
+internal class TomlAklProfile
+{
+
+    public string? SwitchKey { get; set; }
+    public string? DefaultSimulationCombination { get; set; }
+    public string? TextOutput { get; set; }
+    public Dictionary<string, string>? Mappings { get; set; }
+    public Dictionary<string, string>? MouseMappings { get; set; }
+    public Dictionary<string, string>? ProfileMappings { get; set; }
+    public List<TomlApplicationMappings>? ApplicationMappings { get; set; }
+    public List<string>? Import { get; set; }
+
+}
+
+/// <summary>
+///     Configuration as the core library writes it after composing a file,
+///     see <c>compose_configuration</c>.
+/// </summary>
+internal class TomlComposedConfiguration
+{
+
+    public string? SwitchKey { get; set; }
+    public string? DefaultCombination { get; set; }
+    public string? PauseCombination { get; set; }
+    public string? TextOutput { get; set; }
+    public Dictionary<string, string>? Mappings { get; set; }
+    public Dictionary<string, string>? MouseMappings { get; set; }
+    public Dictionary<string, string>? ProfileMappings { get; set; }
+    public List<TomlApplicationMappings>? ApplicationMappings { get; set; }
+    public Dictionary<string, TomlComposedConfiguration>? Profiles { get; set; }
+
+}
+
+/// <summary>
+///     One entry of <c>[[application_mappings]]</c>, the core library writes
+///     composed configurations in the same format.
+/// </summary>
+internal class TomlApplicationMappings
+{
+
+    public string? WindowClass { get; set; }
+    public string? WindowTitle { get; set; }
+    public Dictionary<string, string>? Mappings { get; set; }
+
+}
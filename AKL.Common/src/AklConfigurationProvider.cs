@@ -28,11 +28,11 @@ public class AklConfigurationProvider
     /// </returns>
     /// <exception cref="AklConfigurationParsingException">
     ///     If anything goes wrong in the deserialization or parsing step of the
-    ///     <see cref="AklConfiguration.FromString(string)"> method.
+    ///     <see cref="AklConfiguration.FromFile(FileInfo)"> method.
     /// </exception>
     public static AklConfigurationProvider LoadFromFile(FileInfo file)
     {
-        string rawConfig;
+        AklConfiguration configuration;
 
         if (!file.Exists)
         {
@@ -45,13 +45,13 @@ public class AklConfigurationProvider
             );
 
             using var streamReader = new StreamReader(stream);
-            rawConfig = streamReader.ReadToEnd();
+            configuration = AklConfiguration.FromString(streamReader.ReadToEnd());
             #pragma warning restore CA8602, CS8604
         } else {
-            rawConfig = File.ReadAllText(file.FullName);
+            configuration = AklConfiguration.FromFile(file);
         }
 
-        var provider = new AklConfigurationProvider(file, AklConfiguration.FromString(rawConfig));
+        var provider = new AklConfigurationProvider(file, configuration);
 
         if (!file.Exists)
             provider.SaveToFile();
@@ -7,6 +7,7 @@ public class Key
 
     private VirtualKeyCode? virtualKey;
     private char? textKey;
+    private ushort? rawKey;
     private KeyKind kind;
 
     public Key(VirtualKeyCode? virtualKey, char? textKey, KeyKind kind)
@@ -16,19 +17,37 @@ public class Key
         this.kind = kind;
     }
 
+    /// <summary>
+    ///     Creates a raw key for a key without a name, which matches the key
+    ///     no matter which text it produces.
+    /// </summary>
+    /// <param name="rawKey">The native virtual key code of the key.</param>
+    public Key(ushort rawKey)
+    {
+        this.rawKey = rawKey;
+        this.kind = KeyKind.Raw;
+    }
+
     /// <summary>
     ///     Tries to parse raw as a keyboard key.
     /// 
     ///     Any keyboard key can be either a virtual or text key. If a key
     ///     produces a single character when pressed it's a text key otherwise
     ///     it's a virtual key. (The space key is the only exception!)
+    /// 
+    ///     Parsing is done by the core library so that names are matched
+    ///     case-insensitively and aliases such as <c>Ctrl</c> or <c>Entf</c>
+    ///     are accepted.
     /// </summary>
     /// <param name="raw">
     ///     The name of a virtual key or a single character to represent a text
     ///     key. So any single character input will be treated as a text key.
+    ///     Keys without a name can be specified by their native key code
+    ///     such as <c>vk:0xBA</c> or <c>raw:186</c>.
     /// </param>
     /// <exception cref="ArgumentException">
-    ///     If no virtual key code with the specified name could be found or if 
+    ///     If no virtual key code with the specified name could be found (the
+    ///     message suggests similar names) or if 
     ///     the raw input contains any whitespace as defined
     ///     <a href="https://learn.microsoft.com/en-us/dotnet/api/system.char.iswhitespace?view=net-7.0#system-char-iswhitespace(system-char)">
     ///         here
@@ -42,24 +61,36 @@ public class Key
             throw new ArgumentException("A single key can't contain any whitespace.");
         }
 
-        var virtualKey = VirtualKeyCodeParser.Parse(raw);
+        var rawBytes = System.Text.Encoding.UTF8.GetBytes(raw);
+        var parsed = new FfiKey();
+        FfiResult result;
 
-        if (virtualKey == null)
+        unsafe
         {
-            if (raw.Length != 1)
+            fixed (byte* rawPointer = rawBytes)
             {
-                throw new ArgumentException(
-                    $"Couldn't parse \"{raw}\" with a length of {raw.Length} as " + 
-                    "a virtual nor plain text key."
-                );
+                result = AklCoreNativeInterface.parse_key(rawPointer, (nuint) rawBytes.Length, &parsed);
             }
 
-            return new Key(null, raw[0], KeyKind.Text);
-        }
-        else
-        {
-            return new Key(virtualKey, null, KeyKind.Virtual);
+            if (result.has_error)
+            {
+                var message = new string(result.error_message);
+                AklCoreNativeInterface.destroy_error_message(result.error_message);
+
+                throw new ArgumentException(message);
+            }
         }
+
+        if (parsed.kind == FfiKeyKind.Virtual)
+            return new Key((VirtualKeyCode) parsed.named, null, KeyKind.Virtual);
+
+        if (parsed.kind == FfiKeyKind.Raw)
+            return new Key(parsed.raw);
+
+        if (parsed.text > char.MaxValue)
+            throw new ArgumentException($"The character \"{raw}\" isn't supported as a key.");
+
+        return new Key(null, (char) parsed.text, KeyKind.Text);
     }
 
     public override string ToString()
@@ -67,6 +98,7 @@ public class Key
         return kind switch
         {
             KeyKind.Virtual => this.virtualKey.ToString() ?? "This method can't fail.",
+            KeyKind.Raw => $"vk:0x{this.rawKey:X2}",
             _ => this.textKey.ToString() ?? "This method can't fail.",
         };
     }
@@ -79,6 +111,7 @@ public class Key
 
         return this.virtualKey == other.virtualKey &&
             this.textKey == other.textKey &&
+            this.rawKey == other.rawKey &&
             this.kind == other.kind;
     }
 
@@ -93,6 +126,9 @@ public class Key
             if (this.textKey != null)
                 hashcode = hashcode * 7302013 ^ this.textKey.GetHashCode(); 
 
+            if (this.rawKey != null)
+                hashcode = hashcode * 7302013 ^ (ushort) this.rawKey;
+
             return hashcode * 7302013 ^ (int) this.kind;
         }
     }
@@ -109,6 +145,10 @@ public class Key
                 ffi.kind = FfiKeyKind.Virtual;
                 ffi.named = (byte) (this.virtualKey ?? 0);
                 break;
+            case KeyKind.Raw:
+                ffi.kind = FfiKeyKind.Raw;
+                ffi.raw = this.rawKey ?? 0;
+                break;
         }
 
         return ffi;
@@ -119,7 +159,8 @@ public class Key
 public enum KeyKind
 {
     Text,
-    Virtual
+    Virtual,
+    Raw
 }
 
 public static class VirtualKeyCodeParser
@@ -242,4 +283,13 @@ public enum VirtualKeyCode : byte {
     LaunchApp1,
     LaunchApp2,
     Play,
+    // New keys have to be appended to keep the values in sync with the core
+    // library.
+    Zoom,
+    NumpadEnter,
+    Kana,
+    Kanji,
+    Convert,
+    NonConvert,
+    LaunchMediaSelect,
 }
@@ -1,5 +1,8 @@
 namespace AKL.Common;
 
+using System.Collections.Concurrent;
+using System.Runtime.CompilerServices;
+using System.Runtime.InteropServices;
 using AKL.Core;
 
 /// <summary>
@@ -15,6 +18,26 @@ public unsafe class VirtualLayer
 
     public AklConfiguration Configuration { get; set; }
 
+    /// <summary>
+    ///     Decides which of several running virtual layers sees a key event
+    ///     first, higher values come first. Takes effect on the next
+    ///     <see cref="Update"/>.
+    /// </summary>
+    public int Priority { get; set; }
+
+    /// <summary>
+    ///     Raised once the native virtual layer disabled itself, either through
+    ///     the kill switch or because processing key events took too long too
+    ///     often in a row. Passes the reason, call <see cref="Update"/> to start
+    ///     it again.
+    ///
+    ///     Raised from a separate thread.
+    /// </summary>
+    public event Action<string>? Disabled;
+
+    // Lets the native callback find the virtual layer of its akl context.
+    private static readonly ConcurrentDictionary<nint, VirtualLayer> Layers = new();
+
     private AklContext* akl;
 
     /// <summary>
@@ -33,37 +56,336 @@ public unsafe class VirtualLayer
     {
         Configuration = configuration;
         akl = AklCoreNativeInterface.init();
+        Layers[(nint) akl] = this;
+        // Can't fail because the native virtual layer isn't running yet.
+        AklCoreNativeInterface.set_observer(akl, null, &OnDisabled);
         AppDomain.CurrentDomain.ProcessExit += (_, _) => this.Destroy();
     }
 
+    /// <summary>
+    ///     Whether the native virtual layer passes every key event through
+    ///     except the pause combination. Unlike <see cref="Stop"/> pausing
+    ///     keeps the virtual layer running, it's also kept across
+    ///     <see cref="Update"/>s.
+    /// </summary>
+    public bool Paused
+    {
+        get => akl != null && AklCoreNativeInterface.is_paused(akl);
+        set
+        {
+            if (akl != null)
+                AklCoreNativeInterface.set_paused(akl, value);
+        }
+    }
+
+    /// <summary>
+    ///     Name of the profile the native virtual layer uses. It might have
+    ///     switched profiles by itself through a profile mapping.
+    ///
+    ///     <c>null</c> if the configuration has no profiles.
+    /// </summary>
+    public string? ActiveProfile
+    {
+        get
+        {
+            if (akl == null)
+                return null;
+
+            var length = AklCoreNativeInterface.get_active_profile(akl, null, 0);
+
+            if (length == 0)
+                return null;
+
+            var raw = new byte[(int) length];
+
+            fixed (byte* rawPointer = raw)
+            {
+                AklCoreNativeInterface.get_active_profile(akl, rawPointer, length);
+            }
+
+            return System.Text.Encoding.UTF8.GetString(raw);
+        }
+    }
+
     /// <summary>
     ///     Updates the configuration of the native akl context with the
-    ///     according to this wrapper then restarts it.
+    ///     according to this wrapper then restarts it. The active profile is
+    ///     kept if the configuration still has it, otherwise the default
+    ///     profile is activated.
     /// </summary>
     public void Update()
     {
         if (akl == null)
             return;
 
+        var activeProfile = ActiveProfile;
+
         Stop();
+        Configure();
+
+        if (activeProfile != null && Configuration.Profiles.ContainsKey(activeProfile))
+            SwitchProfile(activeProfile);
+        else if (Configuration.Profiles.Count > 0)
+            SwitchProfile(AklConfiguration.DefaultProfile);
+
+        AklCoreNativeInterface.start(akl);
+    }
+
+    /// <summary>
+    ///     Switches to the profile of the configuration, right away if the
+    ///     native virtual layer is running.
+    /// </summary>
+    /// <param name="name">
+    ///     Name of one of the <see cref="AklConfiguration.Profiles"/> or
+    ///     <see cref="AklConfiguration.DefaultProfile"/>.
+    /// </param>
+    /// <returns>
+    ///     <c>null</c> if the profile is active now, otherwise the reason why
+    ///     it couldn't be switched to.
+    /// </returns>
+    public string? SwitchProfile(string name)
+    {
+        if (akl == null)
+            return "The virtual layer was already destroyed.";
+
+        var raw = System.Text.Encoding.UTF8.GetBytes(name);
+        FfiResult result;
+
+        fixed (byte* rawPointer = raw)
+        {
+            result = AklCoreNativeInterface.switch_profile(akl, rawPointer, (nuint) raw.Length);
+        }
+
+        if (!result.has_error)
+            return null;
+
+        var message = new string(result.error_message);
+        AklCoreNativeInterface.destroy_error_message(result.error_message);
+
+        return message;
+    }
+
+    /// <summary>
+    ///     Checks whether the native virtual layer disabled itself because
+    ///     processing events took too long too often in a row. See also
+    ///     <see cref="Disabled"/>.
+    /// </summary>
+    /// <returns>
+    ///     <c>null</c> if the virtual layer is healthy, otherwise the reason
+    ///     why it was disabled. Call <see cref="Update"/> to start it again.
+    /// </returns>
+    public string? CheckHealth()
+    {
+        if (akl == null)
+            return null;
+
+        var result = AklCoreNativeInterface.check_health(akl);
+
+        if (!result.has_error)
+            return null;
+
+        var message = new string(result.error_message);
+        AklCoreNativeInterface.destroy_error_message(result.error_message);
+
+        return message;
+    }
+
+    /// <summary>
+    ///     Runs the scenario against the current configuration without
+    ///     starting or modifying the running native virtual layer.
+    ///
+    ///     See the <c>scenario</c> module of the core library for the format.
+    /// </summary>
+    /// <param name="scenario">
+    ///     Lines of events and their expected responses, for example
+    ///     <c>press CapsLock => block</c>.
+    /// </param>
+    /// <returns>
+    ///     <c>null</c> if every response matched, otherwise a description of
+    ///     each line that failed.
+    /// </returns>
+    public string? RunScenario(string scenario)
+    {
+        if (akl == null)
+            return "The virtual layer was already destroyed.";
 
-        AklCoreNativeInterface.set_switch_key(akl, Configuration.SwitchKey.ToFfi());
+        Configure();
 
-        if (Configuration.DefaultCombination != null)
-            AklCoreNativeInterface.set_default_combination(akl, Configuration.DefaultCombination.ToFfi());
+        var raw = System.Text.Encoding.UTF8.GetBytes(scenario);
+        FfiResult result;
+
+        fixed (byte* rawPointer = raw)
+        {
+            result = AklCoreNativeInterface.run_scenario(akl, rawPointer, (nuint) raw.Length);
+        }
+
+        if (!result.has_error)
+            return null;
+
+        var message = new string(result.error_message);
+        AklCoreNativeInterface.destroy_error_message(result.error_message);
+
+        return message;
+    }
+
+    /// <summary>
+    ///     Sends the log records of the native library to the sink. Applies to
+    ///     every virtual layer of the process and can be called at any time.
+    /// </summary>
+    /// <param name="sink">
+    ///     One of <c>stderr</c>, <c>file:&lt;path&gt;</c> or
+    ///     <c>tcp:&lt;address&gt;</c>, <c>null</c> turns logging off.
+    /// </param>
+    /// <param name="level">Most verbose level that is still logged.</param>
+    /// <returns>
+    ///     <c>null</c> if logging was configured, otherwise the reason why the
+    ///     sink couldn't be used.
+    /// </returns>
+    public static string? ConfigureLogging(string? sink, FfiLogLevel level)
+    {
+        FfiResult result;
+
+        if (sink == null)
+        {
+            result = AklCoreNativeInterface.configure_logging(null, 0, level);
+        }
+        else
+        {
+            var raw = System.Text.Encoding.UTF8.GetBytes(sink);
+
+            fixed (byte* rawPointer = raw)
+            {
+                result = AklCoreNativeInterface.configure_logging(rawPointer, (nuint) raw.Length, level);
+            }
+        }
+
+        if (!result.has_error)
+            return null;
+
+        var message = new string(result.error_message);
+        AklCoreNativeInterface.destroy_error_message(result.error_message);
+
+        return message;
+    }
+
+    [UnmanagedCallersOnly(CallConvs = new[] { typeof(CallConvCdecl) })]
+    private static void OnDisabled(AklContext* akl, FfiDisableReason reason)
+    {
+        if (!Layers.TryGetValue((nint) akl, out var layer))
+            return;
+
+        var message = reason.kind == FfiDisableReasonKind.Watchdog
+            ? $"{reason.overruns} key events in a row took too long to process."
+            : "The kill switch was pressed.";
+
+        layer.Disabled?.Invoke(message);
+    }
+
+    // Passes the current configuration to the native akl context. The top
+    // level configuration is also saved as the default profile if there are
+    // any profiles. No profile is activated so that the running native
+    // virtual layer keeps its own, see Update.
+    private void Configure()
+    {
+        if (Configuration.KillSwitch != null)
+            AklCoreNativeInterface.set_kill_switch(akl, Configuration.KillSwitch.ToFfi());
+        else
+            AklCoreNativeInterface.set_kill_switch(akl, new FfiKeyCombination());
+
+        // Set before saving the profiles so that all of them share it.
+        if (Configuration.PauseCombination != null)
+            AklCoreNativeInterface.set_pause_combination(akl, Configuration.PauseCombination.ToFfi());
+        else
+            AklCoreNativeInterface.set_pause_combination(akl, new FfiKeyCombination());
+
+        AklCoreNativeInterface.set_priority(akl, Priority);
+
+        AklCoreNativeInterface.clear_profiles(akl);
+
+        foreach (KeyValuePair<string, AklConfiguration> profile in Configuration.Profiles)
+        {
+            ConfigureProfile(profile.Value);
+            SaveProfile(profile.Key);
+        }
+
+        ConfigureProfile(Configuration);
+
+        if (Configuration.Profiles.Count > 0)
+            SaveProfile(AklConfiguration.DefaultProfile);
+    }
+
+    // Passes everything that can differ between profiles to the native akl
+    // context.
+    private void ConfigureProfile(AklConfiguration profile)
+    {
+        AklCoreNativeInterface.set_switch_key(akl, profile.SwitchKey.ToFfi());
+
+        if (profile.DefaultCombination != null)
+            AklCoreNativeInterface.set_default_combination(akl, profile.DefaultCombination.ToFfi());
         else
             AklCoreNativeInterface.set_default_combination(akl, new FfiKeyCombination());
 
+        AklCoreNativeInterface.set_text_output(akl, profile.TextOutput);
+
         AklCoreNativeInterface.clear_mappings(akl);
 
-        foreach (KeyValuePair<KeyCombination, KeyCombination> mapping in Configuration.Mappings)
+        foreach (KeyValuePair<KeyCombination, KeyCombination> mapping in profile.Mappings)
         {
             // At this point no invalid key combination can exist so this method
             // should never cause an error.
             AklCoreNativeInterface.add_mapping(akl, mapping.Key.ToFfi(), mapping.Value.ToFfi());
         }
 
-        AklCoreNativeInterface.start(akl);
+        foreach (KeyValuePair<KeyCombination, FfiMouseAction> mapping in profile.MouseMappings)
+        {
+            AklCoreNativeInterface.add_mouse_mapping(akl, mapping.Key.ToFfi(), mapping.Value);
+        }
+
+        foreach (KeyValuePair<KeyCombination, string> mapping in profile.ProfileMappings)
+        {
+            var raw = System.Text.Encoding.UTF8.GetBytes(mapping.Value);
+
+            fixed (byte* rawPointer = raw)
+            {
+                AklCoreNativeInterface.add_profile_mapping(akl, mapping.Key.ToFfi(), rawPointer, (nuint) raw.Length);
+            }
+        }
+
+        foreach (ApplicationMappingSet set in profile.ApplicationMappings)
+        {
+            // Empty strings match every window just like a missing class or
+            // title.
+            var windowClass = System.Text.Encoding.UTF8.GetBytes(set.WindowClass ?? "");
+            var windowTitle = System.Text.Encoding.UTF8.GetBytes(set.WindowTitle ?? "");
+
+            fixed (byte* windowClassPointer = windowClass, windowTitlePointer = windowTitle)
+            {
+                foreach (KeyValuePair<KeyCombination, KeyCombination> mapping in set.Mappings)
+                {
+                    AklCoreNativeInterface.add_application_mapping(
+                        akl,
+                        windowClassPointer,
+                        (nuint) windowClass.Length,
+                        windowTitlePointer,
+                        (nuint) windowTitle.Length,
+                        mapping.Key.ToFfi(),
+                        mapping.Value.ToFfi()
+                    );
+                }
+            }
+        }
+    }
+
+    // Saves the configuration of the native akl context as the profile, which
+    // can't fail for valid utf-8.
+    private void SaveProfile(string name)
+    {
+        var raw = System.Text.Encoding.UTF8.GetBytes(name);
+
+        fixed (byte* rawPointer = raw)
+        {
+            AklCoreNativeInterface.save_profile(akl, rawPointer, (nuint) raw.Length);
+        }
     }
 
     /// <summary>
@@ -94,6 +416,7 @@ public unsafe class VirtualLayer
         if (akl != null)
         {
             Stop();
+            Layers.TryRemove((nint) akl, out _);
             AklCoreNativeInterface.destroy(akl);
             akl = null;
         }
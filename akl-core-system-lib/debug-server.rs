@@ -1,19 +1,112 @@
+//! Receives the structured log records of any number of akl clients and either
+//! prints them or shows a live dashboard of the virtual layer of each client.
+//!
+//! Run `debug-server --help` for all options.
+
+#[path = "src/debug_protocol.rs"]
+#[allow(dead_code)]
+mod debug_protocol;
+
 use std::{
-    io::{BufRead, BufReader},
-    net::{TcpListener, TcpStream},
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    env,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    process,
+    str::FromStr,
+    sync::{Arc, Mutex},
     thread,
+    time::Duration,
 };
 
+use debug_protocol::{EventRecord, LogRecord, Record};
+use log::{Level, LevelFilter};
+
+const USAGE: &str = "\
+Usage: debug-server [OPTIONS]
+
+Options:
+  --bind <ADDRESS>   Listen on the tcp address [default: 127.0.0.1:7777]
+  --client <TEXT>    Only show clients whose address contains the text
+  --level <LEVEL>    Only show records up to the level (error, warn, info,
+                     debug or trace) [default: trace]
+  --key <KEY>        Only show events of the key, hides all log messages
+  --dashboard        Show the current state of each client instead of
+                     printing every record
+  -h, --help         Print this help";
+
+/// Number of records that are shown for each client on the dashboard.
+const DASHBOARD_RECENT_RECORDS: usize = 10;
+
+/// Decides which records are shown, the dashboard still tracks the state of
+/// every event.
+#[derive(Default)]
+struct Filter {
+    client: Option<String>,
+    level: Option<LevelFilter>,
+    key: Option<String>,
+}
+
+impl Filter {
+    fn shows_client(&self, client: &str) -> bool {
+        self.client
+            .as_ref()
+            .is_none_or(|it| client.contains(it.as_str()))
+    }
+
+    fn shows(&self, record: &Record) -> bool {
+        let level = self.level.unwrap_or(LevelFilter::Trace);
+
+        match record {
+            Record::Log(log) => {
+                self.key.is_none()
+                    && Level::from_str(&log.level)
+                        .map_or(true, |it| it <= level)
+            }
+            Record::Event(event) => {
+                level >= Level::Trace
+                    && self
+                        .key
+                        .as_ref()
+                        .is_none_or(|it| it.eq_ignore_ascii_case(&event.key))
+            }
+        }
+    }
+}
+
+struct Options {
+    address: String,
+    filter: Filter,
+    dashboard: bool,
+}
+
 fn main() {
-    let listener = TcpListener::bind("127.0.0.1:7777")
-        .expect("Should be able to open debug server on port 7777.");
+    let options = parse_options().unwrap_or_else(|error| {
+        eprintln!("{error}\n\n{USAGE}");
+        process::exit(2);
+    });
+
+    let output = Arc::new(Mutex::new(Output {
+        filter: options.filter,
+        dashboard: options.dashboard.then(BTreeMap::new),
+    }));
 
-    println!("Start listening for debugger client connections...");
+    let address = options.address;
+    let listener = TcpListener::bind(&address).unwrap_or_else(|error| {
+        eprintln!("Couldn't open the debug server on {address}: {error}");
+        process::exit(1);
+    });
+
+    println!("Start listening for debugger client connections on {address}...");
 
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
-                thread::spawn(move || handle_connection(stream));
+                let client = stream.peer_addr().map_or_else(
+                    |_| "unknown".to_owned(),
+                    |address| address.to_string(),
+                );
+                spawn_connection(client, stream, &output);
             }
             Err(error) => {
                 println!("A connection failed to be established: {error}");
@@ -22,24 +115,224 @@ fn main() {
     }
 }
 
-fn handle_connection(mut connection: TcpStream) {
-    let address = connection
-        .peer_addr()
-        .expect("Should be able to get local client address.");
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        address: "127.0.0.1:7777".to_owned(),
+        filter: Filter::default(),
+        dashboard: false,
+    };
+
+    let mut arguments = env::args().skip(1);
+
+    while let Some(argument) = arguments.next() {
+        let mut value = || {
+            arguments
+                .next()
+                .ok_or_else(|| format!("Missing value for {argument}."))
+        };
+
+        match argument.as_str() {
+            "--bind" => options.address = value()?,
+            "--client" => options.filter.client = Some(value()?),
+            "--level" => {
+                let level = value()?;
+                options.filter.level = Some(
+                    LevelFilter::from_str(&level)
+                        .map_err(|_| format!("Unknown level \"{level}\"."))?,
+                );
+            }
+            "--key" => options.filter.key = Some(value()?),
+            "--dashboard" => options.dashboard = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            _ => return Err(format!("Unknown option \"{argument}\".")),
+        }
+    }
+
+    Ok(options)
+}
+
+fn spawn_connection(
+    client: String,
+    connection: impl Read + Send + 'static,
+    output: &Arc<Mutex<Output>>,
+) {
+    let output = Arc::clone(output);
+
+    thread::spawn(move || handle_connection(&client, connection, &output));
+}
+
+fn handle_connection(
+    client: &str,
+    connection: impl Read,
+    output: &Mutex<Output>,
+) {
+    if !lock(output).filter.shows_client(client) {
+        return;
+    }
 
-    println!("Connected ({address})");
+    lock(output).connected(client);
 
     let mut line = String::new();
-    let mut reader = BufReader::new(&mut connection);
+    let mut reader = BufReader::new(connection);
 
     while let Ok(bytes) = reader.read_line(&mut line) {
         if bytes == 0 {
             break;
         }
 
-        print!("({address}) {line}");
+        // Clients which don't speak the protocol yet only send plain lines.
+        let record = serde_json::from_str(&line).unwrap_or_else(|_| {
+            Record::Log(LogRecord {
+                level: Level::Info.to_string(),
+                target: String::new(),
+                message: line.trim_end().to_owned(),
+            })
+        });
+
+        lock(output).receive(client, record);
         line.clear();
     }
 
-    println!("Disconnected ({address})");
+    lock(output).disconnected(client);
+}
+
+fn lock(output: &Mutex<Output>) -> std::sync::MutexGuard<'_, Output> {
+    output
+        .lock()
+        .expect("Printing never panics so it can't poison the lock.")
+}
+
+/// State of the virtual layer of one client as shown on the dashboard.
+#[derive(Default)]
+struct ClientState {
+    connected: bool,
+    layer_active: bool,
+    pressed_keys: BTreeSet<String>,
+    layer_keys: Vec<String>,
+    last_event: Option<EventRecord>,
+    recent: VecDeque<String>,
+}
+
+/// Prints records or redraws the dashboard if there is one.
+struct Output {
+    filter: Filter,
+    dashboard: Option<BTreeMap<String, ClientState>>,
+}
+
+impl Output {
+    fn connected(&mut self, client: &str) {
+        match self.dashboard.as_mut() {
+            Some(clients) => {
+                clients.entry(client.to_owned()).or_default().connected = true;
+                self.draw();
+            }
+            None => println!("Connected ({client})"),
+        }
+    }
+
+    fn disconnected(&mut self, client: &str) {
+        match self.dashboard.as_mut() {
+            Some(clients) => {
+                clients.entry(client.to_owned()).or_default().connected = false;
+                self.draw();
+            }
+            None => println!("Disconnected ({client})"),
+        }
+    }
+
+    fn receive(&mut self, client: &str, record: Record) {
+        let shown = self.filter.shows(&record).then(|| format_record(&record));
+
+        let Some(clients) = self.dashboard.as_mut() else {
+            if let Some(line) = shown {
+                println!("({client}) {line}");
+            }
+            return;
+        };
+
+        let state = clients.entry(client.to_owned()).or_default();
+
+        if let Record::Event(event) = record {
+            match event.action.as_str() {
+                "press" => state.pressed_keys.insert(event.key.clone()),
+                _ => state.pressed_keys.remove(&event.key),
+            };
+
+            state.layer_active = event.layer_active;
+            state.layer_keys = event.layer_keys.clone();
+            state.last_event = Some(event);
+        }
+
+        if let Some(line) = shown {
+            if state.recent.len() == DASHBOARD_RECENT_RECORDS {
+                state.recent.pop_front();
+            }
+
+            state.recent.push_back(line);
+        }
+
+        self.draw();
+    }
+
+    fn draw(&self) {
+        let Some(clients) = self.dashboard.as_ref() else {
+            return;
+        };
+
+        let mut screen = String::from("\x1b[2J\x1b[H");
+
+        for (client, state) in clients {
+            screen += &format!(
+                "{client} ({})\n  layer:        {}\n  pressed keys: {}\n  layer keys:   {}\n  last event:   {}\n",
+                if state.connected { "connected" } else { "disconnected" },
+                if state.layer_active { "active" } else { "inactive" },
+                state.pressed_keys.iter().cloned().collect::<Vec<_>>().join(" "),
+                state.layer_keys.join(" "),
+                state.last_event.as_ref().map_or_else(String::new, format_event),
+            );
+
+            for line in &state.recent {
+                screen += &format!("    {line}\n");
+            }
+
+            screen.push('\n');
+        }
+
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(screen.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+fn format_record(record: &Record) -> String {
+    match record {
+        Record::Log(log) if log.target.is_empty() => log.message.clone(),
+        Record::Log(log) => {
+            format!("[{}] {}: {}", log.level, log.target, log.message)
+        }
+        Record::Event(event) => format_event(event),
+    }
+}
+
+fn format_event(event: &EventRecord) -> String {
+    let nanoseconds = |it: Option<u64>| {
+        it.map_or_else(
+            || "-".to_owned(),
+            |it| format!("{:?}", Duration::from_nanos(it)),
+        )
+    };
+
+    format!(
+        "{} {} => {} (total {:?}, translation {}, processing {}, output {})",
+        event.action,
+        event.key,
+        event.response,
+        Duration::from_nanos(event.timings.total),
+        nanoseconds(event.timings.translation),
+        nanoseconds(event.timings.processing),
+        nanoseconds(event.timings.output),
+    )
 }
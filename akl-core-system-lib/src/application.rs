@@ -0,0 +1,118 @@
+//! Mappings that only apply while a certain application is in the foreground.
+//!
+//! Each [`ApplicationMappings`] set describes the windows it applies to by
+//! their class name and title. The event processor picks the first set that
+//! matches the foreground window whenever the switch key is pressed and keeps
+//! it until the virtual layer is left, so that a replacement which brings
+//! another window to the front doesn't change the mappings halfway through.
+//! The mappings of the set take precedence over the regular
+//! [`mappings`](crate::Configuration::mappings), targets it doesn't map still
+//! use the regular ones.
+//!
+//! Windows only tells the keyboard hook which window is in the foreground,
+//! not which application the event is meant for. Class names are stable
+//! across languages and versions of an application while titles usually
+//! contain the open document, which is why titles only have to contain the
+//! text.
+
+use std::collections;
+
+#[cfg(feature = "config-serde")]
+use crate::config_format;
+use crate::key::KeyCombination;
+
+/// Window that is in the foreground.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Application {
+    /// Name of the window class, e.g. `Chrome_WidgetWin_1`.
+    pub class: String,
+    pub title: String,
+}
+
+/// Mappings that replace the regular ones while a matching window is in the
+/// foreground. A set without class and title matches every window.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "config-serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ApplicationMappings {
+    /// Class name the window has to have, ignoring case.
+    #[cfg_attr(
+        feature = "config-serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub window_class: Option<String>,
+    /// Text the title of the window has to contain, ignoring case.
+    #[cfg_attr(
+        feature = "config-serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub window_title: Option<String>,
+    #[cfg_attr(
+        feature = "config-serde",
+        serde(serialize_with = "config_format::sorted")
+    )]
+    pub mappings: collections::HashMap<KeyCombination, KeyCombination>,
+}
+
+impl ApplicationMappings {
+    /// Whether the mappings apply while the application is in the
+    /// foreground.
+    pub fn matches(&self, application: &Application) -> bool {
+        let class_matches = self.window_class.as_ref().is_none_or(|class| {
+            class.to_lowercase() == application.class.to_lowercase()
+        });
+        let title_matches = self.window_title.as_ref().is_none_or(|title| {
+            application
+                .title
+                .to_lowercase()
+                .contains(&title.to_lowercase())
+        });
+
+        class_matches && title_matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn application(class: &str, title: &str) -> Application {
+        Application {
+            class: class.to_owned(),
+            title: title.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let editor = ApplicationMappings {
+            window_class: Some("Chrome_WidgetWin_1".to_owned()),
+            window_title: Some("visual studio code".to_owned()),
+            mappings: collections::HashMap::new(),
+        };
+
+        assert!(editor.matches(&application(
+            "chrome_widgetwin_1",
+            "main.rs - akl - Visual Studio Code"
+        )));
+        assert!(!editor.matches(&application(
+            "Chrome_WidgetWin_1",
+            "Another Keyboard Layer - Google Chrome"
+        )));
+        assert!(!editor.matches(&application(
+            "Notepad",
+            "Visual Studio Code.txt - Editor"
+        )));
+
+        let browser = ApplicationMappings {
+            window_class: Some("MozillaWindowClass".to_owned()),
+            ..ApplicationMappings::default()
+        };
+
+        assert!(browser.matches(&application("MozillaWindowClass", "")));
+        assert!(ApplicationMappings::default().matches(&Application::default()));
+    }
+}
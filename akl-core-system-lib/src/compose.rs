@@ -0,0 +1,740 @@
+//! Composes one [`ConfigurationFile`] out of several toml files, only
+//! available with the `config-serde` feature.
+//!
+//! On top of the regular [`configuration format`](crate::config_format) a
+//! file can include other files and define named groups of mappings that its
+//! configurations import:
+//!
+//! ```toml
+//! include = ["base.toml"]
+//! import = ["navigation"]
+//!
+//! [mapping_groups.navigation]
+//! h = "LeftArrow"
+//! l = "RightArrow"
+//!
+//! [mappings]
+//! h = "Home"
+//!
+//! [profiles.gaming]
+//! switch_key = "Tab"
+//! import = ["navigation"]
+//! ```
+//!
+//! Values override each other in a defined order, from weakest to strongest:
+//!
+//! 1. The included files in the order they are listed, each one resolved the
+//!    same way on its own. Included paths are relative to the including file.
+//! 2. The groups a configuration imports.
+//! 3. The values written in the file itself.
+//!
+//! Single values replace each other while mapping tables, mapping groups and
+//! profiles with the same name are merged target by target. The application
+//! mappings of a file come before the ones it includes so that they win if
+//! both match the same window. Imported groups
+//! are the only source that doesn't override, two imported groups that map
+//! the same target differently are a [`ComposeError::Conflict`]. The same
+//! goes for two spellings of one target in the same table, like `Strg+j` and
+//! `LControl+j`.
+//!
+//! A file can only import the groups it defines or includes, and only the top
+//! level of a file can include files and define groups. Files that include
+//! themselves, directly or through other files, are a [`ComposeError::Cycle`].
+//!
+//! The configuration files of the application spell the default combination
+//! `default_simulation_combination`, which is accepted as well. An empty
+//! string disables it, even if an included file sets one. Values only the
+//! application knows, like `start_with_system`, are ignored.
+
+use std::{
+    collections, fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use serde::{de, Deserialize, Deserializer};
+use thiserror::Error;
+use toml::Spanned;
+
+use crate::{
+    application::ApplicationMappings,
+    config_format::ConfigurationFile,
+    key::{Key, KeyCombination, KeyParsingError, TextOutput},
+    mouse::MouseAction,
+    Configuration,
+};
+
+/// File and line a mapping was written at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.line)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ComposeError {
+    #[error("Couldn't read the configuration \"{}\": {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Invalid configuration \"{}\": {source}", path.display())]
+    Toml {
+        path: PathBuf,
+        source: Box<toml::de::Error>,
+    },
+    #[error("{origin}: {message}")]
+    InvalidTarget { origin: Origin, message: String },
+    #[error("{0}: There is no mapping group named \"{1}\".")]
+    UnknownGroup(Origin, String),
+    #[error(
+        "The profile \"{profile}\" in \"{}\" can't include files, define \
+         mapping groups or profiles.",
+        path.display()
+    )]
+    NestedProfile { path: PathBuf, profile: String },
+    #[error("The configuration includes itself: {}", display_cycle(.0))]
+    Cycle(Vec<PathBuf>),
+    #[error(
+        "The mapping of \"{target}\" at {first} conflicts with the one at \
+         {second}."
+    )]
+    Conflict {
+        target: KeyCombination,
+        first: Origin,
+        second: Origin,
+    },
+}
+
+fn display_cycle(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Reads the toml file at the path together with every file it includes.
+///
+/// # Errors
+///
+/// If a file can't be read or is invalid, if an include forms a cycle, an
+/// import refers to an unknown group or if mappings conflict, see
+/// [`ComposeError`].
+pub fn load(path: &Path) -> Result<ConfigurationFile, ComposeError> {
+    Ok(compose(path, &mut Vec::new())?.into())
+}
+
+type RawMappings<V> = collections::BTreeMap<Spanned<String>, V>;
+
+/// Like a [`ConfigurationFile`] but with the targets of the mappings still
+/// unparsed so that their lines are known.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawConfiguration {
+    include: Vec<Spanned<String>>,
+    mapping_groups: collections::BTreeMap<String, RawMappings<KeyCombination>>,
+    import: Vec<Spanned<String>>,
+    switch_key: Option<Key>,
+    #[serde(alias = "default_simulation_combination")]
+    default_combination: Option<DefaultCombination>,
+    pause_combination: Option<KeyCombination>,
+    text_output: Option<TextOutput>,
+    mappings: RawMappings<KeyCombination>,
+    mouse_mappings: RawMappings<MouseAction>,
+    profile_mappings: RawMappings<String>,
+    application_mappings: Vec<ApplicationMappings>,
+    profiles: collections::BTreeMap<String, RawConfiguration>,
+}
+
+/// Default combination of a file, an empty string disables it so that it can
+/// override the one of an included file.
+#[derive(Debug, Clone, Copy)]
+enum DefaultCombination {
+    Disabled,
+    Enabled(KeyCombination),
+}
+
+impl<'de> Deserialize<'de> for DefaultCombination {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+
+        if raw.is_empty() {
+            return Ok(Self::Disabled);
+        }
+
+        raw.parse().map(Self::Enabled).map_err(de::Error::custom)
+    }
+}
+
+/// Mappings together with where each one was written.
+type Mappings<V> = collections::HashMap<KeyCombination, (V, Origin)>;
+
+/// Configuration of which every value is optional so that it can be laid over
+/// another one.
+#[derive(Debug, Default)]
+struct Layer {
+    switch_key: Option<Key>,
+    default_combination: Option<DefaultCombination>,
+    pause_combination: Option<KeyCombination>,
+    text_output: Option<TextOutput>,
+    mappings: Mappings<KeyCombination>,
+    mouse_mappings: Mappings<MouseAction>,
+    profile_mappings: Mappings<String>,
+    application_mappings: Vec<ApplicationMappings>,
+}
+
+impl Layer {
+    /// Lays the other layer over this one, values of the other layer win.
+    fn overlay(&mut self, other: Layer) {
+        self.switch_key = other.switch_key.or(self.switch_key);
+        self.default_combination =
+            other.default_combination.or(self.default_combination);
+        self.pause_combination =
+            other.pause_combination.or(self.pause_combination);
+        self.text_output = other.text_output.or(self.text_output);
+        self.mappings.extend(other.mappings);
+        self.mouse_mappings.extend(other.mouse_mappings);
+        self.profile_mappings.extend(other.profile_mappings);
+
+        let weaker = std::mem::replace(
+            &mut self.application_mappings,
+            other.application_mappings,
+        );
+        self.application_mappings.extend(weaker);
+    }
+}
+
+impl From<Layer> for Configuration {
+    fn from(value: Layer) -> Self {
+        fn values<V>(
+            mappings: Mappings<V>,
+        ) -> collections::HashMap<KeyCombination, V> {
+            mappings
+                .into_iter()
+                .map(|(target, (value, _))| (target, value))
+                .collect()
+        }
+
+        Self {
+            switch_key: value.switch_key,
+            default_combination: match value.default_combination {
+                Some(DefaultCombination::Enabled(combination)) => {
+                    Some(combination)
+                }
+                Some(DefaultCombination::Disabled) | None => None,
+            },
+            pause_combination: value.pause_combination,
+            mappings: values(value.mappings),
+            mouse_mappings: values(value.mouse_mappings),
+            profile_mappings: values(value.profile_mappings),
+            application_mappings: value.application_mappings,
+            text_output: value.text_output.unwrap_or_default(),
+        }
+    }
+}
+
+/// Everything a file and the files it includes define.
+#[derive(Debug, Default)]
+struct Composition {
+    configuration: Layer,
+    mapping_groups: collections::BTreeMap<String, Mappings<KeyCombination>>,
+    profiles: collections::BTreeMap<String, Layer>,
+}
+
+impl Composition {
+    /// Lays the other composition over this one, values of the other
+    /// composition win.
+    fn overlay(&mut self, other: Composition) {
+        self.configuration.overlay(other.configuration);
+
+        for (name, group) in other.mapping_groups {
+            self.mapping_groups.entry(name).or_default().extend(group);
+        }
+
+        for (name, profile) in other.profiles {
+            self.profiles.entry(name).or_default().overlay(profile);
+        }
+    }
+}
+
+impl From<Composition> for ConfigurationFile {
+    fn from(value: Composition) -> Self {
+        Self {
+            configuration: value.configuration.into(),
+            profiles: value
+                .profiles
+                .into_iter()
+                .map(|(name, profile)| (name, profile.into()))
+                .collect(),
+        }
+    }
+}
+
+/// Path and content of a file that is being composed.
+struct Source<'a> {
+    path: &'a Path,
+    raw: &'a str,
+}
+
+impl Source<'_> {
+    fn origin(&self, span: Range<usize>) -> Origin {
+        Origin {
+            path: self.path.to_owned(),
+            line: self.raw[..span.start].matches('\n').count() + 1,
+        }
+    }
+
+    /// Parses the targets of the mappings.
+    fn mappings<V: PartialEq>(
+        &self,
+        raw: RawMappings<V>,
+    ) -> Result<Mappings<V>, ComposeError> {
+        let mut mappings = Mappings::new();
+        let mut raw = raw.into_iter().collect::<Vec<_>>();
+
+        // Conflicts are reported in the order they were written.
+        raw.sort_by_key(|(target, _)| target.span().start);
+
+        for (target, value) in raw {
+            let origin = self.origin(target.span());
+            let target = target.get_ref().parse().map_err(
+                |error: KeyParsingError| ComposeError::InvalidTarget {
+                    origin: origin.clone(),
+                    message: error.to_string(),
+                },
+            )?;
+
+            merge(&mut mappings, target, value, origin)?;
+        }
+
+        Ok(mappings)
+    }
+
+    /// Imports the groups of the configuration, then lays its own values over
+    /// them.
+    fn layer(
+        &self,
+        raw: RawConfiguration,
+        groups: &collections::BTreeMap<String, Mappings<KeyCombination>>,
+    ) -> Result<Layer, ComposeError> {
+        let mut mappings = Mappings::new();
+
+        for name in raw.import {
+            let group = groups.get(name.get_ref()).ok_or_else(|| {
+                ComposeError::UnknownGroup(
+                    self.origin(name.span()),
+                    name.get_ref().clone(),
+                )
+            })?;
+
+            for (target, (value, origin)) in group {
+                merge(&mut mappings, *target, *value, origin.clone())?;
+            }
+        }
+
+        mappings.extend(self.mappings(raw.mappings)?);
+
+        Ok(Layer {
+            switch_key: raw.switch_key,
+            default_combination: raw.default_combination,
+            pause_combination: raw.pause_combination,
+            text_output: raw.text_output,
+            mappings,
+            mouse_mappings: self.mappings(raw.mouse_mappings)?,
+            profile_mappings: self.mappings(raw.profile_mappings)?,
+            application_mappings: raw.application_mappings,
+        })
+    }
+}
+
+/// Adds the mapping unless the target is already mapped to something else.
+fn merge<V: PartialEq>(
+    mappings: &mut Mappings<V>,
+    target: KeyCombination,
+    value: V,
+    origin: Origin,
+) -> Result<(), ComposeError> {
+    match mappings.get(&target) {
+        Some((existing, _)) if *existing == value => Ok(()),
+        Some((_, first)) => Err(ComposeError::Conflict {
+            target,
+            first: first.clone(),
+            second: origin,
+        }),
+        None => {
+            mappings.insert(target, (value, origin));
+            Ok(())
+        }
+    }
+}
+
+/// Composes the file at the path, `stack` holds the canonical paths of the
+/// files that are currently including it.
+fn compose(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Composition, ComposeError> {
+    let io_error = |source| ComposeError::Io {
+        path: path.to_owned(),
+        source,
+    };
+    let canonical = fs::canonicalize(path).map_err(io_error)?;
+
+    if let Some(index) = stack.iter().position(|it| *it == canonical) {
+        let mut cycle = stack[index..].to_vec();
+        cycle.push(canonical);
+
+        return Err(ComposeError::Cycle(cycle));
+    }
+
+    let raw = fs::read_to_string(path).map_err(io_error)?;
+    let source = Source { path, raw: &raw };
+    let mut configuration: RawConfiguration =
+        toml::from_str(&raw).map_err(|source| ComposeError::Toml {
+            path: path.to_owned(),
+            source: Box::new(source),
+        })?;
+
+    let mut composition = Composition::default();
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    stack.push(canonical);
+
+    for include in std::mem::take(&mut configuration.include) {
+        let included = compose(&directory.join(include.get_ref()), stack)?;
+        composition.overlay(included);
+    }
+
+    stack.pop();
+
+    for (name, group) in std::mem::take(&mut configuration.mapping_groups) {
+        let group = source.mappings(group)?;
+        composition
+            .mapping_groups
+            .entry(name)
+            .or_default()
+            .extend(group);
+    }
+
+    for (name, profile) in std::mem::take(&mut configuration.profiles) {
+        if !profile.include.is_empty()
+            || !profile.mapping_groups.is_empty()
+            || !profile.profiles.is_empty()
+        {
+            return Err(ComposeError::NestedProfile {
+                path: path.to_owned(),
+                profile: name,
+            });
+        }
+
+        let profile = source.layer(profile, &composition.mapping_groups)?;
+        composition
+            .profiles
+            .entry(name)
+            .or_default()
+            .overlay(profile);
+    }
+
+    let layer = source.layer(configuration, &composition.mapping_groups)?;
+    composition.configuration.overlay(layer);
+
+    Ok(composition)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::key::VirtualKey;
+
+    use super::*;
+
+    /// Directory with the files that is removed when dropped.
+    struct Files(PathBuf);
+
+    impl Files {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let directory = std::env::temp_dir()
+                .join(format!("akl-compose-{name}-{}", std::process::id()));
+
+            for (path, content) in files {
+                let path = directory.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, content).unwrap();
+            }
+
+            Self(directory)
+        }
+
+        fn load(&self, path: &str) -> Result<ConfigurationFile, ComposeError> {
+            load(&self.0.join(path))
+        }
+
+        fn origin(&self, path: &str, line: usize) -> Origin {
+            Origin {
+                path: self.0.join(path),
+                line,
+            }
+        }
+    }
+
+    impl Drop for Files {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn target(raw: &str) -> KeyCombination {
+        raw.parse().unwrap()
+    }
+
+    #[test]
+    fn test_include() {
+        let files = Files::new(
+            "include",
+            &[
+                (
+                    "shared/base.toml",
+                    "switch_key = \"CapsLock\"\n\
+                     default_combination = \"Escape\"\n\
+                     [mapping_groups.navigation]\n\
+                     h = \"LeftArrow\"\n\
+                     l = \"RightArrow\"\n\
+                     [mappings]\n\
+                     j = \"DownArrow\"\n\
+                     k = \"UpArrow\"\n\
+                     [profiles.gaming]\n\
+                     switch_key = \"Tab\"\n",
+                ),
+                (
+                    "personal.toml",
+                    "include = [\"shared/base.toml\"]\n\
+                     import = [\"navigation\"]\n\
+                     default_combination = \"LControl+z\"\n\
+                     [mapping_groups.navigation]\n\
+                     l = \"End\"\n\
+                     [mappings]\n\
+                     h = \"Home\"\n\
+                     k = \"PageUp\"\n\
+                     [profiles.gaming]\n\
+                     import = [\"navigation\"]\n",
+                ),
+            ],
+        );
+
+        let file = files.load("personal.toml").unwrap();
+        let configuration = &file.configuration;
+
+        assert_eq!(Some(VirtualKey::CapsLock.into()), configuration.switch_key);
+        assert_eq!(
+            Some(target("LControl+z")),
+            configuration.default_combination
+        );
+        assert_eq!(
+            collections::HashMap::from([
+                (target("h"), target("Home")),
+                (target("j"), target("DownArrow")),
+                (target("k"), target("PageUp")),
+                (target("l"), target("End")),
+            ]),
+            configuration.mappings
+        );
+
+        let gaming = &file.profiles["gaming"];
+
+        assert_eq!(Some(VirtualKey::Tab.into()), gaming.switch_key);
+        assert_eq!(
+            collections::HashMap::from([
+                (target("h"), target("LeftArrow")),
+                (target("l"), target("End")),
+            ]),
+            gaming.mappings
+        );
+    }
+
+    #[test]
+    fn test_application_mappings() {
+        let files = Files::new(
+            "application-mappings",
+            &[
+                (
+                    "base.toml",
+                    "[[application_mappings]]\n\
+                     window_class = \"Chrome_WidgetWin_1\"\n\
+                     [application_mappings.mappings]\n\
+                     h = \"LAlt+LeftArrow\"\n",
+                ),
+                (
+                    "personal.toml",
+                    "include = [\"base.toml\"]\n\
+                     switch_key = \"CapsLock\"\n\
+                     [[application_mappings]]\n\
+                     window_title = \"Visual Studio Code\"\n\
+                     [application_mappings.mappings]\n\
+                     h = \"Home\"\n",
+                ),
+            ],
+        );
+
+        let file = files.load("personal.toml").unwrap();
+
+        assert_eq!(
+            vec![Some("Visual Studio Code"), None,],
+            file.configuration
+                .application_mappings
+                .iter()
+                .map(|it| it.window_title.as_deref())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(&target("LAlt+LeftArrow")),
+            file.configuration.application_mappings[1]
+                .mappings
+                .get(&target("h"))
+        );
+    }
+
+    #[test]
+    fn test_conflict() {
+        let files = Files::new(
+            "conflict",
+            &[
+                (
+                    "groups.toml",
+                    "[mapping_groups.vim]\n\
+                     h = \"LeftArrow\"\n\
+                     \n\
+                     [mapping_groups.emacs]\n\
+                     \"LControl+b\" = \"LeftArrow\"\n\
+                     h = \"Backspace\"\n",
+                ),
+                (
+                    "layer.toml",
+                    "include = [\"groups.toml\"]\n\
+                     import = [\"vim\", \"emacs\"]\n",
+                ),
+                (
+                    "spellings.toml",
+                    "[mappings]\n\
+                     \"Strg+j\" = \"PageUp\"\n\
+                     \"LControl+j\" = \"PageDown\"\n",
+                ),
+                (
+                    "unknown.toml",
+                    "switch_key = \"CapsLock\"\n\
+                     import = [\"vim\"]\n",
+                ),
+            ],
+        );
+
+        let ComposeError::Conflict {
+            target: conflict,
+            first,
+            second,
+        } = files.load("layer.toml").unwrap_err()
+        else {
+            panic!("expected a conflict");
+        };
+
+        assert_eq!(target("h"), conflict);
+        assert_eq!(files.origin("groups.toml", 2), first);
+        assert_eq!(files.origin("groups.toml", 6), second);
+
+        let error = files.load("spellings.toml").unwrap_err();
+        let spellings = files.0.join("spellings.toml");
+
+        assert_eq!(
+            format!(
+                "The mapping of \"LControl+j\" at {}:2 conflicts with the one \
+                 at {}:3.",
+                spellings.display(),
+                spellings.display()
+            ),
+            error.to_string()
+        );
+
+        assert!(matches!(
+            files.load("unknown.toml").unwrap_err(),
+            ComposeError::UnknownGroup(origin, name)
+                if origin == files.origin("unknown.toml", 2) && name == "vim"
+        ));
+    }
+
+    #[test]
+    fn test_cycle() {
+        let files = Files::new(
+            "cycle",
+            &[
+                ("a.toml", "include = [\"b.toml\"]\n"),
+                ("b.toml", "include = [\"c.toml\", \"./a.toml\"]\n"),
+                ("c.toml", "switch_key = \"CapsLock\"\n"),
+                ("diamond.toml", "include = [\"c.toml\", \"c.toml\"]\n"),
+                ("nested.toml", "[profiles.gaming]\ninclude = [\"c.toml\"]\n"),
+            ],
+        );
+
+        let ComposeError::Cycle(cycle) = files.load("a.toml").unwrap_err()
+        else {
+            panic!("expected a cycle");
+        };
+        let names = cycle
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec!["a.toml", "b.toml", "a.toml"], names);
+
+        // Including the same file twice isn't a cycle.
+        assert_eq!(
+            Some(VirtualKey::CapsLock.into()),
+            files.load("diamond.toml").unwrap().configuration.switch_key
+        );
+
+        assert!(matches!(
+            files.load("nested.toml").unwrap_err(),
+            ComposeError::NestedProfile { profile, .. } if profile == "gaming"
+        ));
+        assert!(matches!(
+            files.load("missing.toml").unwrap_err(),
+            ComposeError::Io { .. }
+        ));
+    }
+
+    #[test]
+    fn test_application_file() {
+        let files = Files::new(
+            "application",
+            &[
+                (
+                    "base.toml",
+                    "default_combination = \"Escape\"\n\
+                     [mappings]\n\
+                     h = \"LeftArrow\"\n",
+                ),
+                (
+                    "another-keyboard-layer.toml",
+                    "include = [\"base.toml\"]\n\
+                     start_with_system = false\n\
+                     switch_key = \"CapsLock\"\n\
+                     default_simulation_combination = \"\"\n\
+                     kill_switch = \"LControl+LAlt+k\"\n",
+                ),
+            ],
+        );
+
+        let configuration = files
+            .load("another-keyboard-layer.toml")
+            .unwrap()
+            .configuration;
+
+        assert_eq!(None, configuration.default_combination);
+        assert_eq!(
+            collections::HashMap::from([(target("h"), target("LeftArrow"))]),
+            configuration.mappings
+        );
+    }
+}
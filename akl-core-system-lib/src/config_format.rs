@@ -0,0 +1,433 @@
+//! Serialization of the [`Configuration`] and the key types, only available
+//! with the `config-serde` feature.
+//!
+//! Keys, key combinations, virtual keys and mouse actions are written in the
+//! same human readable form that is used everywhere else, so a configuration
+//! looks the same in every [`ConfigFormat`]:
+//!
+//! ```toml
+//! switch_key = "CapsLock"
+//! default_combination = "Escape"
+//! text_output = "unicode"
+//!
+//! [mappings]
+//! h = "LeftArrow"
+//! "LControl+j" = "PageUp"
+//!
+//! [mouse_mappings]
+//! "LAlt+h" = "MoveLeft"
+//!
+//! [profile_mappings]
+//! "LAlt+2" = "gaming"
+//!
+//! [profiles.gaming]
+//! switch_key = "Tab"
+//! ```
+//!
+//! A [`ConfigurationFile`] holds named [`profiles`](crate::profile) next to the
+//! configuration at the top level. The mappings are written sorted by their
+//! target so that the output is stable and can be compared against snapshots.
+//! Files that include other files and share mapping groups are read with the
+//! [`compose`](crate::compose) module.
+
+use std::{collections, path::Path};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::{
+    key::{Key, KeyCombination, VirtualKey},
+    mouse::MouseAction,
+    profile::{Profiles, DEFAULT_PROFILE},
+    Configuration,
+};
+
+/// Implements serde for types that already convert to and from their human
+/// readable string form with [`Display`](std::fmt::Display) and
+/// [`FromStr`](std::str::FromStr).
+macro_rules! serialize_as_string {
+    ($($type: ty),*) => {
+        $(
+            impl Serialize for $type {
+                fn serialize<S: Serializer>(
+                    &self,
+                    serializer: S,
+                ) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $type {
+                fn deserialize<D: Deserializer<'de>>(
+                    deserializer: D,
+                ) -> Result<Self, D::Error> {
+                    String::deserialize(deserializer)?
+                        .parse()
+                        .map_err(de::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+serialize_as_string!(Key, KeyCombination, MouseAction);
+
+/// Written as the name of the key just like a [`Key::Virtual`].
+impl Serialize for VirtualKey {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&Key::Virtual(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for VirtualKey {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+
+        Self::try_from(raw.as_str()).map_err(de::Error::custom)
+    }
+}
+
+/// Serializes the mappings sorted by the string form of their target.
+pub(crate) fn sorted<S: Serializer, V: Serialize>(
+    mappings: &collections::HashMap<KeyCombination, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    mappings
+        .iter()
+        .map(|(target, value)| (target.to_string(), value))
+        .collect::<collections::BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+/// Configuration with any number of named profiles in `[profiles.<name>]`
+/// tables. Each profile is a complete configuration on its own, values
+/// missing in a profile fall back to their defaults and not to the top level.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConfigurationFile {
+    #[serde(flatten)]
+    pub configuration: Configuration,
+    #[serde(default, skip_serializing_if = "collections::BTreeMap::is_empty")]
+    pub profiles: collections::BTreeMap<String, Configuration>,
+}
+
+impl ConfigurationFile {
+    /// Splits the file into the configuration at the top level and the
+    /// profiles. As soon as there are any profiles, the top level
+    /// configuration is also saved as the [`DEFAULT_PROFILE`] and activated so
+    /// that profile mappings can switch back to it.
+    #[must_use]
+    pub fn into_profiles(self) -> (Configuration, Profiles) {
+        let mut profiles = Profiles::default();
+
+        if self.profiles.is_empty() {
+            return (self.configuration, profiles);
+        }
+
+        profiles.save(DEFAULT_PROFILE, self.configuration.clone());
+
+        // Every profile shares the pause combination of the top level.
+        for (name, configuration) in self.profiles {
+            profiles.save(
+                &name,
+                Configuration {
+                    pause_combination: self.configuration.pause_combination,
+                    ..configuration
+                },
+            );
+        }
+
+        // Only fails for a missing switch key which starting reports anyway.
+        let _ = profiles.activate(DEFAULT_PROFILE);
+
+        (self.configuration, profiles)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigFormatError {
+    #[error("Invalid toml configuration: {0}")]
+    TomlRead(#[from] toml::de::Error),
+    #[error("Couldn't write the configuration as toml: {0}")]
+    TomlWrite(#[from] toml::ser::Error),
+    #[error("Invalid json configuration: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid yaml configuration: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+/// Text formats a [`Configuration`] can be read from and written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Guesses the format from the extension of the path.
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Parses the configuration, missing values fall back to their defaults.
+    ///
+    /// # Errors
+    ///
+    /// If `raw` isn't valid in this format or contains invalid keys.
+    pub fn read(self, raw: &str) -> Result<Configuration, ConfigFormatError> {
+        Ok(self.read_file(raw)?.configuration)
+    }
+
+    /// Like [`read`](Self::read) but keeps the profiles.
+    ///
+    /// # Errors
+    ///
+    /// If `raw` isn't valid in this format or contains invalid keys.
+    pub fn read_file(
+        self,
+        raw: &str,
+    ) -> Result<ConfigurationFile, ConfigFormatError> {
+        Ok(match self {
+            Self::Toml => toml::from_str(raw)?,
+            Self::Json => serde_json::from_str(raw)?,
+            Self::Yaml => serde_yaml::from_str(raw)?,
+        })
+    }
+
+    /// Writes the configuration in a human readable way.
+    ///
+    /// # Errors
+    ///
+    /// Never for json and yaml, toml only fails for unrepresentable values
+    /// which the configuration doesn't contain.
+    pub fn write(
+        self,
+        configuration: &Configuration,
+    ) -> Result<String, ConfigFormatError> {
+        Ok(match self {
+            Self::Toml => toml::to_string(configuration)?,
+            Self::Json => serde_json::to_string_pretty(configuration)?,
+            Self::Yaml => serde_yaml::to_string(configuration)?,
+        })
+    }
+
+    /// Like [`write`](Self::write) but includes the profiles.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`write`](Self::write).
+    pub fn write_file(
+        self,
+        file: &ConfigurationFile,
+    ) -> Result<String, ConfigFormatError> {
+        Ok(match self {
+            Self::Toml => toml::to_string(file)?,
+            Self::Json => serde_json::to_string_pretty(file)?,
+            Self::Yaml => serde_yaml::to_string(file)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        application::ApplicationMappings,
+        key::TextOutput,
+        mouse::{Direction, MouseAction},
+    };
+
+    use super::*;
+
+    fn configuration() -> Configuration {
+        Configuration {
+            switch_key: Some(VirtualKey::CapsLock.into()),
+            default_combination: Some("Escape".parse().unwrap()),
+            pause_combination: Some("LControl+LAlt+p".parse().unwrap()),
+            mappings: collections::HashMap::from([
+                ("h".parse().unwrap(), "LeftArrow".parse().unwrap()),
+                ("LControl+j".parse().unwrap(), "PageUp".parse().unwrap()),
+                ("vk:0xBA".parse().unwrap(), "LShift+ä".parse().unwrap()),
+            ]),
+            mouse_mappings: collections::HashMap::from([(
+                "LAlt+h".parse().unwrap(),
+                MouseAction::Move(Direction::Left),
+            )]),
+            profile_mappings: collections::HashMap::from([(
+                "LAlt+2".parse().unwrap(),
+                "gaming".to_owned(),
+            )]),
+            application_mappings: Vec::new(),
+            text_output: TextOutput::Layout,
+        }
+    }
+
+    fn assert_round_trip(format: ConfigFormat) {
+        let expected = configuration();
+        let written = format.write(&expected).unwrap();
+        let read = format.read(&written).unwrap();
+
+        assert_eq!(expected.switch_key, read.switch_key);
+        assert_eq!(expected.default_combination, read.default_combination);
+        assert_eq!(expected.pause_combination, read.pause_combination);
+        assert_eq!(expected.mappings, read.mappings);
+        assert_eq!(expected.mouse_mappings, read.mouse_mappings);
+        assert_eq!(expected.profile_mappings, read.profile_mappings);
+        assert_eq!(expected.application_mappings, read.application_mappings);
+        assert_eq!(expected.text_output, read.text_output);
+
+        // Writing is stable.
+        assert_eq!(written, format.write(&read).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_round_trip(ConfigFormat::Toml);
+        assert_round_trip(ConfigFormat::Json);
+        assert_round_trip(ConfigFormat::Yaml);
+    }
+
+    #[test]
+    fn test_toml() {
+        assert_eq!(
+            "switch_key = \"CapsLock\"\n\
+             default_combination = \"Escape\"\n\
+             pause_combination = \"LControl+LAlt+p\"\n\
+             text_output = \"layout\"\n\
+             \n\
+             [mappings]\n\
+             \"LControl+j\" = \"PageUp\"\n\
+             h = \"LeftArrow\"\n\
+             \"vk:0xBA\" = \"LShift+ä\"\n\
+             \n\
+             [mouse_mappings]\n\
+             \"LAlt+h\" = \"MoveLeft\"\n\
+             \n\
+             [profile_mappings]\n\
+             \"LAlt+2\" = \"gaming\"\n",
+            ConfigFormat::Toml.write(&configuration()).unwrap()
+        );
+
+        // Every value is optional.
+        let configuration = ConfigFormat::Toml
+            .read(
+                "switch_key = \"capslock\"\n[mappings]\n\"Strg+j\" = \"PgUp\"",
+            )
+            .unwrap();
+
+        assert_eq!(Some(VirtualKey::CapsLock.into()), configuration.switch_key);
+        assert_eq!(None, configuration.default_combination);
+        assert_eq!(TextOutput::Unicode, configuration.text_output);
+        assert_eq!(
+            Some(&"PageUp".parse().unwrap()),
+            configuration.mappings.get(&"LControl+j".parse().unwrap())
+        );
+
+        let error = ConfigFormat::Json
+            .read("{\"switch_key\": \"Capslok\"}")
+            .unwrap_err();
+        assert!(error.to_string().contains("Did you mean \"CapsLock\"?"));
+    }
+
+    #[test]
+    fn test_application_mappings() {
+        let mut expected = configuration();
+        expected.application_mappings = vec![ApplicationMappings {
+            window_class: Some("Chrome_WidgetWin_1".to_owned()),
+            window_title: Some("Visual Studio Code".to_owned()),
+            mappings: collections::HashMap::from([(
+                "h".parse().unwrap(),
+                "Home".parse().unwrap(),
+            )]),
+        }];
+
+        let written = ConfigFormat::Toml.write(&expected).unwrap();
+        assert!(written.contains(
+            "[[application_mappings]]\n\
+             window_class = \"Chrome_WidgetWin_1\"\n\
+             window_title = \"Visual Studio Code\"\n\
+             \n\
+             [application_mappings.mappings]\n\
+             h = \"Home\"\n"
+        ));
+        assert_eq!(
+            expected.application_mappings,
+            ConfigFormat::Toml
+                .read(&written)
+                .unwrap()
+                .application_mappings
+        );
+    }
+
+    #[test]
+    fn test_profiles() {
+        let file = ConfigFormat::Toml
+            .read_file(
+                "switch_key = \"CapsLock\"\n\
+                 [profile_mappings]\n\
+                 \"LAlt+2\" = \"gaming\"\n\
+                 [profiles.gaming]\n\
+                 switch_key = \"Tab\"\n\
+                 [profiles.gaming.profile_mappings]\n\
+                 \"LAlt+1\" = \"default\"\n",
+            )
+            .unwrap();
+
+        assert_eq!(
+            Some(&"gaming".to_owned()),
+            file.configuration
+                .profile_mappings
+                .get(&"LAlt+2".parse().unwrap())
+        );
+        assert_eq!(
+            Some(VirtualKey::Tab.into()),
+            file.profiles["gaming"].switch_key
+        );
+
+        for format in
+            [ConfigFormat::Toml, ConfigFormat::Json, ConfigFormat::Yaml]
+        {
+            let written = format.write_file(&file).unwrap();
+            let read = format.read_file(&written).unwrap();
+
+            assert_eq!(written, format.write_file(&read).unwrap());
+        }
+
+        let (configuration, profiles) = file.into_profiles();
+
+        assert_eq!(Some(DEFAULT_PROFILE), profiles.active());
+        assert_eq!(Some(1), profiles.position("gaming"));
+        assert_eq!(Ok(()), profiles.validate(&configuration));
+    }
+
+    #[test]
+    fn test_virtual_key() {
+        let json = serde_json::to_string(&VirtualKey::PageUp).unwrap();
+
+        assert_eq!("\"PageUp\"", json);
+        assert_eq!(
+            VirtualKey::PageUp,
+            serde_json::from_str::<VirtualKey>(&json).unwrap()
+        );
+        assert!(serde_json::from_str::<VirtualKey>("\"a\"").is_err());
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            Some(ConfigFormat::Yaml),
+            ConfigFormat::from_path(Path::new("akl.yml"))
+        );
+        assert_eq!(None, ConfigFormat::from_path(Path::new("akl.ini")));
+    }
+}
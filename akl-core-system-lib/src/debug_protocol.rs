@@ -0,0 +1,96 @@
+//! Structured records that are sent to the debug server (see `debug-server.rs`).
+//!
+//! Every record is serialized as a single line of json which is tagged with its
+//! `type`:
+//!
+//! ```text
+//! {"type":"log","level":"INFO","target":"akl_core_system_lib","message":"..."}
+//! {"type":"event","action":"press","key":"h","response":"LeftArrow","layer_active":true,"layer_keys":[],"timings":{...}}
+//! ```
+//!
+//! This module is shared with the debug server binary and thus must not depend
+//! on anything else from this crate.
+
+use serde::{Deserialize, Serialize};
+
+/// One line sent from the library to the debug server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Log(LogRecord),
+    Event(EventRecord),
+}
+
+/// A regular log message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Level as written by [`log::Level`] (`ERROR` to `TRACE`).
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+/// An event that was processed by the keyboard hook. Only sent if the `TRACE`
+/// level is enabled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventRecord {
+    /// Either `press` or `release`.
+    pub action: String,
+    /// Key in the same format as the configuration file.
+    pub key: String,
+    /// Either `block`, `nothing` or the replacement key combination.
+    pub response: String,
+    /// Whether the virtual layer is active after processing the event.
+    pub layer_active: bool,
+    /// Keys that are held down on the virtual layer after processing the
+    /// event.
+    pub layer_keys: Vec<String>,
+    pub timings: Timings,
+}
+
+/// Time in nanoseconds each stage of handling the event took, `None` if the
+/// stage was skipped.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timings {
+    pub translation: Option<u64>,
+    pub processing: Option<u64>,
+    pub output: Option<u64>,
+    pub total: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_format() {
+        let record = Record::Event(EventRecord {
+            action: "press".to_owned(),
+            key: "h".to_owned(),
+            response: "LeftArrow".to_owned(),
+            layer_active: true,
+            layer_keys: vec![],
+            timings: Timings {
+                translation: Some(1),
+                total: 3,
+                ..Default::default()
+            },
+        });
+
+        let line = serde_json::to_string(&record).unwrap();
+
+        assert!(line.starts_with(r#"{"type":"event","action":"press""#));
+        assert_eq!(record, serde_json::from_str(&line).unwrap());
+
+        let record = Record::Log(LogRecord {
+            level: "INFO".to_owned(),
+            target: "akl".to_owned(),
+            message: "Register \"hook\"".to_owned(),
+        });
+
+        assert_eq!(
+            r#"{"type":"log","level":"INFO","target":"akl","message":"Register \"hook\""}"#,
+            serde_json::to_string(&record).unwrap()
+        );
+    }
+}
@@ -0,0 +1,1006 @@
+//! Fans out the events of the single native keyboard hook to every registered
+//! [`Context`] in order of their priority.
+//!
+//! Each [`AnotherKeyboardLayer`](crate::AnotherKeyboardLayer) that is running
+//! owns one context with its own event processor, kill switch, observer,
+//! latency and watchdog so that any number of them can run side by side. The
+//! contexts are asked one after another, starting with the highest priority,
+//! until one of them responds with anything but
+//! [`DoNothing`](ResponseAction::DoNothing). Contexts with a lower priority
+//! don't see the event anymore.
+//!
+//! Every kill switch sees every event before any event processor does. Once
+//! the combination of any of them is pressed all contexts get disabled.
+//!
+//! A context switches its event processor to another
+//! [`profile`](crate::profile) as soon as it responds with
+//! [`SwitchProfile`](ResponseAction::SwitchProfile).
+//!
+//! Event processors with [application mappings](crate::application) learn
+//! which window is in the foreground whenever their virtual layer gets
+//! activated.
+//!
+//! Each context keeps track of the [input it holds](HeldInput) so that only
+//! its own keys, buttons and pointer movement are released once it goes away.
+//!
+//! The keyboard hook only waits for the event processor of a context until
+//! the [time budget](crate::watchdog) of the event is used up. Events that
+//! exceed it are passed through unchanged as if the context didn't exist. The
+//! api reads the statistics, latency and state of a context from a
+//! [snapshot](Context::statistics) so that it never holds up the keyboard
+//! hook.
+
+use std::{
+    cell::OnceCell,
+    mem,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Instant,
+};
+
+use arc_swap::ArcSwap;
+use log::{error, info, log_enabled, warn, Level};
+
+use crate::{
+    application::Application,
+    debug_protocol::EventRecord,
+    event::{Action, Event, EventProcessor, ResponseAction},
+    key::{Key, TextOutput},
+    kill_switch::KillSwitch,
+    latency::{Latency, Sample},
+    logging,
+    mouse::{MouseAction, MouseButton, Movement},
+    observer::ObserverSender,
+    profile::{ProfileError, Profiles},
+    statistics::Statistics,
+    watchdog::{Verdict, Watchdog},
+};
+
+/// Every time this many events were recorded the latency of a context gets
+/// logged.
+const LOG_LATENCY_INTERVAL: u64 = 1000;
+
+/// Why a context disabled itself while its owner was still running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisableReason {
+    /// The kill switch combination was pressed.
+    KillSwitch,
+    /// Too many events in a row exceeded the time budget of the watchdog.
+    Watchdog { overruns: u32 },
+}
+
+/// State the event processor needs for each event.
+struct ContextState {
+    event_processor: EventProcessor,
+    /// Used for the replacements of the active profile.
+    text_output: TextOutput,
+    profiles: Profiles,
+    /// Whether a profile replaced the configuration the context was created
+    /// with.
+    switched_profile: bool,
+}
+
+impl ContextState {
+    /// Continues with the configuration of the profile at the position. Does
+    /// nothing if there is no such profile.
+    fn switch_profile(&mut self, index: usize) {
+        let Some(configuration) = self.profiles.activate_at(index).cloned()
+        else {
+            return;
+        };
+
+        info!("Switching to the profile {:?}", self.profiles.active());
+
+        self.switched_profile = true;
+        self.text_output = configuration.text_output;
+        self.event_processor
+            .switch_to(EventProcessor::with_profiles(
+                configuration,
+                &self.profiles,
+            ));
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            statistics: self.event_processor.statistics().clone(),
+            paused: self.event_processor.is_paused(),
+            active_profile: self.profiles.active().map(str::to_owned),
+            switched_profile: self.switched_profile,
+        }
+    }
+}
+
+/// Events the event processor missed because they exceeded the time budget.
+#[derive(Default)]
+struct Missed {
+    /// Keys whose press was passed through. The event processor never saw the
+    /// press, so their auto repeat and release are passed through as well.
+    presses: Vec<Key>,
+    /// Releases that were passed through although the event processor saw
+    /// the press. It catches up on them as soon as it gets the chance so that
+    /// it never waits for them.
+    releases: Vec<(Event, Option<u16>)>,
+}
+
+/// Copy of the state the api reads, published whenever the state changes.
+struct Snapshot {
+    statistics: Statistics,
+    paused: bool,
+    active_profile: Option<String>,
+    switched_profile: bool,
+}
+
+/// Input of a context that reached the applications and still has to be
+/// ended once the context goes away.
+pub struct HeldInput {
+    /// Native virtual key codes whose key down reached the applications
+    /// (passed through or simulated) without a key up yet.
+    pub keys: Vec<u16>,
+    /// Mouse buttons held down by mouse keys.
+    pub buttons: Vec<MouseButton>,
+    /// Directions the pointer moves in because of mouse keys.
+    pub movement: Movement,
+}
+
+impl HeldInput {
+    const fn new() -> Self {
+        Self {
+            keys: vec![],
+            buttons: vec![],
+            movement: Movement::new(),
+        }
+    }
+
+    /// Keeps the keys up to date with an event that reached the applications.
+    pub fn track_key(&mut self, key_code: u16, action: Action) {
+        match action {
+            Action::Press if !self.keys.contains(&key_code) => {
+                self.keys.push(key_code);
+            }
+            Action::Press => {}
+            Action::Release => self.keys.retain(|it| *it != key_code),
+        }
+    }
+
+    /// Keeps the buttons up to date with a simulated button event.
+    pub fn track_button(&mut self, button: MouseButton, action: Action) {
+        match action {
+            Action::Press if !self.buttons.contains(&button) => {
+                self.buttons.push(button);
+            }
+            Action::Press => {}
+            Action::Release => self.buttons.retain(|it| *it != button),
+        }
+    }
+}
+
+/// Everything one virtual layer needs to process events. The kill switch,
+/// watchdog and disable reason are kept outside of the processing state so
+/// that checking them never waits for the event processor.
+pub struct Context {
+    priority: i32,
+    /// Only locked by the keyboard hook for as long as the time budget allows,
+    /// see [`try_state`](Self::try_state).
+    state: parking_lot::Mutex<ContextState>,
+    snapshot: ArcSwap<Snapshot>,
+    /// Only recorded by the keyboard hook.
+    latency: ArcSwap<Latency>,
+    observer: Option<ObserverSender>,
+    kill_switch: Mutex<KillSwitch>,
+    watchdog: Mutex<Watchdog>,
+    disabled: Mutex<Option<DisableReason>>,
+    held_input: Mutex<HeldInput>,
+    missed: Mutex<Missed>,
+}
+
+impl Context {
+    /// Contexts with a higher priority are asked first. The text output is
+    /// used for the replacements of this context until it switches to one of
+    /// the profiles, which have to be
+    /// [`valid`](crate::profile::Profiles::validate).
+    pub fn new(
+        event_processor: EventProcessor,
+        kill_switch: KillSwitch,
+        observer: Option<ObserverSender>,
+        priority: i32,
+        text_output: TextOutput,
+        profiles: Profiles,
+    ) -> Self {
+        let state = ContextState {
+            event_processor,
+            text_output,
+            profiles,
+            switched_profile: false,
+        };
+
+        Self {
+            priority,
+            snapshot: ArcSwap::from_pointee(state.snapshot()),
+            state: parking_lot::Mutex::new(state),
+            latency: ArcSwap::default(),
+            observer,
+            kill_switch: Mutex::new(kill_switch),
+            watchdog: Mutex::new(Watchdog::default()),
+            disabled: Mutex::new(None),
+            held_input: Mutex::new(HeldInput::new()),
+            missed: Mutex::new(Missed::default()),
+        }
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Snapshot of the usage statistics collected by the event processor.
+    pub fn statistics(&self) -> Statistics {
+        self.snapshot.load().statistics.clone()
+    }
+
+    /// Switches the event processor to the profile right away.
+    ///
+    /// # Errors
+    ///
+    /// - [`ProfileError::Unknown`] => If there is no profile with the name
+    pub fn switch_profile(&self, name: &str) -> Result<(), ProfileError> {
+        let mut state = self.state();
+
+        let index = state
+            .profiles
+            .position(name)
+            .ok_or_else(|| ProfileError::Unknown(name.to_owned()))?;
+
+        state.switch_profile(index);
+        self.publish(&state);
+
+        Ok(())
+    }
+
+    /// Pauses or resumes the event processor right away.
+    pub fn set_paused(&self, paused: bool) {
+        let mut state = self.state();
+        state.event_processor.set_paused(paused);
+        self.publish(&state);
+    }
+
+    /// Whether the event processor passes every event through.
+    pub fn is_paused(&self) -> bool {
+        self.snapshot.load().paused
+    }
+
+    /// Name of the profile the context switched to last.
+    pub fn active_profile(&self) -> Option<String> {
+        self.snapshot.load().active_profile.clone()
+    }
+
+    /// Whether the context switched to a profile at all since it was created,
+    /// even if it switched back to the one that was active at the time.
+    pub fn has_switched_profile(&self) -> bool {
+        self.snapshot.load().switched_profile
+    }
+
+    /// Snapshot of the time the keyboard hook took for the events this
+    /// context processed.
+    pub fn latency(&self) -> Latency {
+        Latency::clone(&self.latency.load())
+    }
+
+    /// Why the context disabled itself if it did.
+    pub fn disabled(&self) -> Option<DisableReason> {
+        *lock(&self.disabled)
+    }
+
+    /// Input this context holds, see [`HeldInput`].
+    pub fn held_input(&self) -> MutexGuard<'_, HeldInput> {
+        lock(&self.held_input)
+    }
+
+    /// Adds the sample of an event this context processed and periodically
+    /// logs the latency.
+    pub fn record_latency(&self, sample: &Sample) {
+        let mut latency = Latency::clone(&self.latency.load());
+        let previous_count = latency.total.count();
+        latency.record(sample);
+
+        let count = latency.total.count();
+
+        if count != previous_count && count.is_multiple_of(LOG_LATENCY_INTERVAL)
+        {
+            info!("Hook latency {latency}");
+        }
+
+        self.latency.store(Arc::new(latency));
+    }
+
+    fn state(&self) -> parking_lot::MutexGuard<'_, ContextState> {
+        self.state.lock()
+    }
+
+    /// Waits for the processing state until the time budget of the event that
+    /// started at the instant is used up.
+    fn try_state(
+        &self,
+        started: Instant,
+    ) -> Option<parking_lot::MutexGuard<'_, ContextState>> {
+        let remaining = lock(&self.watchdog)
+            .budget()
+            .saturating_sub(started.elapsed());
+
+        self.state.try_lock_for(remaining)
+    }
+
+    /// Lets the api see the changes of the state.
+    fn publish(&self, state: &ContextState) {
+        self.snapshot.store(Arc::new(state.snapshot()));
+    }
+
+    /// Whether the event belongs to a key whose press was passed through
+    /// without the event processor. Forgets the key once it is released.
+    fn is_missed_press(&self, key: Key, action: Action) -> bool {
+        let presses = &mut lock(&self.missed).presses;
+
+        if !presses.contains(&key) {
+            return false;
+        }
+
+        if action == Action::Release {
+            presses.retain(|it| *it != key);
+        }
+
+        true
+    }
+
+    /// Remembers the event that is passed through without the event
+    /// processor, see [`Missed`].
+    fn miss(&self, key: Key, event: Event, key_code: Option<u16>) {
+        let mut missed = lock(&self.missed);
+
+        match event.action {
+            Action::Press if !missed.presses.contains(&key) => {
+                missed.presses.push(key);
+            }
+            Action::Press => {}
+            Action::Release => missed.releases.push((event, key_code)),
+        }
+    }
+
+    /// Lets the event processor catch up on the releases it missed. The
+    /// responses are too late except for the mouse actions that still have to
+    /// be ended, which are returned.
+    fn catch_up(&self, state: &mut ContextState) -> Vec<MouseAction> {
+        let releases = mem::take(&mut lock(&self.missed).releases);
+
+        releases
+            .into_iter()
+            .filter_map(|(event, key_code)| {
+                let event = seen_as(event, key_code, &state.event_processor);
+
+                match state.event_processor.process(event) {
+                    ResponseAction::Mouse(mouse_action, Action::Release) => {
+                        Some(mouse_action)
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Disables the context and lets the observer know about it.
+    fn disable(&self, reason: DisableReason) {
+        warn!("Disabling the virtual layer: {reason:?}");
+        *lock(&self.disabled) = Some(reason);
+
+        if let Some(observer) = &self.observer {
+            observer.notify_disabled(reason);
+        }
+    }
+
+    /// Checks the time this context spent on the event and disables the
+    /// context after too many overruns in a row.
+    fn check_watchdog(&self, started: Instant) -> Verdict {
+        let elapsed = started.elapsed();
+        let mut watchdog = lock(&self.watchdog);
+        let verdict = watchdog.check(elapsed);
+
+        match verdict {
+            Verdict::WithinBudget => {}
+            Verdict::Overrun => {
+                warn!("Event exceeded the time budget ({elapsed:?}), passing it through.");
+            }
+            Verdict::Disable => {
+                let overruns = watchdog.consecutive_overruns();
+                drop(watchdog);
+
+                error!("{overruns} events in a row exceeded the time budget.");
+                self.disable(DisableReason::Watchdog { overruns });
+            }
+        }
+
+        verdict
+    }
+}
+
+/// The event as the event processor sees it, see [`dispatch`].
+fn seen_as(
+    event: Event,
+    key_code: Option<u16>,
+    event_processor: &EventProcessor,
+) -> Event {
+    match (event.key, key_code) {
+        (Key::Text(_), Some(key_code))
+            if event_processor.uses_raw_key(key_code) =>
+        {
+            Event {
+                key: Key::Raw(key_code),
+                ..event
+            }
+        }
+        _ => event,
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .expect("Processing never panics and thus never poisons this mutex.")
+}
+
+/// Outcome of dispatching one event to all contexts.
+pub struct Dispatch {
+    /// Response of the first context that didn't respond with
+    /// [`DoNothing`](ResponseAction::DoNothing).
+    pub response: ResponseAction,
+    /// How the context that responded wants text keys to be simulated.
+    pub text_output: TextOutput,
+    /// Context that responded, it holds the input of the response.
+    pub responder: Option<Arc<Context>>,
+    /// Contexts that processed the event or passed it through because it
+    /// exceeded their time budget, the latency should be
+    /// [`recorded`](Context::record_latency) for each of them.
+    pub processed_by: Vec<Arc<Context>>,
+    /// Contexts that disabled themselves because of this event.
+    pub disabled: Vec<Arc<Context>>,
+    /// Mouse actions whose release a context only caught up on after it
+    /// exceeded the time budget, they still have to be ended.
+    pub ended_late: Vec<(Arc<Context>, MouseAction)>,
+    /// Describes the event for the log if the `Trace` level is enabled.
+    pub event_record: Option<EventRecord>,
+}
+
+/// Passes the event to the contexts, which have to be sorted by descending
+/// priority, until one of them responds. Disabled contexts are skipped.
+///
+/// The native key code of the event is only needed for keys that produce
+/// text. Contexts that [use](EventProcessor::uses_raw_key) the key as raw key
+/// see the event as that raw key instead.
+///
+/// A context that can't respond within the time budget passes the event
+/// through and takes back whatever its event processor did with it. The
+/// release of a key is passed through as well if its press was, otherwise the
+/// event processor catches up on it later, see [`Missed`].
+///
+/// The foreground application is only looked up if an event processor
+/// [needs](EventProcessor::needs_application) it and at most once per event.
+pub fn dispatch(
+    contexts: &[Arc<Context>],
+    event: Event,
+    key_code: Option<u16>,
+    foreground: impl Fn() -> Application,
+) -> Dispatch {
+    let mut dispatch = Dispatch {
+        response: ResponseAction::DoNothing,
+        text_output: TextOutput::default(),
+        responder: None,
+        processed_by: vec![],
+        disabled: vec![],
+        ended_late: vec![],
+        event_record: None,
+    };
+
+    let enabled = contexts
+        .iter()
+        .filter(|context| context.disabled().is_none())
+        .collect::<Vec<_>>();
+
+    // Checked before any event processor so that even a broken configuration
+    // can't prevent disabling the virtual layers. Every kill switch has to see
+    // the event to keep track of the pressed keys.
+    let mut killed = false;
+
+    for context in &enabled {
+        killed |= lock(&context.kill_switch).process(event);
+    }
+
+    if killed {
+        for context in enabled {
+            context.disable(DisableReason::KillSwitch);
+            dispatch.disabled.push(Arc::clone(context));
+        }
+
+        dispatch.response = ResponseAction::Block;
+        return dispatch;
+    }
+
+    // Identifies the key of a passed through event, the text a key produces
+    // might be different once it's released.
+    let key = key_code.map_or(event.key, Key::Raw);
+    let application = OnceCell::new();
+
+    for context in enabled {
+        let started = Instant::now();
+
+        if context.is_missed_press(key, event.action) {
+            dispatch.processed_by.push(Arc::clone(context));
+            continue;
+        }
+
+        let Some(mut state) = context.try_state(started) else {
+            context.miss(key, event, key_code);
+            dispatch.processed_by.push(Arc::clone(context));
+
+            if context.check_watchdog(started) == Verdict::Disable {
+                dispatch.disabled.push(Arc::clone(context));
+            }
+
+            continue;
+        };
+
+        for mouse_action in context.catch_up(&mut state) {
+            dispatch
+                .ended_late
+                .push((Arc::clone(context), mouse_action));
+        }
+
+        let seen = seen_as(event, key_code, &state.event_processor);
+
+        if state.event_processor.needs_application(seen) {
+            state
+                .event_processor
+                .set_application(application.get_or_init(&foreground));
+        }
+
+        let checkpoint = state.event_processor.checkpoint();
+        let response = state.event_processor.process(seen);
+        dispatch.processed_by.push(Arc::clone(context));
+
+        let verdict = context.check_watchdog(started);
+
+        if verdict != Verdict::WithinBudget {
+            // The response would be too late, so it's as if the event
+            // processor never saw the event.
+            state.event_processor.restore(checkpoint);
+            context.publish(&state);
+            drop(state);
+            context.miss(key, event, key_code);
+
+            if verdict == Verdict::Disable {
+                dispatch.disabled.push(Arc::clone(context));
+            }
+
+            continue;
+        }
+
+        if let ResponseAction::SwitchProfile(index) = response {
+            state.switch_profile(index);
+        }
+
+        context.publish(&state);
+
+        if log_enabled!(Level::Trace) {
+            dispatch.event_record = Some(logging::event_record(
+                seen,
+                response,
+                &state.event_processor,
+            ));
+        }
+
+        if let Some(observer) = &context.observer {
+            // A full queue means the observer can't keep up, dropping the
+            // notification is preferable to delaying the input.
+            let _ = observer.notify(seen, response);
+        }
+
+        let text_output = state.text_output;
+        drop(state);
+
+        if response != ResponseAction::DoNothing {
+            dispatch.response = response;
+            dispatch.text_output = text_output;
+            dispatch.responder = Some(Arc::clone(context));
+            break;
+        }
+    }
+
+    dispatch
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections};
+
+    use crate::{
+        application::ApplicationMappings, key::VirtualKey, Configuration,
+    };
+
+    use super::*;
+
+    fn context(switch_key: VirtualKey, priority: i32) -> Arc<Context> {
+        let configuration = Configuration {
+            switch_key: Some(switch_key.into()),
+            default_combination: None,
+            pause_combination: None,
+            mappings: collections::HashMap::from([(
+                "h".parse().unwrap(),
+                "LeftArrow".parse().unwrap(),
+            )]),
+            mouse_mappings: collections::HashMap::new(),
+            profile_mappings: collections::HashMap::new(),
+            application_mappings: Vec::new(),
+            text_output: TextOutput::default(),
+        };
+
+        Arc::new(Context::new(
+            configuration.into(),
+            KillSwitch::new("LControl+k".parse().unwrap()),
+            None,
+            priority,
+            TextOutput::default(),
+            Profiles::default(),
+        ))
+    }
+
+    fn press(key: impl Into<Key>) -> Event {
+        Event {
+            action: Action::Press,
+            key: key.into(),
+        }
+    }
+
+    fn release(key: impl Into<Key>) -> Event {
+        Event {
+            action: Action::Release,
+            key: key.into(),
+        }
+    }
+
+    #[test]
+    fn test_priority() {
+        let high = context(VirtualKey::CapsLock, 1);
+        let low = context(VirtualKey::Tab, 0);
+        let contexts = [Arc::clone(&high), Arc::clone(&low)];
+
+        // The switch key of the low priority context isn't used by the high
+        // priority one.
+        let dispatched = dispatch(
+            &contexts,
+            press(VirtualKey::Tab),
+            None,
+            Application::default,
+        );
+        assert_eq!(ResponseAction::Block, dispatched.response);
+        assert_eq!(2, dispatched.processed_by.len());
+
+        // The low priority context never sees events the high priority one
+        // responds to.
+        let dispatched = dispatch(
+            &contexts,
+            press(VirtualKey::CapsLock),
+            None,
+            Application::default,
+        );
+        assert_eq!(ResponseAction::Block, dispatched.response);
+        assert_eq!(1, dispatched.processed_by.len());
+
+        assert_eq!(1, high.statistics().layer_activations);
+        assert_eq!(1, low.statistics().layer_activations);
+    }
+
+    #[test]
+    fn test_kill_switch() {
+        let high = context(VirtualKey::CapsLock, 1);
+        let low = context(VirtualKey::Tab, 0);
+        let contexts = [Arc::clone(&high), Arc::clone(&low)];
+
+        // The kill switch of the low priority context sees the events the
+        // high priority one responds to.
+        dispatch(
+            &contexts,
+            press(VirtualKey::CapsLock),
+            None,
+            Application::default,
+        );
+        let dispatched = dispatch(
+            &contexts,
+            press(VirtualKey::LControl),
+            None,
+            Application::default,
+        );
+        assert_eq!(1, dispatched.processed_by.len());
+        let killed =
+            dispatch(&contexts, press('k'), None, Application::default);
+
+        assert_eq!(2, killed.disabled.len());
+        assert_eq!(ResponseAction::Block, killed.response);
+        assert_eq!(Some(DisableReason::KillSwitch), high.disabled());
+        assert_eq!(Some(DisableReason::KillSwitch), low.disabled());
+
+        // Disabled contexts are skipped from now on.
+        let dispatched = dispatch(
+            &contexts,
+            press(VirtualKey::Tab),
+            None,
+            Application::default,
+        );
+        assert_eq!(ResponseAction::DoNothing, dispatched.response);
+        assert!(dispatched.processed_by.is_empty());
+    }
+
+    #[test]
+    fn test_paused() {
+        let high = context(VirtualKey::CapsLock, 1);
+        let low = context(VirtualKey::Tab, 0);
+        let contexts = [Arc::clone(&high), Arc::clone(&low)];
+
+        dispatch(
+            &contexts,
+            press(VirtualKey::CapsLock),
+            None,
+            Application::default,
+        );
+        high.set_paused(true);
+        assert!(high.is_paused());
+
+        // The paused context passes the event on to the next one.
+        let dispatched =
+            dispatch(&contexts, press('h'), None, Application::default);
+        assert_eq!(ResponseAction::DoNothing, dispatched.response);
+        assert_eq!(2, dispatched.processed_by.len());
+
+        // The switch key that was held down when pausing stays blocked.
+        let dispatched = dispatch(
+            &contexts,
+            release(VirtualKey::CapsLock),
+            None,
+            Application::default,
+        );
+        assert_eq!(ResponseAction::Block, dispatched.response);
+
+        high.set_paused(false);
+        dispatch(
+            &contexts,
+            press(VirtualKey::CapsLock),
+            None,
+            Application::default,
+        );
+        let dispatched =
+            dispatch(&contexts, press('h'), None, Application::default);
+        assert_eq!(
+            ResponseAction::ReplaceWith("LeftArrow".parse().unwrap()),
+            dispatched.response
+        );
+    }
+
+    #[test]
+    fn test_raw_key() {
+        let mut configuration = Configuration {
+            switch_key: Some(VirtualKey::CapsLock.into()),
+            ..Configuration::default()
+        };
+        configuration
+            .mappings
+            .insert("vk:0xBA".parse().unwrap(), "LeftArrow".parse().unwrap());
+        configuration
+            .mappings
+            .insert("h".parse().unwrap(), "Tab".parse().unwrap());
+
+        let contexts = [Arc::new(Context::new(
+            configuration.into(),
+            KillSwitch::new("LControl+k".parse().unwrap()),
+            None,
+            0,
+            TextOutput::default(),
+            Profiles::default(),
+        ))];
+
+        dispatch(
+            &contexts,
+            press(VirtualKey::CapsLock),
+            None,
+            Application::default,
+        );
+
+        // The key produces text but the configuration uses its key code.
+        let dispatched =
+            dispatch(&contexts, press(';'), Some(0xBA), Application::default);
+        assert_eq!(
+            ResponseAction::ReplaceWith("LeftArrow".parse().unwrap()),
+            dispatched.response
+        );
+
+        // Other keys that produce text are still seen as text.
+        let dispatched =
+            dispatch(&contexts, press('h'), Some(0x48), Application::default);
+        assert_eq!(
+            ResponseAction::ReplaceWith("Tab".parse().unwrap()),
+            dispatched.response
+        );
+    }
+
+    #[test]
+    fn test_switch_profile() {
+        let mut coding = Configuration {
+            switch_key: Some(VirtualKey::CapsLock.into()),
+            ..Configuration::default()
+        };
+        coding
+            .profile_mappings
+            .insert("LAlt+2".parse().unwrap(), "gaming".to_owned());
+
+        let mut profiles = Profiles::default();
+        profiles.save("coding", coding.clone());
+        profiles.save(
+            "gaming",
+            Configuration {
+                switch_key: Some(VirtualKey::Tab.into()),
+                text_output: TextOutput::Layout,
+                ..Configuration::default()
+            },
+        );
+
+        let context = Arc::new(Context::new(
+            EventProcessor::with_profiles(coding, &profiles),
+            KillSwitch::new("LControl+k".parse().unwrap()),
+            None,
+            0,
+            TextOutput::default(),
+            profiles,
+        ));
+        let contexts = [Arc::clone(&context)];
+        assert!(!context.has_switched_profile());
+
+        dispatch(
+            &contexts,
+            press(VirtualKey::CapsLock),
+            None,
+            Application::default,
+        );
+        dispatch(
+            &contexts,
+            press(VirtualKey::LAlt),
+            None,
+            Application::default,
+        );
+        let switched =
+            dispatch(&contexts, press('2'), None, Application::default);
+
+        assert_eq!(ResponseAction::SwitchProfile(1), switched.response);
+        assert_eq!(Some("gaming".to_owned()), context.active_profile());
+        assert!(context.has_switched_profile());
+
+        let dispatched = dispatch(
+            &contexts,
+            press(VirtualKey::Tab),
+            None,
+            Application::default,
+        );
+        assert_eq!(ResponseAction::Block, dispatched.response);
+        assert_eq!(TextOutput::Layout, dispatched.text_output);
+
+        context.switch_profile("coding").unwrap();
+        assert_eq!(Some("coding".to_owned()), context.active_profile());
+        assert_eq!(
+            Err(ProfileError::Unknown("reading".to_owned())),
+            context.switch_profile("reading")
+        );
+    }
+
+    #[test]
+    fn test_application_mappings() {
+        let configuration = Configuration {
+            switch_key: Some(VirtualKey::CapsLock.into()),
+            application_mappings: vec![ApplicationMappings {
+                window_class: Some("Notepad".to_owned()),
+                window_title: None,
+                mappings: collections::HashMap::from([(
+                    "h".parse().unwrap(),
+                    "Home".parse().unwrap(),
+                )]),
+            }],
+            ..Configuration::default()
+        };
+        let contexts = [
+            context(VirtualKey::Tab, 1),
+            Arc::new(Context::new(
+                configuration.into(),
+                KillSwitch::new("LControl+k".parse().unwrap()),
+                None,
+                0,
+                TextOutput::default(),
+                Profiles::default(),
+            )),
+        ];
+
+        let lookups = Cell::new(0);
+        let foreground = || {
+            lookups.set(lookups.get() + 1);
+
+            Application {
+                class: "Notepad".to_owned(),
+                title: String::new(),
+            }
+        };
+
+        // Only the activation of a layer with application mappings needs the
+        // foreground window.
+        dispatch(&contexts, press(VirtualKey::Tab), None, foreground);
+        dispatch(&contexts, release(VirtualKey::Tab), None, foreground);
+        assert_eq!(0, lookups.get());
+
+        dispatch(&contexts, press(VirtualKey::CapsLock), None, foreground);
+        dispatch(&contexts, press(VirtualKey::CapsLock), None, foreground);
+        assert_eq!(1, lookups.get());
+
+        let dispatched = dispatch(&contexts, press('h'), None, foreground);
+        assert_eq!(
+            ResponseAction::ReplaceWith("Home".parse().unwrap()),
+            dispatched.response
+        );
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let context = context(VirtualKey::CapsLock, 0);
+        let contexts = [Arc::clone(&context)];
+
+        dispatch(
+            &contexts,
+            press(VirtualKey::CapsLock),
+            None,
+            Application::default,
+        );
+
+        // The event processor is busy for longer than the time budget.
+        let state = context.state();
+
+        let dispatched =
+            dispatch(&contexts, press('h'), None, Application::default);
+        assert_eq!(ResponseAction::DoNothing, dispatched.response);
+        assert_eq!(1, dispatched.processed_by.len());
+
+        let dispatched = dispatch(
+            &contexts,
+            release(VirtualKey::CapsLock),
+            None,
+            Application::default,
+        );
+        assert_eq!(ResponseAction::DoNothing, dispatched.response);
+
+        drop(state);
+
+        // The event processor never saw the press, so the release is passed
+        // through as well.
+        let dispatched =
+            dispatch(&contexts, release('h'), None, Application::default);
+        assert_eq!(ResponseAction::DoNothing, dispatched.response);
+
+        // The event processor caught up on the release of the switch key.
+        let dispatched =
+            dispatch(&contexts, press('h'), None, Application::default);
+        assert_eq!(ResponseAction::DoNothing, dispatched.response);
+        assert!(context.statistics().mappings.is_empty());
+        assert_eq!(None, context.disabled());
+
+        let state = context.state();
+
+        // Auto repeat of a passed through press doesn't count again.
+        for key in ['j', 'k', 'l'] {
+            dispatch(&contexts, press(key), None, Application::default);
+            dispatch(&contexts, press(key), None, Application::default);
+        }
+
+        drop(state);
+
+        assert_eq!(
+            Some(DisableReason::Watchdog { overruns: 3 }),
+            context.disabled()
+        );
+    }
+}
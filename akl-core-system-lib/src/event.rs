@@ -8,25 +8,40 @@
 //! applied by the keyboard hook, it then fetches the next message and repeats
 //! this procedure.
 
-use std::collections;
+use std::{collections, fmt, mem};
 
 use crate::{
+    application::{Application, ApplicationMappings},
     key::{Key, KeyCombination},
+    kill_switch::KillSwitch,
+    mouse::MouseAction,
+    profile::Profiles,
+    statistics::Statistics,
     Configuration,
 };
 
 /// The action that caused this event which is either the pressing or releasing
 /// of any keyboard key.
 #[allow(unused)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Action {
     Press,
     Release,
 }
 
+/// Writes `press` or `release`.
+impl fmt::Display for Action {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Press => write!(formatter, "press"),
+            Self::Release => write!(formatter, "release"),
+        }
+    }
+}
+
 /// Platform independent abstraction over a low level keyboard event that
 /// specifies the trigger and related [`key`](crate::key::Key).
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Event {
     pub action: Action,
     pub key: Key,
@@ -40,6 +55,32 @@ pub enum ResponseAction {
     DoNothing,
     Block,
     ReplaceWith(KeyCombination),
+    /// Starts the mouse action on press and ends it on release, see the
+    /// [`mouse`](crate::mouse) module.
+    Mouse(MouseAction, Action),
+    /// Blocks the event and activates the profile at this
+    /// [`position`](crate::profile::Profiles::position), see the
+    /// [`profile`](crate::profile) module.
+    SwitchProfile(usize),
+}
+
+/// Writes `nothing`, `block`, the replacement key combination or the action
+/// followed by the mouse action (`press MoveLeft`) or the position of the
+/// profile (`profile 1`).
+impl fmt::Display for ResponseAction {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DoNothing => write!(formatter, "nothing"),
+            Self::Block => write!(formatter, "block"),
+            Self::ReplaceWith(combination) => {
+                write!(formatter, "{combination}")
+            }
+            Self::Mouse(mouse_action, action) => {
+                write!(formatter, "{action} {mouse_action}")
+            }
+            Self::SwitchProfile(index) => write!(formatter, "profile {index}"),
+        }
+    }
 }
 
 /// Processes events according to the algorithm visualized in the **README**.
@@ -48,39 +89,268 @@ pub struct EventProcessor {
     switch_key: Key,
     default_combination: Option<KeyCombination>,
     mappings: collections::HashMap<KeyCombination, KeyCombination>,
+    mouse_mappings: collections::HashMap<KeyCombination, MouseAction>,
+    /// Targets of profile mappings with the position of their profile.
+    profile_mappings: collections::HashMap<KeyCombination, usize>,
+    application_mappings: Vec<ApplicationMappings>,
+    /// Position of the application mappings that matched the foreground
+    /// window when the virtual layer was activated last.
+    application: Option<usize>,
+    /// Native key codes of the raw keys the configuration reacts to, see
+    /// [`uses_raw_key`](EventProcessor::uses_raw_key).
+    raw_keys: Vec<u16>,
+    currently_pressed: Vec<Key>,
+    /// Targets of mouse mappings that are held down together with the
+    /// started mouse action which has to end once they are released.
+    mouse_keys: Vec<(Key, MouseAction)>,
+    /// Keys that were held down on the virtual layer when the configuration
+    /// was switched or processing was paused, they stay blocked until they are
+    /// released.
+    held_keys: Vec<Key>,
+    /// Detects the pause combination the same way the kill switch is
+    /// detected.
+    pause_switch: Option<KillSwitch>,
+    /// Every event is passed through while paused.
+    paused: bool,
+    block_events: bool,
+    key_combination_executed: bool,
+    statistics: Statistics,
+}
+
+/// Everything [`process`](EventProcessor::process) changes, so that the
+/// response to an event can be taken back. See
+/// [`checkpoint`](EventProcessor::checkpoint).
+pub struct Checkpoint {
     currently_pressed: Vec<Key>,
+    mouse_keys: Vec<(Key, MouseAction)>,
+    held_keys: Vec<Key>,
+    pause_switch: Option<KillSwitch>,
+    paused: bool,
     block_events: bool,
     key_combination_executed: bool,
+    statistics: Statistics,
 }
 
 /// Convenience implementation for creating an event processor with the specific
-/// configuration which will fail if the `switch_key` field is none.
+/// configuration which will fail if the `switch_key` field is none. Profile
+/// mappings are ignored, see [`with_profiles`](EventProcessor::with_profiles).
 ///
 /// # Panics
 ///
 /// Panics if the `switch_key` field of the configuration is none.
 impl From<Configuration> for EventProcessor {
     fn from(value: Configuration) -> Self {
+        Self::with_profiles(value, &Profiles::default())
+    }
+}
+
+impl EventProcessor {
+    /// Creates an event processor whose profile mappings switch to the
+    /// profiles. Profile mappings to unknown profiles are ignored, see
+    /// [`Profiles::validate`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `switch_key` field of the configuration is none.
+    pub fn with_profiles(value: Configuration, profiles: &Profiles) -> Self {
+        let profile_mappings = value
+            .profile_mappings
+            .iter()
+            .filter_map(|(target, name)| {
+                Some((*target, profiles.position(name)?))
+            })
+            .collect();
+
+        let raw_keys = value
+            .mappings
+            .keys()
+            .chain(value.mouse_mappings.keys())
+            .chain(value.profile_mappings.keys())
+            .chain(
+                value
+                    .application_mappings
+                    .iter()
+                    .flat_map(|it| it.mappings.keys()),
+            )
+            .flat_map(<[Option<Key>; 4]>::from)
+            .flatten()
+            .chain(value.switch_key)
+            .filter_map(|key| match key {
+                Key::Raw(key_code) => Some(key_code),
+                _ => None,
+            })
+            .collect();
+
         Self {
             switch_key: value
                 .switch_key
                 .expect("Switch key should be valid for an event processor."),
             default_combination: value.default_combination,
             mappings: value.mappings,
+            mouse_mappings: value.mouse_mappings,
+            profile_mappings,
+            application_mappings: value.application_mappings,
+            application: None,
+            raw_keys,
             currently_pressed: vec![],
+            mouse_keys: vec![],
+            held_keys: vec![],
+            pause_switch: value.pause_combination.map(KillSwitch::new),
+            paused: false,
             block_events: false,
             key_combination_executed: false,
+            statistics: Statistics::default(),
         }
     }
-}
 
-impl EventProcessor {
-    /// Process the event as specified in the **README**.
+    /// Usage statistics of all events processed so far.
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    /// Whether the switch key is held down and events are processed on the
+    /// virtual layer.
+    pub fn is_layer_active(&self) -> bool {
+        self.block_events
+    }
+
+    /// Keys that are currently held down on the virtual layer.
+    pub fn layer_keys(&self) -> &[Key] {
+        &self.currently_pressed
+    }
+
+    /// Whether the event of the key with the native key code has to be
+    /// processed as the [raw key](Key::Raw) even though the key produces text.
+    /// That's the case if the configuration reacts to the raw key or the raw
+    /// key is still held down.
+    pub fn uses_raw_key(&self, key_code: u16) -> bool {
+        let key = Key::Raw(key_code);
+
+        self.raw_keys.contains(&key_code)
+            || self.currently_pressed.contains(&key)
+            || self.held_keys.contains(&key)
+            || self.mouse_keys.iter().any(|(it, _)| *it == key)
+            || self
+                .pause_switch
+                .as_ref()
+                .is_some_and(|pause_switch| pause_switch.contains(key))
+    }
+
+    /// Whether the event activates the virtual layer and the application in
+    /// the foreground has to be [`set`](Self::set_application) first.
+    pub fn needs_application(&self, event: Event) -> bool {
+        !self.application_mappings.is_empty()
+            && !self.paused
+            && !self.block_events
+            && event.action == Action::Press
+            && event.key == self.switch_key
+    }
+
+    /// Uses the first application mappings that match the application until
+    /// the virtual layer is activated the next time.
+    pub fn set_application(&mut self, application: &Application) {
+        self.application = self
+            .application_mappings
+            .iter()
+            .position(|it| it.matches(application));
+    }
+
+    /// Whether every event is passed through until processing is resumed.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses or resumes processing. Keys held down on the virtual layer stay
+    /// blocked until they are released so that applications never see a
+    /// release without its press. Mouse actions still end once their target
+    /// is released.
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused != paused {
+            self.paused = paused;
+            self.hold_layer_keys();
+        }
+    }
+
+    /// Continues with the configuration of the other event processor. Keys
+    /// held down on the virtual layer stay blocked until they are released,
+    /// mouse actions still end once their target is released and the
+    /// statistics keep counting. Stays paused if it was paused and keeps the
+    /// pause combination, which is the same for every profile so that a paused
+    /// layer can always be resumed.
+    pub fn switch_to(&mut self, mut next: Self) {
+        self.hold_layer_keys();
+
+        next.statistics = mem::take(&mut self.statistics);
+        next.mouse_keys = mem::take(&mut self.mouse_keys);
+        next.held_keys = mem::take(&mut self.held_keys);
+        next.paused = self.paused;
+        next.pause_switch = self.pause_switch.take();
+
+        *self = next;
+    }
+
+    /// Saves the state that processing the next event changes.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            currently_pressed: self.currently_pressed.clone(),
+            mouse_keys: self.mouse_keys.clone(),
+            held_keys: self.held_keys.clone(),
+            pause_switch: self.pause_switch.clone(),
+            paused: self.paused,
+            block_events: self.block_events,
+            key_combination_executed: self.key_combination_executed,
+            statistics: self.statistics.clone(),
+        }
+    }
+
+    /// Goes back to the state of the checkpoint as if the events processed
+    /// since then never happened.
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        self.currently_pressed = checkpoint.currently_pressed;
+        self.mouse_keys = checkpoint.mouse_keys;
+        self.held_keys = checkpoint.held_keys;
+        self.pause_switch = checkpoint.pause_switch;
+        self.paused = checkpoint.paused;
+        self.block_events = checkpoint.block_events;
+        self.key_combination_executed = checkpoint.key_combination_executed;
+        self.statistics = checkpoint.statistics;
+    }
+
+    /// Leaves the virtual layer and keeps blocking every key that is held down
+    /// on it including the switch key.
+    fn hold_layer_keys(&mut self) {
+        self.held_keys.append(&mut self.currently_pressed);
+
+        if self.block_events {
+            self.held_keys.push(self.switch_key);
+        }
+
+        self.block_events = false;
+        self.key_combination_executed = false;
+    }
+
+    /// Process the event as specified in the **README**. While paused only
+    /// the pause combination and the releases of held keys are processed.
     #[allow(unused)]
     pub fn process(&mut self, event: Event) -> ResponseAction {
+        if self.process_pause_switch(event) || self.process_held_key(event) {
+            return ResponseAction::Block;
+        }
+
+        if self.paused {
+            return self
+                .end_mouse_action(event)
+                .unwrap_or(ResponseAction::DoNothing);
+        }
+
         match event.action {
             Action::Press => {
                 if event.key == self.switch_key {
+                    // Holding the switch key repeats the press event.
+                    if !self.block_events {
+                        self.statistics.layer_activations += 1;
+                    }
+
                     self.block_events = true;
                     self.currently_pressed.clear();
                     return ResponseAction::Block;
@@ -97,24 +367,73 @@ impl EventProcessor {
 
                 if let Ok(target_combination) = maybe_target_combination {
                     if let Some(replacement_combination) =
-                        self.mappings.get(&target_combination)
+                        self.replacement(target_combination)
                     {
                         self.key_combination_executed = true;
                         self.currently_pressed.pop();
+                        *self
+                            .statistics
+                            .mappings
+                            .entry(target_combination)
+                            .or_default() += 1;
                         return ResponseAction::ReplaceWith(
-                            *replacement_combination,
+                            replacement_combination,
                         );
                     }
+
+                    if let Some(mouse_action) =
+                        self.mouse_mappings.get(&target_combination).copied()
+                    {
+                        self.key_combination_executed = true;
+                        self.currently_pressed.pop();
+                        return self.start_mouse_action(
+                            event.key,
+                            target_combination,
+                            mouse_action,
+                        );
+                    }
+
+                    if let Some(profile) =
+                        self.profile_mappings.get(&target_combination).copied()
+                    {
+                        self.key_combination_executed = true;
+                        self.currently_pressed.pop();
+
+                        // The next profile has to keep blocking the auto
+                        // repeat and the release of the target key.
+                        if !self.held_keys.contains(&event.key) {
+                            self.held_keys.push(event.key);
+                        }
+
+                        *self
+                            .statistics
+                            .mappings
+                            .entry(target_combination)
+                            .or_default() += 1;
+                        return ResponseAction::SwitchProfile(profile);
+                    }
                 }
 
+                *self
+                    .statistics
+                    .blocked_key_presses
+                    .entry(event.key)
+                    .or_default() += 1;
+
                 ResponseAction::Block
             }
             Action::Release => {
+                // Also ends mouse actions whose switch key was released first.
+                if let Some(response) = self.end_mouse_action(event) {
+                    return response;
+                }
+
                 if event.key == self.switch_key {
                     self.block_events = false;
 
                     if !self.key_combination_executed {
                         if let Some(combination) = self.default_combination {
+                            self.statistics.default_combination_triggers += 1;
                             return ResponseAction::ReplaceWith(combination);
                         }
                     }
@@ -137,6 +456,98 @@ impl EventProcessor {
             }
         }
     }
+
+    /// Replacement of the target, the application mappings take precedence
+    /// over the regular ones.
+    fn replacement(&self, target: KeyCombination) -> Option<KeyCombination> {
+        self.application
+            .and_then(|index| {
+                self.application_mappings[index].mappings.get(&target)
+            })
+            .or_else(|| self.mappings.get(&target))
+            .copied()
+    }
+
+    /// Pauses or resumes processing if the event completes the pause
+    /// combination. Returns `true` if it did.
+    fn process_pause_switch(&mut self, event: Event) -> bool {
+        if !self
+            .pause_switch
+            .as_mut()
+            .is_some_and(|pause_switch| pause_switch.process(event))
+        {
+            return false;
+        }
+
+        self.set_paused(!self.paused);
+
+        // The release of the last key has to be blocked as well.
+        if !self.held_keys.contains(&event.key) {
+            self.held_keys.push(event.key);
+        }
+
+        true
+    }
+
+    /// Keeps blocking the keys that were held down when the configuration was
+    /// switched or processing was paused until they are released. Returns
+    /// `true` for those keys.
+    fn process_held_key(&mut self, event: Event) -> bool {
+        let Some(index) =
+            self.held_keys.iter().position(|key| *key == event.key)
+        else {
+            return false;
+        };
+
+        // Auto repeat keeps pressing the key until it's released.
+        if event.action == Action::Release {
+            self.held_keys.swap_remove(index);
+        }
+
+        true
+    }
+
+    /// Ends the mouse action if the event releases its target.
+    fn end_mouse_action(&mut self, event: Event) -> Option<ResponseAction> {
+        if event.action != Action::Release {
+            return None;
+        }
+
+        let index = self
+            .mouse_keys
+            .iter()
+            .position(|(key, _)| *key == event.key)?;
+        let (_, mouse_action) = self.mouse_keys.swap_remove(index);
+
+        Some(ResponseAction::Mouse(mouse_action, Action::Release))
+    }
+
+    /// Starts the mouse action unless auto repeat of the target would repeat
+    /// an action that only happens once per press.
+    fn start_mouse_action(
+        &mut self,
+        key: Key,
+        target_combination: KeyCombination,
+        mouse_action: MouseAction,
+    ) -> ResponseAction {
+        let repeated = self.mouse_keys.iter().any(|(it, _)| *it == key);
+
+        if repeated && !mouse_action.repeats() {
+            return ResponseAction::Block;
+        }
+
+        if !repeated {
+            self.mouse_keys.push((key, mouse_action));
+        }
+
+        *self
+            .statistics
+            .mappings
+            .entry(target_combination)
+            .or_default() += 1;
+
+        ResponseAction::Mouse(mouse_action, Action::Press)
+    }
 }
 
 #[cfg(test)]
@@ -203,5 +614,207 @@ mod tests {
         test_event!(Action::Press, 't', ResponseAction::ReplaceWith(kc!('a')));
 
         test_event!(Action::Release, switch_key, ResponseAction::Block);
+
+        let statistics = event_processor.statistics();
+
+        assert_eq!(2, statistics.layer_activations);
+        assert_eq!(1, statistics.default_combination_triggers);
+        assert_eq!(Some(&1), statistics.mappings.get(&kc!('t')));
+        assert_eq!(Some(&1), statistics.blocked_key_presses.get(&'a'.into()));
+    }
+
+    #[test]
+    fn test_switch_profile() {
+        let press = |key: Key| Event {
+            action: Action::Press,
+            key,
+        };
+        let release = |key: Key| Event {
+            action: Action::Release,
+            key,
+        };
+
+        let caps_lock = Key::Virtual(VirtualKey::CapsLock);
+        let tab = Key::Virtual(VirtualKey::Tab);
+
+        let mut profiles = Profiles::default();
+        let mut coding = Configuration {
+            switch_key: Some(caps_lock),
+            pause_combination: Some("LControl+p".parse().unwrap()),
+            ..Configuration::default()
+        };
+        coding
+            .profile_mappings
+            .insert("LAlt+2".parse().unwrap(), "gaming".to_owned());
+        profiles.save("coding", coding.clone());
+        profiles.save(
+            "gaming",
+            Configuration {
+                switch_key: Some(tab),
+                ..Configuration::default()
+            },
+        );
+
+        let mut event_processor =
+            EventProcessor::with_profiles(coding, &profiles);
+
+        assert_eq!(
+            ResponseAction::Block,
+            event_processor.process(press(caps_lock))
+        );
+        assert_eq!(
+            ResponseAction::Block,
+            event_processor.process(press(VirtualKey::LAlt.into()))
+        );
+        assert_eq!(
+            ResponseAction::SwitchProfile(1),
+            event_processor.process(press('2'.into()))
+        );
+
+        let gaming = profiles.activate_at(1).unwrap().clone();
+        event_processor
+            .switch_to(EventProcessor::with_profiles(gaming, &profiles));
+
+        // Keys held down before the switch stay blocked until released.
+        assert_eq!(
+            ResponseAction::Block,
+            event_processor.process(press(caps_lock))
+        );
+        assert_eq!(
+            ResponseAction::Block,
+            event_processor.process(press('2'.into()))
+        );
+        assert_eq!(
+            ResponseAction::Block,
+            event_processor.process(release('2'.into()))
+        );
+        assert_eq!(
+            ResponseAction::DoNothing,
+            event_processor.process(press('2'.into()))
+        );
+        assert_eq!(
+            ResponseAction::Block,
+            event_processor.process(release(VirtualKey::LAlt.into()))
+        );
+        assert_eq!(
+            ResponseAction::Block,
+            event_processor.process(release(caps_lock))
+        );
+        assert_eq!(
+            ResponseAction::DoNothing,
+            event_processor.process(press(caps_lock))
+        );
+
+        assert_eq!(ResponseAction::Block, event_processor.process(press(tab)));
+        assert_eq!(
+            Some(&1),
+            event_processor
+                .statistics()
+                .mappings
+                .get(&"LAlt+2".parse().unwrap())
+        );
+
+        // The gaming profile has no pause combination but the one the layer
+        // started with is kept.
+        assert_eq!(
+            ResponseAction::Block,
+            event_processor.process(release(tab))
+        );
+        assert_eq!(
+            ResponseAction::DoNothing,
+            event_processor.process(press(VirtualKey::LControl.into()))
+        );
+        assert_eq!(
+            ResponseAction::Block,
+            event_processor.process(press('p'.into()))
+        );
+        assert!(event_processor.is_paused());
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let mut event_processor: EventProcessor = Configuration {
+            switch_key: Some(VirtualKey::CapsLock.into()),
+            ..Configuration::default()
+        }
+        .into();
+
+        let checkpoint = event_processor.checkpoint();
+
+        assert_eq!(
+            ResponseAction::Block,
+            event_processor.process(Event {
+                action: Action::Press,
+                key: VirtualKey::CapsLock.into(),
+            })
+        );
+        assert!(event_processor.is_layer_active());
+
+        event_processor.restore(checkpoint);
+
+        assert!(!event_processor.is_layer_active());
+        assert_eq!(0, event_processor.statistics().layer_activations);
+    }
+
+    #[test]
+    fn test_application_mappings() {
+        let caps_lock = Key::from(VirtualKey::CapsLock);
+        let press = |key| Event {
+            action: Action::Press,
+            key,
+        };
+        let release = |key| Event {
+            action: Action::Release,
+            key,
+        };
+        let replacement =
+            |raw: &str| ResponseAction::ReplaceWith(raw.parse().unwrap());
+
+        let mut event_processor: EventProcessor = Configuration {
+            switch_key: Some(caps_lock),
+            mappings: collections::HashMap::from([
+                ("h".parse().unwrap(), "LeftArrow".parse().unwrap()),
+                ("l".parse().unwrap(), "RightArrow".parse().unwrap()),
+            ]),
+            application_mappings: vec![ApplicationMappings {
+                window_class: Some("Notepad".to_owned()),
+                window_title: None,
+                mappings: collections::HashMap::from([(
+                    "h".parse().unwrap(),
+                    "Home".parse().unwrap(),
+                )]),
+            }],
+            ..Configuration::default()
+        }
+        .into();
+
+        let notepad = Application {
+            class: "Notepad".to_owned(),
+            title: "akl.txt - Editor".to_owned(),
+        };
+
+        assert!(!event_processor.needs_application(press('h'.into())));
+        assert!(event_processor.needs_application(press(caps_lock)));
+        event_processor.set_application(&notepad);
+        event_processor.process(press(caps_lock));
+
+        // The auto repeat of the switch key keeps the mappings.
+        assert!(!event_processor.needs_application(press(caps_lock)));
+        assert_eq!(
+            replacement("Home"),
+            event_processor.process(press('h'.into()))
+        );
+        assert_eq!(
+            replacement("RightArrow"),
+            event_processor.process(press('l'.into()))
+        );
+        event_processor.process(release(caps_lock));
+
+        event_processor.set_application(&Application::default());
+        event_processor.process(press(caps_lock));
+        assert_eq!(
+            replacement("LeftArrow"),
+            event_processor.process(press('h'.into()))
+        );
     }
 }
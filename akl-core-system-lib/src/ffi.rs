@@ -9,7 +9,20 @@
 #![allow(dead_code)]
 
 use crate::{
-    key::Key, key::KeyCombination, key::VirtualKey, AnotherKeyboardLayer,
+    application::ApplicationMappings,
+    dispatch::DisableReason,
+    event::{Action, Event, ResponseAction},
+    key::Key,
+    key::KeyCombination,
+    key::TextOutput,
+    key::VirtualKey,
+    kill_switch,
+    latency::LatencySummary,
+    logging::{self, LogSink, LoggingConfiguration},
+    mouse::{Direction, MouseAction, MouseButton},
+    observer::{Notification, Observer},
+    scenario::Scenario,
+    AnotherKeyboardLayer,
 };
 
 /// Pointer type for methods that require an instance of
@@ -40,6 +53,24 @@ fn akl_from_raw<'arbitrary>(
     }
 }
 
+/// Decodes the utf-8 encoded string the c# side passed as a pointer and its
+/// length in bytes. `None` if it's a null pointer or isn't valid utf-8.
+///
+/// # Safety
+///
+/// Same as for [`akl_from_raw`], the string is only valid for as long as the
+/// memory behind the pointer is.
+fn str_from_raw<'arbitrary>(
+    raw: *const u8,
+    length: usize,
+) -> Option<&'arbitrary str> {
+    if raw.is_null() {
+        return None;
+    }
+
+    std::str::from_utf8(unsafe { std::slice::from_raw_parts(raw, length) }).ok()
+}
+
 /// A ffi safe representation of a [`key`](crate::Key) which is used to transfer
 /// from the c# key type safely.
 #[repr(C)]
@@ -49,6 +80,8 @@ pub struct FfiKey {
     /// Representation of a virtual key that is shared between c# and rust.
     /// The enum definition has to be kept the same so that this doesn't break.
     named: u8,
+    /// Native virtual key code of a [raw key](Key::Raw).
+    raw: u16,
     /// Marks the type of key this instance represents.
     kind: FfiKeyKind,
 }
@@ -67,6 +100,9 @@ pub enum FfiKeyKind {
     /// safely transferring [key combinations](crate::KeyCombination) with less
     /// than four keys from c#.
     None,
+    /// Any other key identified by its native virtual key code, it can also be
+    /// a key that produces text.
+    Raw,
 }
 
 impl TryFrom<FfiKey> for Key {
@@ -92,11 +128,51 @@ impl TryFrom<FfiKey> for Key {
 
                 Err(())
             }
+            FfiKeyKind::Raw => Ok(Self::Raw(value.raw)),
             FfiKeyKind::None => Err(()),
         }
     }
 }
 
+impl From<Key> for FfiKey {
+    fn from(value: Key) -> Self {
+        match value {
+            Key::Text(character) => Self {
+                text: character.into(),
+                named: 0,
+                raw: 0,
+                kind: FfiKeyKind::Text,
+            },
+            Key::Virtual(virtual_key) => Self {
+                text: 0,
+                named: virtual_key as u8,
+                raw: 0,
+                kind: FfiKeyKind::Virtual,
+            },
+            Key::Raw(code) => Self {
+                text: 0,
+                named: 0,
+                raw: code,
+                kind: FfiKeyKind::Raw,
+            },
+        }
+    }
+}
+
+impl From<Option<Key>> for FfiKey {
+    fn from(value: Option<Key>) -> Self {
+        value.map_or(
+            Self {
+                text: 0,
+                named: 0,
+                raw: 0,
+                kind: FfiKeyKind::None,
+            },
+            Into::into,
+        )
+    }
+}
+
 /// Ffi save representation of a [key combination](crate::KeyCombination) which
 /// uses the [`FfiKeyKind::None`] variant to represent an undefined / missing
 /// key.
@@ -132,6 +208,198 @@ impl TryFrom<FfiKeyCombination> for KeyCombination {
     }
 }
 
+impl From<KeyCombination> for FfiKeyCombination {
+    fn from(value: KeyCombination) -> Self {
+        let [first, second, third, fourth]: [Option<Key>; 4] = (&value).into();
+
+        Self(first.into(), second.into(), third.into(), fourth.into())
+    }
+}
+
+/// Ffi safe representation of an [event](crate::event::Event) which is passed
+/// to the observer.
+#[repr(C)]
+pub struct FfiEvent {
+    action: FfiAction,
+    key: FfiKey,
+}
+
+/// Mirrors [`Action`](crate::event::Action).
+#[repr(u8)]
+pub enum FfiAction {
+    Press,
+    Release,
+}
+
+impl From<Event> for FfiEvent {
+    fn from(value: Event) -> Self {
+        Self {
+            action: match value.action {
+                Action::Press => FfiAction::Press,
+                Action::Release => FfiAction::Release,
+            },
+            key: value.key.into(),
+        }
+    }
+}
+
+/// Ffi safe representation of a [response action](crate::event::ResponseAction)
+/// which is passed to the observer. The replacement only contains keys if the
+/// kind is [`ReplaceWith`](FfiResponseActionKind::ReplaceWith) and the mouse
+/// action is only set for the mouse kinds. Use [`get_active_profile`] to find
+/// out which profile the layer switched to.
+#[repr(C)]
+pub struct FfiResponseAction {
+    kind: FfiResponseActionKind,
+    replacement: FfiKeyCombination,
+    mouse_action: FfiMouseAction,
+}
+
+/// Indicates the type of response stored in [`FfiResponseAction`].
+#[repr(u8)]
+pub enum FfiResponseActionKind {
+    DoNothing,
+    Block,
+    ReplaceWith,
+    MousePress,
+    MouseRelease,
+    SwitchProfile,
+}
+
+/// Mirrors [`MouseAction`] with the same names the configuration file uses.
+#[repr(u8)]
+#[derive(Clone, Copy)]
+pub enum FfiMouseAction {
+    None,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    ClickLeft,
+    ClickMiddle,
+    ClickRight,
+    ScrollUp,
+    ScrollDown,
+    ScrollLeft,
+    ScrollRight,
+}
+
+impl From<MouseAction> for FfiMouseAction {
+    fn from(value: MouseAction) -> Self {
+        match value {
+            MouseAction::Move(Direction::Up) => Self::MoveUp,
+            MouseAction::Move(Direction::Down) => Self::MoveDown,
+            MouseAction::Move(Direction::Left) => Self::MoveLeft,
+            MouseAction::Move(Direction::Right) => Self::MoveRight,
+            MouseAction::Click(MouseButton::Left) => Self::ClickLeft,
+            MouseAction::Click(MouseButton::Middle) => Self::ClickMiddle,
+            MouseAction::Click(MouseButton::Right) => Self::ClickRight,
+            MouseAction::Scroll(Direction::Up) => Self::ScrollUp,
+            MouseAction::Scroll(Direction::Down) => Self::ScrollDown,
+            MouseAction::Scroll(Direction::Left) => Self::ScrollLeft,
+            MouseAction::Scroll(Direction::Right) => Self::ScrollRight,
+        }
+    }
+}
+
+/// Fails for [`None`](FfiMouseAction::None).
+impl TryFrom<FfiMouseAction> for MouseAction {
+    type Error = ();
+
+    fn try_from(value: FfiMouseAction) -> Result<Self, Self::Error> {
+        Ok(match value {
+            FfiMouseAction::None => return Err(()),
+            FfiMouseAction::MoveUp => Self::Move(Direction::Up),
+            FfiMouseAction::MoveDown => Self::Move(Direction::Down),
+            FfiMouseAction::MoveLeft => Self::Move(Direction::Left),
+            FfiMouseAction::MoveRight => Self::Move(Direction::Right),
+            FfiMouseAction::ClickLeft => Self::Click(MouseButton::Left),
+            FfiMouseAction::ClickMiddle => Self::Click(MouseButton::Middle),
+            FfiMouseAction::ClickRight => Self::Click(MouseButton::Right),
+            FfiMouseAction::ScrollUp => Self::Scroll(Direction::Up),
+            FfiMouseAction::ScrollDown => Self::Scroll(Direction::Down),
+            FfiMouseAction::ScrollLeft => Self::Scroll(Direction::Left),
+            FfiMouseAction::ScrollRight => Self::Scroll(Direction::Right),
+        })
+    }
+}
+
+impl From<ResponseAction> for FfiResponseAction {
+    fn from(value: ResponseAction) -> Self {
+        let (kind, replacement, mouse_action) = match value {
+            ResponseAction::DoNothing => {
+                (FfiResponseActionKind::DoNothing, None, FfiMouseAction::None)
+            }
+            ResponseAction::Block => {
+                (FfiResponseActionKind::Block, None, FfiMouseAction::None)
+            }
+            ResponseAction::ReplaceWith(combination) => (
+                FfiResponseActionKind::ReplaceWith,
+                Some(combination),
+                FfiMouseAction::None,
+            ),
+            ResponseAction::Mouse(mouse_action, Action::Press) => {
+                (FfiResponseActionKind::MousePress, None, mouse_action.into())
+            }
+            ResponseAction::Mouse(mouse_action, Action::Release) => (
+                FfiResponseActionKind::MouseRelease,
+                None,
+                mouse_action.into(),
+            ),
+            ResponseAction::SwitchProfile(_) => (
+                FfiResponseActionKind::SwitchProfile,
+                None,
+                FfiMouseAction::None,
+            ),
+        };
+
+        Self {
+            kind,
+            mouse_action,
+            replacement: replacement.map_or(
+                FfiKeyCombination(
+                    None.into(),
+                    None.into(),
+                    None.into(),
+                    None.into(),
+                ),
+                Into::into,
+            ),
+        }
+    }
+}
+
+/// Ffi safe representation of the [reason](DisableReason) why the virtual
+/// layer disabled itself which is passed to the observer. The overruns are
+/// only set for the [`Watchdog`](FfiDisableReasonKind::Watchdog) kind.
+#[repr(C)]
+pub struct FfiDisableReason {
+    kind: FfiDisableReasonKind,
+    overruns: u32,
+}
+
+/// Indicates the type of reason stored in [`FfiDisableReason`].
+#[repr(u8)]
+pub enum FfiDisableReasonKind {
+    KillSwitch,
+    Watchdog,
+}
+
+impl From<DisableReason> for FfiDisableReason {
+    fn from(value: DisableReason) -> Self {
+        match value {
+            DisableReason::KillSwitch => Self {
+                kind: FfiDisableReasonKind::KillSwitch,
+                overruns: 0,
+            },
+            DisableReason::Watchdog { overruns } => Self {
+                kind: FfiDisableReasonKind::Watchdog,
+                overruns,
+            },
+        }
+    }
+}
+
 /// Ffi save result type that contains an error message as a cstring if the
 /// `has_error` field is set to true.
 #[repr(C)]
@@ -245,6 +513,23 @@ pub extern "C" fn is_running(raw_context: *mut AklContext) -> bool {
     }
 }
 
+/// Fails if the virtual layer disabled itself because processing events took
+/// too long too often in a row. See `AnotherKeyboardLayer::check_health`.
+#[no_mangle]
+pub extern "C" fn check_health(raw_context: *mut AklContext) -> FfiResult {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return FfiResult::error(
+            "Can't operate on a null pointer. See init method",
+        );
+    };
+
+    if let Err(error) = akl.check_health() {
+        return FfiResult::error(&error.to_string());
+    }
+
+    FfiResult::ok()
+}
+
 /// Tries to set the switch key. Fails if the key [kind](FfiKeyKind) is `None`.
 #[no_mangle]
 pub extern "C" fn set_switch_key(
@@ -292,6 +577,90 @@ pub extern "C" fn set_default_combination(
     }
 }
 
+/// Sets the combination that pauses and resumes the virtual layer. A key
+/// combination with all keys set to [None](FfiKeyKind::None) means no pause
+/// combination.
+#[no_mangle]
+pub extern "C" fn set_pause_combination(
+    raw_context: *mut AklContext,
+    key_combination: FfiKeyCombination,
+) {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return;
+    };
+
+    akl.configuration.pause_combination = key_combination.try_into().ok();
+}
+
+/// Pauses or resumes the virtual layer, right away if it is running. See
+/// [`set_paused`](crate::AnotherKeyboardLayer::set_paused)-method of
+/// `AnotherKeyboardLayer`.
+#[no_mangle]
+pub extern "C" fn set_paused(raw_context: *mut AklContext, paused: bool) {
+    if let Some(akl) = akl_from_raw(raw_context) {
+        akl.set_paused(paused);
+    }
+}
+
+/// Check if the virtual layer is paused, it might have been toggled with the
+/// pause combination.
+#[no_mangle]
+pub extern "C" fn is_paused(raw_context: *mut AklContext) -> bool {
+    akl_from_raw(raw_context).is_some_and(|akl| akl.is_paused())
+}
+
+/// Sets the combination that immediately disables the virtual layer. A key
+/// combination with all keys set to [None](FfiKeyKind::None) restores the
+/// default `LControl+LAlt+LShift+Escape`. Takes effect on the next start.
+#[no_mangle]
+pub extern "C" fn set_kill_switch(
+    raw_context: *mut AklContext,
+    key_combination: FfiKeyCombination,
+) {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return;
+    };
+
+    akl.kill_switch = key_combination
+        .try_into()
+        .unwrap_or_else(|()| kill_switch::default_combination());
+}
+
+/// Sets the priority that decides which of several running contexts sees an
+/// event first, higher values come first. Takes effect on the next start.
+#[no_mangle]
+pub extern "C" fn set_priority(raw_context: *mut AklContext, priority: i32) {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return;
+    };
+
+    akl.priority = priority;
+}
+
+/// Mirrors [`TextOutput`].
+#[repr(u8)]
+pub enum FfiTextOutput {
+    Unicode,
+    Layout,
+}
+
+/// Sets how replacements simulate text keys. Doesn't update the currently
+/// running layer.
+#[no_mangle]
+pub extern "C" fn set_text_output(
+    raw_context: *mut AklContext,
+    text_output: FfiTextOutput,
+) {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return;
+    };
+
+    akl.configuration.text_output = match text_output {
+        FfiTextOutput::Unicode => TextOutput::Unicode,
+        FfiTextOutput::Layout => TextOutput::Layout,
+    };
+}
+
 /// Adds a mapping or overrides it if it is already targeted. Can fail if any
 /// of the key combinations are invalid.
 #[no_mangle]
@@ -324,14 +693,157 @@ pub extern "C" fn add_mapping(
         (target.unwrap(), replacement.unwrap())
     };
 
+    let _ = akl.configuration.mouse_mappings.remove(&target);
+    let _ = akl.configuration.profile_mappings.remove(&target);
     let _ = akl.configuration.mappings.insert(target, replacement);
 
     FfiResult::ok()
 }
 
-/// Removes the mapping with the specified target. Regardless of if the key
-/// combination is valid only a return value of `true` means that a combination
-/// was removed.
+/// Adds a mapping whose target starts the mouse action or overrides it if the
+/// target is already used. Can fail if the target key combination is invalid
+/// or the mouse action is [`None`](FfiMouseAction::None).
+#[no_mangle]
+pub extern "C" fn add_mouse_mapping(
+    raw_context: *mut AklContext,
+    target: FfiKeyCombination,
+    mouse_action: FfiMouseAction,
+) -> FfiResult {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return FfiResult::error(
+            "Can't operate on a null pointer. See init method",
+        );
+    };
+
+    let Ok(target) = KeyCombination::try_from(target) else {
+        return FfiResult::error("The target key combination is invalid.");
+    };
+
+    let Ok(mouse_action) = MouseAction::try_from(mouse_action) else {
+        return FfiResult::error("The mouse action can't be none.");
+    };
+
+    let _ = akl.configuration.mappings.remove(&target);
+    let _ = akl.configuration.profile_mappings.remove(&target);
+    let _ = akl
+        .configuration
+        .mouse_mappings
+        .insert(target, mouse_action);
+
+    FfiResult::ok()
+}
+
+/// Adds a mapping whose target switches to the utf-8 encoded profile or
+/// overrides it if the target is already used. Can fail if the target key
+/// combination or the name is invalid. Whether the profile exists is checked
+/// once the virtual layer starts.
+#[no_mangle]
+pub extern "C" fn add_profile_mapping(
+    raw_context: *mut AklContext,
+    target: FfiKeyCombination,
+    profile: *const u8,
+    profile_length: usize,
+) -> FfiResult {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return FfiResult::error(
+            "Can't operate on a null pointer. See init method",
+        );
+    };
+
+    let Ok(target) = KeyCombination::try_from(target) else {
+        return FfiResult::error("The target key combination is invalid.");
+    };
+
+    let Some(profile) = str_from_raw(profile, profile_length) else {
+        return FfiResult::error("The profile isn't valid utf-8.");
+    };
+
+    let _ = akl.configuration.mappings.remove(&target);
+    let _ = akl.configuration.mouse_mappings.remove(&target);
+    let _ = akl
+        .configuration
+        .profile_mappings
+        .insert(target, profile.to_owned());
+
+    FfiResult::ok()
+}
+
+/// Adds a mapping to the [application mappings](crate::application) for the
+/// utf-8 encoded window class and title, or overrides it if the target is
+/// already mapped there. An empty class or title, which can also be a null
+/// pointer, matches every window. The
+/// mappings for a class and title are created the first time one is added,
+/// earlier ones are matched first. Can fail if any of the key combinations
+/// are invalid.
+#[no_mangle]
+pub extern "C" fn add_application_mapping(
+    raw_context: *mut AklContext,
+    window_class: *const u8,
+    window_class_length: usize,
+    window_title: *const u8,
+    window_title_length: usize,
+    target: FfiKeyCombination,
+    replacement: FfiKeyCombination,
+) -> FfiResult {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return FfiResult::error(
+            "Can't operate on a null pointer. See init method",
+        );
+    };
+
+    // C# passes a null pointer for empty arrays.
+    let text = |raw, length| match length {
+        0 => Some(""),
+        _ => str_from_raw(raw, length),
+    };
+
+    let (Some(window_class), Some(window_title)) = (
+        text(window_class, window_class_length),
+        text(window_title, window_title_length),
+    ) else {
+        return FfiResult::error(
+            "The window class or title isn't valid utf-8.",
+        );
+    };
+
+    let Ok(target) = KeyCombination::try_from(target) else {
+        return FfiResult::error("The target key combination is invalid.");
+    };
+
+    let Ok(replacement) = KeyCombination::try_from(replacement) else {
+        return FfiResult::error("The replacement key combination is invalid.");
+    };
+
+    let non_empty = |raw: &str| (!raw.is_empty()).then(|| raw.to_owned());
+    let window_class = non_empty(window_class);
+    let window_title = non_empty(window_title);
+
+    let application_mappings = &mut akl.configuration.application_mappings;
+    let index = application_mappings
+        .iter()
+        .position(|it| {
+            it.window_class == window_class && it.window_title == window_title
+        })
+        .unwrap_or_else(|| {
+            application_mappings.push(ApplicationMappings {
+                window_class,
+                window_title,
+                ..ApplicationMappings::default()
+            });
+
+            application_mappings.len() - 1
+        });
+
+    let _ = application_mappings[index]
+        .mappings
+        .insert(target, replacement);
+
+    FfiResult::ok()
+}
+
+/// Removes the mapping or mouse mapping with the specified target. Regardless
+/// of if the key combination is valid only a return value of `true` means that
+/// a combination was removed.
 #[no_mangle]
 pub extern "C" fn remove_mapping(
     raw_context: *mut AklContext,
@@ -352,14 +864,494 @@ pub extern "C" fn remove_mapping(
     };
 
     let previous = akl.configuration.mappings.remove(&target);
+    let previous_mouse = akl.configuration.mouse_mappings.remove(&target);
+    let previous_profile = akl.configuration.profile_mappings.remove(&target);
 
-    previous.is_some()
+    previous.is_some() || previous_mouse.is_some() || previous_profile.is_some()
 }
 
-/// Clears all mappings. Doesn't update the currently running layer.
+/// Clears all mappings including the mouse, profile and application mappings.
+/// Doesn't update the currently running layer.
 #[no_mangle]
 pub extern "C" fn clear_mappings(raw_context: *mut AklContext) {
     if let Some(akl) = akl_from_raw(raw_context) {
         akl.configuration.mappings.clear();
+        akl.configuration.mouse_mappings.clear();
+        akl.configuration.profile_mappings.clear();
+        akl.configuration.application_mappings.clear();
     }
 }
+
+/// Saves a copy of the current configuration as the utf-8 encoded
+/// [profile](crate::profile), replacing the profile with the same name. The
+/// running layer only knows the profiles that existed when it was started.
+#[no_mangle]
+pub extern "C" fn save_profile(
+    raw_context: *mut AklContext,
+    name: *const u8,
+    name_length: usize,
+) -> FfiResult {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return FfiResult::error(
+            "Can't operate on a null pointer. See init method",
+        );
+    };
+
+    let Some(name) = str_from_raw(name, name_length) else {
+        return FfiResult::error("The profile name isn't valid utf-8.");
+    };
+
+    akl.profiles.save(name, akl.configuration.clone());
+
+    FfiResult::ok()
+}
+
+/// Removes all profiles. Doesn't update the currently running layer.
+#[no_mangle]
+pub extern "C" fn clear_profiles(raw_context: *mut AklContext) {
+    if let Some(akl) = akl_from_raw(raw_context) {
+        akl.profiles.clear();
+    }
+}
+
+/// Makes the utf-8 encoded profile the current configuration and switches the
+/// running layer to it. See
+/// [`switch_profile`](crate::AnotherKeyboardLayer::switch_profile)-method of
+/// `AnotherKeyboardLayer`.
+#[no_mangle]
+pub extern "C" fn switch_profile(
+    raw_context: *mut AklContext,
+    name: *const u8,
+    name_length: usize,
+) -> FfiResult {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return FfiResult::error(
+            "Can't operate on a null pointer. See init method",
+        );
+    };
+
+    let Some(name) = str_from_raw(name, name_length) else {
+        return FfiResult::error("The profile name isn't valid utf-8.");
+    };
+
+    if let Err(error) = akl.switch_profile(name) {
+        return FfiResult::error(&error.to_string());
+    }
+
+    FfiResult::ok()
+}
+
+/// Writes the utf-8 encoded name of the active profile to the buffer if it is
+/// large enough and returns its length in bytes. Call it with a null pointer
+/// first to get the needed size. Returns zero if no profile is active.
+#[no_mangle]
+pub extern "C" fn get_active_profile(
+    raw_context: *mut AklContext,
+    buffer: *mut u8,
+    buffer_length: usize,
+) -> usize {
+    let Some(name) =
+        akl_from_raw(raw_context).and_then(|akl| akl.active_profile())
+    else {
+        return 0;
+    };
+
+    if !buffer.is_null() && name.len() <= buffer_length {
+        unsafe {
+            std::ptr::copy_nonoverlapping(name.as_ptr(), buffer, name.len());
+        }
+    }
+
+    name.len()
+}
+
+/// Parses the utf-8 encoded name of a virtual key, one of its aliases or a
+/// single character and writes the result to `key`. This way the configuration
+/// accepts the same keys as the rest of the library.
+///
+/// The error message suggests similar names if the key is unknown.
+#[no_mangle]
+pub extern "C" fn parse_key(
+    raw: *const u8,
+    raw_length: usize,
+    key: *mut FfiKey,
+) -> FfiResult {
+    if raw.is_null() || key.is_null() {
+        return FfiResult::error("Can't operate on a null pointer.");
+    }
+
+    let bytes = unsafe { std::slice::from_raw_parts(raw, raw_length) };
+
+    let Ok(raw_key) = std::str::from_utf8(bytes) else {
+        return FfiResult::error("The key isn't valid utf-8.");
+    };
+
+    match raw_key.parse::<Key>() {
+        Ok(parsed) => {
+            unsafe { key.write(parsed.into()) };
+            FfiResult::ok()
+        }
+        Err(error) => FfiResult::error(&error.to_string()),
+    }
+}
+
+/// Reads the configuration file at the utf-8 encoded path together with every
+/// file it includes, see [compose](crate::compose). The composed configuration
+/// is written to the buffer as toml if it is large enough and its length in
+/// bytes to `length`. Call it with a null pointer first to get the needed size.
+///
+/// The error message names the file and line of conflicting mappings.
+#[cfg(feature = "config-serde")]
+#[no_mangle]
+pub extern "C" fn compose_configuration(
+    path: *const u8,
+    path_length: usize,
+    buffer: *mut u8,
+    buffer_length: usize,
+    length: *mut usize,
+) -> FfiResult {
+    if length.is_null() {
+        return FfiResult::error("Can't operate on a null pointer.");
+    }
+
+    let Some(path) = str_from_raw(path, path_length) else {
+        return FfiResult::error("The path isn't valid utf-8.");
+    };
+
+    let composed = crate::compose::load(std::path::Path::new(path))
+        .map_err(|error| error.to_string())
+        .and_then(|file| {
+            crate::config_format::ConfigFormat::Toml
+                .write_file(&file)
+                .map_err(|error| error.to_string())
+        });
+
+    let composed = match composed {
+        Ok(composed) => composed,
+        Err(error) => return FfiResult::error(&error),
+    };
+
+    if !buffer.is_null() && composed.len() <= buffer_length {
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                composed.as_ptr(),
+                buffer,
+                composed.len(),
+            );
+        }
+    }
+
+    unsafe { length.write(composed.len()) };
+
+    FfiResult::ok()
+}
+
+/// Runs the utf-8 encoded [scenario](crate::scenario) against a fresh event
+/// processor. Until the scenario contains a `config:` line the current
+/// configuration of the context is used. Doesn't affect the running layer.
+///
+/// The error message lists every line whose response didn't match.
+#[no_mangle]
+pub extern "C" fn run_scenario(
+    raw_context: *mut AklContext,
+    scenario: *const u8,
+    scenario_length: usize,
+) -> FfiResult {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return FfiResult::error(
+            "Can't operate on a null pointer. See init method",
+        );
+    };
+
+    if scenario.is_null() {
+        return FfiResult::error("The scenario can't be a null pointer.");
+    }
+
+    let raw_scenario = {
+        let bytes =
+            unsafe { std::slice::from_raw_parts(scenario, scenario_length) };
+
+        let Ok(raw_scenario) = std::str::from_utf8(bytes) else {
+            return FfiResult::error("The scenario isn't valid utf-8.");
+        };
+
+        raw_scenario
+    };
+
+    let result = raw_scenario
+        .parse::<Scenario>()
+        .and_then(|scenario| scenario.run(&akl.configuration));
+
+    if let Err(error) = result {
+        return FfiResult::error(&error.to_string());
+    }
+
+    FfiResult::ok()
+}
+
+/// Sets the callback that gets called with every event processed by the
+/// virtual layer and the response to it and the callback that gets called with
+/// the context and the reason once the virtual layer disabled itself. Passing
+/// null removes a callback. Fails if the virtual layer is running.
+///
+/// The callbacks are never called from the keyboard hook itself but from a
+/// separate thread. If it can't keep up, events are dropped instead of delaying
+/// any input. Disabling is always reported.
+#[no_mangle]
+pub extern "C" fn set_observer(
+    raw_context: *mut AklContext,
+    callback: Option<extern "C" fn(FfiEvent, FfiResponseAction)>,
+    disabled_callback: Option<extern "C" fn(*mut AklContext, FfiDisableReason)>,
+) -> FfiResult {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return FfiResult::error(
+            "Can't operate on a null pointer. See init method",
+        );
+    };
+
+    // Raw pointers can't be sent to the delivery thread, the context is only
+    // passed back as an identifier anyway.
+    let context_address = raw_context as usize;
+
+    let observer =
+        (callback.is_some() || disabled_callback.is_some()).then(|| {
+            Observer::new(move |notification| match notification {
+                Notification::Processed(event, response) => {
+                    if let Some(callback) = callback {
+                        callback(event.into(), response.into());
+                    }
+                }
+                Notification::Disabled(reason) => {
+                    if let Some(disabled_callback) = disabled_callback {
+                        disabled_callback(
+                            context_address as *mut AklContext,
+                            reason.into(),
+                        );
+                    }
+                }
+            })
+        });
+
+    if let Err(error) = akl.set_observer(observer) {
+        return FfiResult::error(&error.to_string());
+    }
+
+    FfiResult::ok()
+}
+
+/// Ffi safe snapshot of the totals of the [usage statistics](crate::statistics::Statistics).
+/// Use [`get_mapping_usage`] and [`get_blocked_key_presses`] for the counters
+/// of a specific mapping or key.
+#[repr(C)]
+pub struct FfiStatistics {
+    layer_activations: u64,
+    default_combination_triggers: u64,
+    mapping_triggers: u64,
+    blocked_key_presses: u64,
+}
+
+/// Returns the totals of the usage statistics over all runs. All counters are
+/// zero if the context is a null pointer.
+#[no_mangle]
+pub extern "C" fn get_statistics(
+    raw_context: *mut AklContext,
+) -> FfiStatistics {
+    let statistics = akl_from_raw(raw_context)
+        .map(|akl| akl.statistics())
+        .unwrap_or_default();
+
+    FfiStatistics {
+        layer_activations: statistics.layer_activations,
+        default_combination_triggers: statistics.default_combination_triggers,
+        mapping_triggers: statistics.total_mapping_triggers(),
+        blocked_key_presses: statistics.total_blocked_key_presses(),
+    }
+}
+
+/// Returns how often the mapping with the specified target fired. Invalid key
+/// combinations were never used so zero is returned for them.
+#[no_mangle]
+pub extern "C" fn get_mapping_usage(
+    raw_context: *mut AklContext,
+    target: FfiKeyCombination,
+) -> u64 {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return 0;
+    };
+
+    let Ok(target) = KeyCombination::try_from(target) else {
+        return 0;
+    };
+
+    akl.statistics()
+        .mappings
+        .get(&target)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Returns how often the key was blocked while the virtual layer was active.
+#[no_mangle]
+pub extern "C" fn get_blocked_key_presses(
+    raw_context: *mut AklContext,
+    key: FfiKey,
+) -> u64 {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return 0;
+    };
+
+    let Ok(key) = Key::try_from(key) else {
+        return 0;
+    };
+
+    akl.statistics()
+        .blocked_key_presses
+        .get(&key)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Sets the utf-8 encoded path of the file the statistics are persisted to
+/// whenever the virtual layer stops. Existing statistics in that file are
+/// loaded immediately. A null pointer stops persisting the statistics.
+#[no_mangle]
+pub extern "C" fn set_statistics_file(
+    raw_context: *mut AklContext,
+    path: *const u8,
+    path_length: usize,
+) -> FfiResult {
+    let Some(akl) = akl_from_raw(raw_context) else {
+        return FfiResult::error(
+            "Can't operate on a null pointer. See init method",
+        );
+    };
+
+    let path = if path.is_null() {
+        None
+    } else {
+        let bytes = unsafe { std::slice::from_raw_parts(path, path_length) };
+
+        let Ok(path) = std::str::from_utf8(bytes) else {
+            return FfiResult::error("The path isn't valid utf-8.");
+        };
+
+        Some(std::path::PathBuf::from(path))
+    };
+
+    if let Err(error) = akl.set_statistics_file(path) {
+        return FfiResult::error(&error.to_string());
+    }
+
+    FfiResult::ok()
+}
+
+/// Ffi safe [latency summary](crate::latency::LatencySummary) with all
+/// durations in nanoseconds.
+#[repr(C)]
+pub struct FfiLatencySummary {
+    count: u64,
+    p50: u64,
+    p99: u64,
+    max: u64,
+}
+
+impl From<LatencySummary> for FfiLatencySummary {
+    fn from(value: LatencySummary) -> Self {
+        let nanoseconds = |duration: std::time::Duration| {
+            u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+        };
+
+        Self {
+            count: value.count,
+            p50: nanoseconds(value.p50),
+            p99: nanoseconds(value.p99),
+            max: nanoseconds(value.max),
+        }
+    }
+}
+
+/// Ffi safe snapshot of the [latency](crate::latency::Latency) of the keyboard
+/// hook. `output` is the time spent simulating replacements.
+#[repr(C)]
+pub struct FfiLatency {
+    total: FfiLatencySummary,
+    translation: FfiLatencySummary,
+    processing: FfiLatencySummary,
+    output: FfiLatencySummary,
+}
+
+/// Returns how long the keyboard hook took for each event since the virtual
+/// layer was started. All counters are zero if it isn't running.
+#[no_mangle]
+pub extern "C" fn get_latency(raw_context: *mut AklContext) -> FfiLatency {
+    let latency = akl_from_raw(raw_context)
+        .map(|akl| akl.latency())
+        .unwrap_or_default();
+
+    FfiLatency {
+        total: latency.total.summary().into(),
+        translation: latency.translation.summary().into(),
+        processing: latency.processing.summary().into(),
+        output: latency.output.summary().into(),
+    }
+}
+
+/// Mirrors [`LevelFilter`](log::LevelFilter).
+#[repr(u8)]
+pub enum FfiLogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<FfiLogLevel> for log::LevelFilter {
+    fn from(value: FfiLogLevel) -> Self {
+        match value {
+            FfiLogLevel::Off => Self::Off,
+            FfiLogLevel::Error => Self::Error,
+            FfiLogLevel::Warn => Self::Warn,
+            FfiLogLevel::Info => Self::Info,
+            FfiLogLevel::Debug => Self::Debug,
+            FfiLogLevel::Trace => Self::Trace,
+        }
+    }
+}
+
+/// Sends all log records up to the level to the utf-8 encoded sink which is
+/// one of `stderr`, `file:<path>` or `tcp:<address>`. A null pointer turns
+/// logging off. Applies to every akl context of the process.
+///
+/// See the [logging](crate::logging) module for details on each sink.
+#[no_mangle]
+pub extern "C" fn configure_logging(
+    sink: *const u8,
+    sink_length: usize,
+    level: FfiLogLevel,
+) -> FfiResult {
+    let configuration = if sink.is_null() {
+        None
+    } else {
+        let bytes = unsafe { std::slice::from_raw_parts(sink, sink_length) };
+
+        let Ok(raw_sink) = std::str::from_utf8(bytes) else {
+            return FfiResult::error("The sink isn't valid utf-8.");
+        };
+
+        match raw_sink.parse::<LogSink>() {
+            Ok(sink) => Some(LoggingConfiguration {
+                sink,
+                level: level.into(),
+            }),
+            Err(error) => return FfiResult::error(&error.to_string()),
+        }
+    };
+
+    if let Err(error) = logging::configure(configuration.as_ref()) {
+        return FfiResult::error(&error.to_string());
+    }
+
+    FfiResult::ok()
+}
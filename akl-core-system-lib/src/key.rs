@@ -6,7 +6,7 @@
 //! can be found under the `Trait Implementations` segment of each type.
 #![allow(non_upper_case_globals)]
 
-use std::hash::Hash;
+use std::{fmt, hash::Hash, str::FromStr};
 
 use num_enum::TryFromPrimitive;
 use thiserror::Error;
@@ -18,6 +18,11 @@ use windows::Win32::UI::Input::KeyboardAndMouse::VIRTUAL_KEY;
 pub enum Key {
     Text(char),
     Virtual(VirtualKey),
+    /// Any other key identified by its native virtual key code. Written as
+    /// `vk:0xBA` or `raw:186`. Keys that produce text are only seen as raw
+    /// keys by configurations that [use](crate::event::EventProcessor::uses_raw_key)
+    /// them.
+    Raw(u16),
 }
 
 /// Convenience `from` implementation that justs wraps the character in
@@ -36,6 +41,181 @@ impl From<VirtualKey> for Key {
     }
 }
 
+/// Formats the key the same way it is written in the configuration file, the
+/// name of a virtual key or the character itself.
+impl fmt::Display for Key {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(character) => write!(formatter, "{character}"),
+            Self::Virtual(virtual_key) => write!(formatter, "{virtual_key:?}"),
+            Self::Raw(code) => write!(formatter, "vk:0x{code:02X}"),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum KeyParsingError {
+    /// Contains the names of similar virtual keys to suggest to the user.
+    #[error(
+        "Couldn't parse \"{key}\" as a virtual nor plain text key.{}",
+        did_you_mean(.suggestions)
+    )]
+    UnknownKey {
+        key: String,
+        suggestions: Vec<&'static str>,
+    },
+    #[error("Couldn't parse \"{0}\" as a raw key, expected a key code between 1 and 0xFF such as \"vk:0xBA\" or \"raw:186\".")]
+    InvalidRawKey(String),
+    #[error("{0}")]
+    InvalidCombination(#[from] KeyCombinationConversionError),
+}
+
+/// Parses either the name of a virtual key, a single character or a raw key
+/// code prefixed with `vk:` or `raw:`.
+impl FromStr for Key {
+    type Err = KeyParsingError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        if let Some(code) =
+            raw.strip_prefix("vk:").or_else(|| raw.strip_prefix("raw:"))
+        {
+            return parse_raw_key(code)
+                .ok_or_else(|| KeyParsingError::InvalidRawKey(raw.to_owned()));
+        }
+
+        if let Ok(virtual_key) = VirtualKey::try_from(raw) {
+            return Ok(virtual_key.into());
+        }
+
+        let mut characters = raw.chars();
+
+        match (characters.next(), characters.next()) {
+            (Some(character), None) => Ok(character.into()),
+            _ => Err(KeyParsingError::UnknownKey {
+                key: raw.to_owned(),
+                suggestions: suggestions(raw),
+            }),
+        }
+    }
+}
+
+/// Parses the hexadecimal (`0x` prefix) or decimal native key code of a raw
+/// key. Codes of named virtual keys are turned into those so that both ways
+/// of writing the key are equal.
+fn parse_raw_key(code: &str) -> Option<Key> {
+    let code =
+        match code.strip_prefix("0x").or_else(|| code.strip_prefix("0X")) {
+            Some(hexadecimal) => u16::from_str_radix(hexadecimal, 16),
+            None => code.parse(),
+        }
+        .ok()
+        .filter(|code| (1..=0xFF).contains(code))?;
+
+    Some(
+        VirtualKey::try_from(VIRTUAL_KEY(code))
+            .map_or(Key::Raw(code), Into::into),
+    )
+}
+
+/// Alternative names for virtual keys that are accepted when parsing. Just
+/// like the actual names they are matched case-insensitively. The German names
+/// are the ones printed on German keyboards.
+const ALIASES: &[(&str, VirtualKey)] = &[
+    ("Ctrl", VirtualKey::LControl),
+    ("Control", VirtualKey::LControl),
+    ("Shift", VirtualKey::LShift),
+    ("Alt", VirtualKey::LAlt),
+    ("AltGr", VirtualKey::RAlt),
+    ("Win", VirtualKey::LMeta),
+    ("Windows", VirtualKey::LMeta),
+    ("Super", VirtualKey::LMeta),
+    ("Cmd", VirtualKey::LMeta),
+    ("Meta", VirtualKey::LMeta),
+    ("Menu", VirtualKey::Apps),
+    ("Esc", VirtualKey::Escape),
+    ("Enter", VirtualKey::Return),
+    ("Backspace", VirtualKey::Back),
+    ("Del", VirtualKey::Delete),
+    ("Ins", VirtualKey::Insert),
+    ("PgUp", VirtualKey::PageUp),
+    ("PgDn", VirtualKey::PageDown),
+    ("Strg", VirtualKey::LControl),
+    ("Umschalt", VirtualKey::LShift),
+    ("Entf", VirtualKey::Delete),
+    ("Einfg", VirtualKey::Insert),
+    ("Pos1", VirtualKey::Home),
+    ("Ende", VirtualKey::End),
+    ("Druck", VirtualKey::Print),
+    ("Leertaste", VirtualKey::Space),
+];
+
+/// Maximum number of names suggested for an unknown key.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Names and aliases of virtual keys that are spelled similar to `raw`, the
+/// most similar first.
+fn suggestions(raw: &str) -> Vec<&'static str> {
+    let raw = raw.to_lowercase();
+
+    let mut similar = VirtualKey::NAMES
+        .iter()
+        .chain(ALIASES)
+        .filter_map(|(name, _)| {
+            let distance = edit_distance(&raw, &name.to_lowercase());
+
+            // Allows one typo for every three characters of the name.
+            (distance * 3 <= name.chars().count().max(3))
+                .then_some((distance, *name))
+        })
+        .collect::<Vec<_>>();
+
+    similar.sort_unstable();
+    similar.truncate(MAX_SUGGESTIONS);
+
+    similar.into_iter().map(|(_, name)| name).collect()
+}
+
+/// Number of inserted, removed or replaced characters needed to turn `from`
+/// into `to` (Levenshtein distance).
+fn edit_distance(from: &str, to: &str) -> usize {
+    let to = to.chars().collect::<Vec<_>>();
+    let mut previous = (0..=to.len()).collect::<Vec<_>>();
+
+    for (row, from_character) in from.chars().enumerate() {
+        let mut current = vec![row + 1];
+
+        for (column, to_character) in to.iter().enumerate() {
+            let replace =
+                previous[column] + usize::from(from_character != *to_character);
+
+            current.push(
+                replace
+                    .min(previous[column + 1] + 1)
+                    .min(current[column] + 1),
+            );
+        }
+
+        previous = current;
+    }
+
+    previous[to.len()]
+}
+
+fn did_you_mean(suggestions: &[&str]) -> String {
+    let quoted = suggestions
+        .iter()
+        .map(|name| format!("\"{name}\""))
+        .collect::<Vec<_>>();
+
+    match quoted.as_slice() {
+        [] => String::new(),
+        [only] => format!(" Did you mean {only}?"),
+        [rest @ .., last] => {
+            format!(" Did you mean {} or {last}?", rest.join(", "))
+        }
+    }
+}
+
 /// Represents a valid key combination as used by the event processor to
 /// translate mappings.
 ///
@@ -167,8 +347,88 @@ impl PartialEq for KeyCombination {
 
 impl Eq for KeyCombination {}
 
+/// Formats the keys separated by `+` in the order they are stored in.
+impl fmt::Display for KeyCombination {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: [Option<Key>; 4] = self.into();
+
+        for (index, key) in keys.iter().flatten().enumerate() {
+            if index != 0 {
+                write!(formatter, "+")?;
+            }
+
+            write!(formatter, "{key}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses up to four [keys](Key) separated by `+` such as `LControl+j`.
+impl FromStr for KeyCombination {
+    type Err = KeyParsingError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let keys = raw
+            .split('+')
+            .map(|key| key.trim().parse())
+            .collect::<Result<Vec<Key>, _>>()?;
+
+        Ok(keys.as_slice().try_into()?)
+    }
+}
+
+/// How replacements simulate [text keys](Key::Text).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "config-serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum TextOutput {
+    /// Sends the character itself which works for every character, but some
+    /// applications ignore such input when matching shortcuts.
+    #[default]
+    Unicode,
+    /// Presses the key that produces the character in the current keyboard
+    /// layout together with the modifiers it needs. Falls back to
+    /// [`Unicode`](Self::Unicode) if the layout doesn't have the character.
+    Layout,
+}
+
+/// Linux input event code of a key as defined by the `KEY_*` constants in
+/// `linux/input-event-codes.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EvdevCode(pub u16);
+
+impl EvdevCode {
+    /// `KEY_RESERVED`, declared for virtual keys that don't exist on Linux.
+    pub const RESERVED: Self = Self(0);
+}
+
+/// X11 keysym of a key as defined by the `XK_*` constants in
+/// `X11/keysymdef.h` and `X11/XF86keysym.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Keysym(pub u32);
+
+impl Keysym {
+    /// `NoSymbol`, declared for virtual keys that don't have a keysym.
+    pub const NO_SYMBOL: Self = Self(0);
+}
+
+/// Turns the optional `extended` marker of a key in
+/// [`define_virtual_key_codes`] into a bool.
+macro_rules! is_extended {
+    () => {
+        false
+    };
+    (extended) => {
+        true
+    };
+}
+
 macro_rules! define_virtual_key_codes {
-    ($($name: ident = $windows_translation: expr),*,) => {
+    ($($name: ident = ($windows_translation: expr, $evdev: expr, $keysym: expr $(, $extended: ident)?)),*,) => {
         /// Represents any key that doesn't produce any text / characters when
         /// pressed, dead keys excluded.
         ///
@@ -188,17 +448,26 @@ macro_rules! define_virtual_key_codes {
             NoKeyWithSpecifiedName,
             #[error("No virtual key with the specified code ({:X}) exists.", (.0).0)]
             NoKeyWithSpecifiedCode(VIRTUAL_KEY),
+            #[error("No virtual key with the specified evdev code ({}) exists.", (.0).0)]
+            NoKeyWithSpecifiedEvdevCode(EvdevCode),
+            #[error("No virtual key with the specified keysym ({:X}) exists.", (.0).0)]
+            NoKeyWithSpecifiedKeysym(Keysym),
+            #[error("The virtual key {0:?} doesn't exist on this platform.")]
+            NotOnPlatform(VirtualKey),
         }
 
-        /// Try to get a virtual key with the specified name fails if not found.
+        /// Try to get a virtual key with the specified name or one of its
+        /// aliases (case-insensitive) fails if not found.
         impl TryFrom<&str> for VirtualKey {
             type Error = VirtualKeyConversionError;
 
             fn try_from(value: &str) -> Result<Self, Self::Error> {
-                match value {
-                    $(stringify!($name) => Ok(VirtualKey::$name),)*
-                    _ => Err(VirtualKeyConversionError::NoKeyWithSpecifiedName)
-                }
+                Self::NAMES
+                    .iter()
+                    .chain(ALIASES)
+                    .find(|(name, _)| name.eq_ignore_ascii_case(value))
+                    .map(|(_, virtual_key)| *virtual_key)
+                    .ok_or(VirtualKeyConversionError::NoKeyWithSpecifiedName)
             }
         }
 
@@ -206,14 +475,14 @@ macro_rules! define_virtual_key_codes {
         /// to actual virtual keys. This library defines a virtual key as any
         /// key that doesn't print characters when pressed but the windows api
         /// includes letters A-Z and some oem keys that will fail the `try_from`.
+        /// Keys that share their code with another key and can only be told
+        /// apart by the extended flag (e.g. `NumpadEnter`) are never returned,
+        /// see [`VirtualKey::from_windows_key`].
         impl TryFrom<VIRTUAL_KEY> for VirtualKey {
             type Error = VirtualKeyConversionError;
 
             fn try_from(windows_key: VIRTUAL_KEY) -> Result<Self, Self::Error> {
-                match windows_key.0 {
-                    $($windows_translation => Ok(VirtualKey::$name),)*
-                    _ => Err(VirtualKeyConversionError::NoKeyWithSpecifiedCode(windows_key)),
-                }
+                Self::from_windows_key(windows_key, false)
             }
         }
 
@@ -227,7 +496,105 @@ macro_rules! define_virtual_key_codes {
             }
         }
 
+        /// Tries to translate a linux input event code to a virtual key, fails
+        /// for codes of text keys and [`EvdevCode::RESERVED`].
+        impl TryFrom<EvdevCode> for VirtualKey {
+            type Error = VirtualKeyConversionError;
+
+            fn try_from(code: EvdevCode) -> Result<Self, Self::Error> {
+                Self::LINUX_CODES
+                    .iter()
+                    .find(|(_, evdev, _)| *evdev == code && code != EvdevCode::RESERVED)
+                    .map(|(virtual_key, _, _)| *virtual_key)
+                    .ok_or(VirtualKeyConversionError::NoKeyWithSpecifiedEvdevCode(code))
+            }
+        }
+
+        /// Fails for virtual keys without a linux input event code.
+        impl TryFrom<VirtualKey> for EvdevCode {
+            type Error = VirtualKeyConversionError;
+
+            fn try_from(virtual_key: VirtualKey) -> Result<Self, Self::Error> {
+                match virtual_key {
+                    $(VirtualKey::$name => Some(EvdevCode($evdev)),)*
+                }
+                .filter(|code| *code != EvdevCode::RESERVED)
+                .ok_or(VirtualKeyConversionError::NotOnPlatform(virtual_key))
+            }
+        }
+
+        /// Tries to translate an X11 keysym to a virtual key, fails for the
+        /// keysyms of text keys and [`Keysym::NO_SYMBOL`].
+        impl TryFrom<Keysym> for VirtualKey {
+            type Error = VirtualKeyConversionError;
+
+            fn try_from(keysym: Keysym) -> Result<Self, Self::Error> {
+                Self::LINUX_CODES
+                    .iter()
+                    .find(|(_, _, it)| *it == keysym && keysym != Keysym::NO_SYMBOL)
+                    .map(|(virtual_key, _, _)| *virtual_key)
+                    .ok_or(VirtualKeyConversionError::NoKeyWithSpecifiedKeysym(keysym))
+            }
+        }
+
+        /// Fails for virtual keys without an X11 keysym.
+        impl TryFrom<VirtualKey> for Keysym {
+            type Error = VirtualKeyConversionError;
+
+            fn try_from(virtual_key: VirtualKey) -> Result<Self, Self::Error> {
+                match virtual_key {
+                    $(VirtualKey::$name => Some(Keysym($keysym)),)*
+                }
+                .filter(|keysym| *keysym != Keysym::NO_SYMBOL)
+                .ok_or(VirtualKeyConversionError::NotOnPlatform(virtual_key))
+            }
+        }
+
         impl VirtualKey {
+            /// Every virtual key together with its windows virtual key code and
+            /// whether it needs the extended flag.
+            const WINDOWS_CODES: &'static [(VirtualKey, u16, bool)] =
+                &[$((VirtualKey::$name, $windows_translation, is_extended!($($extended)?)),)*];
+
+            /// Translates the windows virtual key code of a key event. The
+            /// extended flag distinguishes keys like `NumpadEnter` from the ones
+            /// they share their code with. Other keys ignore the flag because
+            /// windows sets it for many of them, e.g. the arrow keys.
+            pub fn from_windows_key(
+                windows_key: VIRTUAL_KEY,
+                extended: bool,
+            ) -> Result<Self, VirtualKeyConversionError> {
+                let find = |extended| {
+                    Self::WINDOWS_CODES.iter().find(|(_, code, it)| {
+                        *code == windows_key.0 && *it == extended
+                    })
+                };
+
+                extended
+                    .then(|| find(true))
+                    .flatten()
+                    .or_else(|| find(false))
+                    .map(|(virtual_key, _, _)| *virtual_key)
+                    .ok_or(VirtualKeyConversionError::NoKeyWithSpecifiedCode(windows_key))
+            }
+
+            /// Whether simulating the key on windows requires the extended
+            /// flag.
+            pub fn is_extended(self) -> bool {
+                match self {
+                    $(VirtualKey::$name => is_extended!($($extended)?),)*
+                }
+            }
+
+            /// Every virtual key together with its linux input event code and
+            /// X11 keysym.
+            const LINUX_CODES: &'static [(VirtualKey, EvdevCode, Keysym)] =
+                &[$((VirtualKey::$name, EvdevCode($evdev), Keysym($keysym)),)*];
+
+            /// Every virtual key together with the name it is displayed as.
+            const NAMES: &'static [(&'static str, VirtualKey)] =
+                &[$((stringify!($name), VirtualKey::$name),)*];
+
             /// Convenience function for converting to the raw windows virtual
             /// key code translation.
             pub fn to_windows_key(self) -> u16 {
@@ -237,109 +604,130 @@ macro_rules! define_virtual_key_codes {
     };
 }
 
-// Defines the virtual key code enum. For now the macro only declares a windows
-// translation from here https://learn.microsoft.com/en-us/windows/win32/inputdev/virtual-key-codes
-// this will change when the linux implementation get created.
+// Defines the virtual key code enum. Each key declares its windows translation
+// from here https://learn.microsoft.com/en-us/windows/win32/inputdev/virtual-key-codes
+// followed by its linux input event code and X11 keysym. A zero means the key
+// doesn't exist on that platform (`KEY_RESERVED` / `NoSymbol`). Keys marked as
+// `extended` share their windows code with another key and need the extended
+// flag. All three tables have to be bijective which is checked by
+// `test_code_tables_bijective`.
 define_virtual_key_codes!(
-    Back = 0x08,
-    Tab = 0x09,
-    Clear = 0x0c,
-    Return = 0x0d,
-    Pause = 0x13,
-    CapsLock = 0x14,
-    Escape = 0x1b,
-    Space = 0x20,
-    PageUp = 0x21,
-    PageDown = 0x22,
-    Home = 0x24,
-    End = 0x23,
-    LeftArrow = 0x25,
-    UpArrow = 0x26,
-    RightArrow = 0x27,
-    DownArrow = 0x28,
-    Select = 0x29,
-    Print = 0x2a,
-    Execute = 0x2b,
-    Insert = 0x2d,
-    Delete = 0x2e,
-    Help = 0x2f,
-    LMeta = 0x5b,
-    RMeta = 0x5c,
-    Apps = 0x5d,
-    Sleep = 0x5f,
-    Numpad0 = 0x60,
-    Numpad1 = 0x61,
-    Numpad2 = 0x62,
-    Numpad3 = 0x63,
-    Numpad4 = 0x64,
-    Numpad5 = 0x65,
-    Numpad6 = 0x66,
-    Numpad7 = 0x67,
-    Numpad8 = 0x68,
-    Numpad9 = 0x69,
-    Multiply = 0x6a,
-    Add = 0x6b,
-    Separator = 0x6c,
-    Subtract = 0x6d,
-    Decimal = 0x6e,
-    Divide = 0x6f,
-    F1 = 0x70,
-    F2 = 0x71,
-    F3 = 0x72,
-    F4 = 0x73,
-    F5 = 0x74,
-    F6 = 0x75,
-    F7 = 0x76,
-    F8 = 0x77,
-    F9 = 0x78,
-    F10 = 0x79,
-    F11 = 0x7A,
-    F12 = 0x7B,
-    F13 = 0x7C,
-    F14 = 0x7D,
-    F15 = 0x7E,
-    F16 = 0x7F,
-    F17 = 0x80,
-    F18 = 0x81,
-    F19 = 0x82,
-    F20 = 0x83,
-    F21 = 0x84,
-    F22 = 0x85,
-    F23 = 0x86,
-    F24 = 0x87,
-    Numlock = 0x90,
-    Scroll = 0x91,
-    LShift = 0xa0,
-    RShift = 0xa1,
-    LControl = 0xa2,
-    RControl = 0xa3,
-    LAlt = 0xa4,
-    RAlt = 0xa5,
-    BrowserBack = 0xa6,
-    BrowserForward = 0xa7,
-    BrowserRefresh = 0xa8,
-    BrowserStop = 0xa9,
-    BrowserSearch = 0xaa,
-    BrowserFavorites = 0xab,
-    BrowserHome = 0xac,
-    VolumeMute = 0xad,
-    VolumeDown = 0xae,
-    VolumeUp = 0xaf,
-    MediaNextTrack = 0xb0,
-    MediaPrevTrack = 0xb1,
-    MediaStop = 0xb2,
-    MediaPlayPause = 0xb3,
-    LaunchMail = 0xb4,
-    LaunchApp1 = 0xb6,
-    LaunchApp2 = 0xb7,
-    Play = 0xfa,
+    Back = (0x08, 14, 0xff08), // KEY_BACKSPACE, XK_BackSpace
+    Tab = (0x09, 15, 0xff09),  // KEY_TAB, XK_Tab
+    Clear = (0x0c, 355, 0xff0b), // KEY_CLEAR, XK_Clear
+    Return = (0x0d, 28, 0xff0d), // KEY_ENTER, XK_Return
+    Pause = (0x13, 119, 0xff13), // KEY_PAUSE, XK_Pause
+    CapsLock = (0x14, 58, 0xffe5), // KEY_CAPSLOCK, XK_Caps_Lock
+    Escape = (0x1b, 1, 0xff1b), // KEY_ESC, XK_Escape
+    Space = (0x20, 57, 0x20),  // KEY_SPACE, XK_space
+    PageUp = (0x21, 104, 0xff55), // KEY_PAGEUP, XK_Prior
+    PageDown = (0x22, 109, 0xff56), // KEY_PAGEDOWN, XK_Next
+    Home = (0x24, 102, 0xff50), // KEY_HOME, XK_Home
+    End = (0x23, 107, 0xff57), // KEY_END, XK_End
+    LeftArrow = (0x25, 105, 0xff51), // KEY_LEFT, XK_Left
+    UpArrow = (0x26, 103, 0xff52), // KEY_UP, XK_Up
+    RightArrow = (0x27, 106, 0xff53), // KEY_RIGHT, XK_Right
+    DownArrow = (0x28, 108, 0xff54), // KEY_DOWN, XK_Down
+    Select = (0x29, 353, 0xff60), // KEY_SELECT, XK_Select
+    Print = (0x2a, 210, 0xff61), // KEY_PRINT, XK_Print
+    Execute = (0x2b, 0, 0xff62), // none, XK_Execute
+    Insert = (0x2d, 110, 0xff63), // KEY_INSERT, XK_Insert
+    Delete = (0x2e, 111, 0xffff), // KEY_DELETE, XK_Delete
+    Help = (0x2f, 138, 0xff6a), // KEY_HELP, XK_Help
+    LMeta = (0x5b, 125, 0xffeb), // KEY_LEFTMETA, XK_Super_L
+    RMeta = (0x5c, 126, 0xffec), // KEY_RIGHTMETA, XK_Super_R
+    Apps = (0x5d, 127, 0xff67), // KEY_COMPOSE, XK_Menu
+    Sleep = (0x5f, 142, 0x1008_ff2f), // KEY_SLEEP, XF86XK_Sleep
+    Numpad0 = (0x60, 82, 0xffb0), // KEY_KP0, XK_KP_0
+    Numpad1 = (0x61, 79, 0xffb1), // KEY_KP1, XK_KP_1
+    Numpad2 = (0x62, 80, 0xffb2), // KEY_KP2, XK_KP_2
+    Numpad3 = (0x63, 81, 0xffb3), // KEY_KP3, XK_KP_3
+    Numpad4 = (0x64, 75, 0xffb4), // KEY_KP4, XK_KP_4
+    Numpad5 = (0x65, 76, 0xffb5), // KEY_KP5, XK_KP_5
+    Numpad6 = (0x66, 77, 0xffb6), // KEY_KP6, XK_KP_6
+    Numpad7 = (0x67, 71, 0xffb7), // KEY_KP7, XK_KP_7
+    Numpad8 = (0x68, 72, 0xffb8), // KEY_KP8, XK_KP_8
+    Numpad9 = (0x69, 73, 0xffb9), // KEY_KP9, XK_KP_9
+    Multiply = (0x6a, 55, 0xffaa), // KEY_KPASTERISK, XK_KP_Multiply
+    Add = (0x6b, 78, 0xffab),  // KEY_KPPLUS, XK_KP_Add
+    Separator = (0x6c, 121, 0xffac), // KEY_KPCOMMA, XK_KP_Separator
+    Subtract = (0x6d, 74, 0xffad), // KEY_KPMINUS, XK_KP_Subtract
+    Decimal = (0x6e, 83, 0xffae), // KEY_KPDOT, XK_KP_Decimal
+    Divide = (0x6f, 98, 0xffaf), // KEY_KPSLASH, XK_KP_Divide
+    F1 = (0x70, 59, 0xffbe),   // KEY_F1, XK_F1
+    F2 = (0x71, 60, 0xffbf),   // KEY_F2, XK_F2
+    F3 = (0x72, 61, 0xffc0),   // KEY_F3, XK_F3
+    F4 = (0x73, 62, 0xffc1),   // KEY_F4, XK_F4
+    F5 = (0x74, 63, 0xffc2),   // KEY_F5, XK_F5
+    F6 = (0x75, 64, 0xffc3),   // KEY_F6, XK_F6
+    F7 = (0x76, 65, 0xffc4),   // KEY_F7, XK_F7
+    F8 = (0x77, 66, 0xffc5),   // KEY_F8, XK_F8
+    F9 = (0x78, 67, 0xffc6),   // KEY_F9, XK_F9
+    F10 = (0x79, 68, 0xffc7),  // KEY_F10, XK_F10
+    F11 = (0x7a, 87, 0xffc8),  // KEY_F11, XK_F11
+    F12 = (0x7b, 88, 0xffc9),  // KEY_F12, XK_F12
+    F13 = (0x7c, 183, 0xffca), // KEY_F13, XK_F13
+    F14 = (0x7d, 184, 0xffcb), // KEY_F14, XK_F14
+    F15 = (0x7e, 185, 0xffcc), // KEY_F15, XK_F15
+    F16 = (0x7f, 186, 0xffcd), // KEY_F16, XK_F16
+    F17 = (0x80, 187, 0xffce), // KEY_F17, XK_F17
+    F18 = (0x81, 188, 0xffcf), // KEY_F18, XK_F18
+    F19 = (0x82, 189, 0xffd0), // KEY_F19, XK_F19
+    F20 = (0x83, 190, 0xffd1), // KEY_F20, XK_F20
+    F21 = (0x84, 191, 0xffd2), // KEY_F21, XK_F21
+    F22 = (0x85, 192, 0xffd3), // KEY_F22, XK_F22
+    F23 = (0x86, 193, 0xffd4), // KEY_F23, XK_F23
+    F24 = (0x87, 194, 0xffd5), // KEY_F24, XK_F24
+    Numlock = (0x90, 69, 0xff7f), // KEY_NUMLOCK, XK_Num_Lock
+    Scroll = (0x91, 70, 0xff14), // KEY_SCROLLLOCK, XK_Scroll_Lock
+    LShift = (0xa0, 42, 0xffe1), // KEY_LEFTSHIFT, XK_Shift_L
+    RShift = (0xa1, 54, 0xffe2), // KEY_RIGHTSHIFT, XK_Shift_R
+    LControl = (0xa2, 29, 0xffe3), // KEY_LEFTCTRL, XK_Control_L
+    RControl = (0xa3, 97, 0xffe4), // KEY_RIGHTCTRL, XK_Control_R
+    LAlt = (0xa4, 56, 0xffe9), // KEY_LEFTALT, XK_Alt_L
+    RAlt = (0xa5, 100, 0xffea), // KEY_RIGHTALT, XK_Alt_R
+    BrowserBack = (0xa6, 158, 0x1008_ff26), // KEY_BACK, XF86XK_Back
+    BrowserForward = (0xa7, 159, 0x1008_ff27), // KEY_FORWARD, XF86XK_Forward
+    BrowserRefresh = (0xa8, 173, 0x1008_ff29), // KEY_REFRESH, XF86XK_Refresh
+    BrowserStop = (0xa9, 128, 0x1008_ff28), // KEY_STOP, XF86XK_Stop
+    BrowserSearch = (0xaa, 217, 0x1008_ff1b), // KEY_SEARCH, XF86XK_Search
+    // KEY_BOOKMARKS, XF86XK_Favorites
+    BrowserFavorites = (0xab, 156, 0x1008_ff30),
+    BrowserHome = (0xac, 172, 0x1008_ff18), // KEY_HOMEPAGE, XF86XK_HomePage
+    VolumeMute = (0xad, 113, 0x1008_ff12),  // KEY_MUTE, XF86XK_AudioMute
+    // KEY_VOLUMEDOWN, XF86XK_AudioLowerVolume
+    VolumeDown = (0xae, 114, 0x1008_ff11),
+    // KEY_VOLUMEUP, XF86XK_AudioRaiseVolume
+    VolumeUp = (0xaf, 115, 0x1008_ff13),
+    MediaNextTrack = (0xb0, 163, 0x1008_ff17), // KEY_NEXTSONG, XF86XK_AudioNext
+    // KEY_PREVIOUSSONG, XF86XK_AudioPrev
+    MediaPrevTrack = (0xb1, 165, 0x1008_ff16),
+    MediaStop = (0xb2, 166, 0x1008_ff15), // KEY_STOPCD, XF86XK_AudioStop
+    // KEY_PLAYPAUSE, XF86XK_AudioPlay
+    MediaPlayPause = (0xb3, 164, 0x1008_ff14),
+    LaunchMail = (0xb4, 155, 0x1008_ff19), // KEY_MAIL, XF86XK_Mail
+    LaunchApp1 = (0xb6, 157, 0x1008_ff33), // KEY_COMPUTER, XF86XK_MyComputer
+    LaunchApp2 = (0xb7, 140, 0x1008_ff1d), // KEY_CALC, XF86XK_Calculator
+    Play = (0xfa, 207, 0),                 // KEY_PLAY, none
+    // New keys have to be appended to keep the discriminants stable for ffi.
+    Zoom = (0xfb, 372, 0), // KEY_ZOOM, none
+    NumpadEnter = (0x0d, 96, 0xff8d, extended), // KEY_KPENTER, XK_KP_Enter
+    // KEY_KATAKANAHIRAGANA, XK_Hiragana_Katakana
+    Kana = (0x15, 93, 0xff27),
+    Kanji = (0x19, 85, 0xff2a), // KEY_ZENKAKUHANKAKU, XK_Zenkaku_Hankaku
+    Convert = (0x1c, 92, 0xff23), // KEY_HENKAN, XK_Henkan
+    NonConvert = (0x1d, 94, 0xff22), // KEY_MUHENKAN, XK_Muhenkan
+    // KEY_MEDIA, XF86XK_AudioMedia
+    LaunchMediaSelect = (0xb5, 226, 0x1008_ff32),
 );
 
 #[cfg(test)]
 mod tests {
     use std::{collections::hash_map::DefaultHasher, hash::Hasher};
 
-    use windows::Win32::UI::Input::KeyboardAndMouse::VK_TAB;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        VK_RETURN, VK_TAB, VK_UP,
+    };
 
     use super::*;
 
@@ -490,6 +878,35 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_key_parsing_and_display() {
+        assert_eq!(Ok(KEY_A), "a".parse());
+        assert_eq!(Ok(KEY_ESCAPE), "Escape".parse());
+        assert_eq!(
+            Err(KeyParsingError::UnknownKey {
+                key: "Escap".to_owned(),
+                suggestions: vec!["Escape"],
+            }),
+            "Escap".parse::<Key>()
+        );
+
+        assert_eq!(
+            Ok(KeyCombination(KEY_ESCAPE, Some(KEY_A), None, None)),
+            "Escape + a".parse()
+        );
+        assert_eq!(
+            Err(KeyParsingError::InvalidCombination(
+                KeyCombinationConversionError::TooManyKeys
+            )),
+            "a+b+Escape+Return+a".parse::<KeyCombination>()
+        );
+
+        assert_eq!("Escape+a+Return", {
+            KeyCombination(KEY_ESCAPE, Some(KEY_A), Some(KEY_RETURN), None)
+                .to_string()
+        });
+    }
+
     #[test]
     fn test_virtual_key_conversion() {
         assert_eq!(Ok(VirtualKey::Tab), TryInto::<VirtualKey>::try_into("Tab"));
@@ -500,6 +917,134 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_key_parsing_aliases() {
+        assert_eq!(Ok(KEY_ESCAPE), "escape".parse());
+        assert_eq!(Ok(KEY_ESCAPE), "ESC".parse());
+        assert_eq!(Ok(KEY_RETURN), "Enter".parse());
+
+        // Single characters stay text keys and keep their case.
+        assert_eq!(Ok(Key::Text('A')), "A".parse());
+
+        // Aliases are displayed with the actual name.
+        let combination = "Strg+Entf+Pos1".parse::<KeyCombination>().unwrap();
+        assert_eq!("LControl+Delete+Home", combination.to_string());
+        assert_eq!(Ok(combination), "ctrl+del+home".parse());
+
+        for (alias, virtual_key) in [
+            ("Win", VirtualKey::LMeta),
+            ("Super", VirtualKey::LMeta),
+            ("Cmd", VirtualKey::LMeta),
+            ("PgUp", VirtualKey::PageUp),
+            ("Einfg", VirtualKey::Insert),
+        ] {
+            assert_eq!(Ok(virtual_key.into()), alias.parse::<Key>());
+        }
+    }
+
+    #[test]
+    fn test_raw_key_parsing() {
+        assert_eq!(Ok(Key::Raw(0xBA)), "vk:0xBA".parse());
+        assert_eq!(Ok(Key::Raw(0xBA)), "raw:186".parse());
+        assert_eq!("vk:0xBA", Key::Raw(0xBA).to_string());
+
+        // Named keys don't have a second representation.
+        assert_eq!(Ok(KEY_ESCAPE), "vk:0x1B".parse());
+
+        for invalid in ["vk:", "vk:0", "raw:0x100", "raw:BA"] {
+            assert_eq!(
+                Err(KeyParsingError::InvalidRawKey(invalid.to_owned())),
+                invalid.parse::<Key>()
+            );
+        }
+
+        assert_eq!(
+            Ok(KeyCombination(Key::Raw(0xBA), Some(KEY_A), None, None)),
+            "raw:186+a".parse()
+        );
+    }
+
+    #[test]
+    fn test_key_parsing_suggestions() {
+        let error = "Contrl".parse::<Key>().unwrap_err();
+        assert_eq!(
+            "Couldn't parse \"Contrl\" as a virtual nor plain text key. Did \
+             you mean \"Control\", \"LControl\" or \"RControl\"?",
+            error.to_string()
+        );
+
+        assert_eq!(
+            Err(KeyParsingError::UnknownKey {
+                key: "Nothing".to_owned(),
+                suggestions: vec![],
+            }),
+            "Nothing".parse::<Key>()
+        );
+    }
+
+    #[test]
+    fn test_code_tables_bijective() {
+        for (_, virtual_key) in VirtualKey::NAMES {
+            let windows_key = VIRTUAL_KEY::from(*virtual_key);
+            assert_eq!(
+                Ok(*virtual_key),
+                VirtualKey::from_windows_key(
+                    windows_key,
+                    virtual_key.is_extended()
+                )
+            );
+
+            if let Ok(code) = EvdevCode::try_from(*virtual_key) {
+                assert_eq!(Ok(*virtual_key), code.try_into());
+            }
+
+            if let Ok(keysym) = Keysym::try_from(*virtual_key) {
+                assert_eq!(Ok(*virtual_key), keysym.try_into());
+            }
+        }
+
+        // Keys that don't exist on a platform are the exception.
+        let without_code = |platform_code: fn(VirtualKey) -> bool| {
+            VirtualKey::NAMES
+                .iter()
+                .filter(|(_, virtual_key)| !platform_code(*virtual_key))
+                .count()
+        };
+
+        assert_eq!(1, without_code(|it| EvdevCode::try_from(it).is_ok()));
+        assert_eq!(2, without_code(|it| Keysym::try_from(it).is_ok()));
+
+        // Windows sets the extended flag for more keys than need it.
+        assert_eq!(
+            Ok(VirtualKey::UpArrow),
+            VirtualKey::from_windows_key(VK_UP, true)
+        );
+        assert_eq!(
+            Ok(VirtualKey::NumpadEnter),
+            VirtualKey::from_windows_key(VK_RETURN, true)
+        );
+        assert_eq!(Ok(VirtualKey::Return), VK_RETURN.try_into());
+
+        assert_eq!(
+            Err(VirtualKeyConversionError::NoKeyWithSpecifiedEvdevCode(
+                EvdevCode::RESERVED
+            )),
+            VirtualKey::try_from(EvdevCode::RESERVED)
+        );
+        assert_eq!(
+            Err(VirtualKeyConversionError::NotOnPlatform(VirtualKey::Play)),
+            Keysym::try_from(VirtualKey::Play)
+        );
+        assert_eq!(
+            Ok(VirtualKey::LControl),
+            VirtualKey::try_from(EvdevCode(29))
+        );
+        assert_eq!(
+            Ok(VirtualKey::Escape),
+            VirtualKey::try_from(Keysym(0xff1b))
+        );
+    }
+
     #[test]
     fn test_virtual_key_conversion_windows() {
         assert_eq!(Ok(VirtualKey::Tab), VK_TAB.try_into());
@@ -1,5 +1,6 @@
 //! Native low level platform dependent keyboard input hook abstraction that
-//! directly calls the [`event processor`](crate::event::EventProcessor).
+//! [`dispatches`](crate::dispatch) every event to the contexts of all
+//! registered handles.
 //!
 //! Windows implementation of the keyboard hook using the native
 //! [WH_KEYBOARD_LL](https://learn.microsoft.com/en-us/windows/win32/winmsg/about-hooks#wh_keyboard_ll)
@@ -13,123 +14,330 @@
     clippy::cast_possible_wrap
 )]
 
+mod pointer;
 mod translation;
 
 use std::{
     mem,
     os::windows::prelude::AsRawHandle,
     ptr,
-    sync::{mpsc, Mutex},
-    thread,
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
 };
 
 use windows::Win32::{
     Foundation::{HANDLE, HMODULE, LPARAM, LRESULT, WPARAM},
-    System::Threading::GetThreadId,
+    System::Threading::{GetCurrentThreadId, GetThreadId},
     UI::{
         Input::KeyboardAndMouse::{SendInput, INPUT},
         WindowsAndMessaging::{
-            CallNextHookEx, GetMessageW, PostThreadMessageW, SetWindowsHookExW,
-            UnhookWindowsHookEx, HHOOK, KBDLLHOOKSTRUCT, MSG, WH_KEYBOARD_LL,
-            WM_APP,
+            CallNextHookEx, GetMessageW, PeekMessageW, PostThreadMessageW,
+            SetWindowsHookExW, UnhookWindowsHookEx, HHOOK, KBDLLHOOKSTRUCT,
+            MSG, PM_NOREMOVE, WH_KEYBOARD_LL, WM_APP,
         },
     },
 };
 
-use log::{error, info};
+use log::info;
 use thiserror::Error;
 
-use crate::event::{EventProcessor, ResponseAction};
+use crate::{
+    dispatch::{self, Context, DisableReason},
+    event::{Action, EventProcessor, ResponseAction},
+    key::TextOutput,
+    kill_switch::KillSwitch,
+    latency::{Latency, Stage, Stopwatch},
+    logging,
+    mouse::MouseAction,
+    observer::ObserverSender,
+    profile::{ProfileError, Profiles},
+    statistics::Statistics,
+};
 
 /// All errors that can occur while trying to register a keyboard hook.
 #[derive(Error, Debug)]
 pub enum HandleError {
-    #[error("Can't have two hooks registered at the same time.")]
-    AnotherHookIsAlreadyInstalled,
     #[error("{0}")]
     RegistrationFailed(String),
 }
 
 /// Windows keyboard hook handle implementation which ensures safety.
 ///
+/// Each handle owns a [`Context`] that gets events from the single native
+/// hook shared by all handles. The native hook and its message queue are
+/// started with the first handle and stopped after the last one is dropped.
+///
 /// This handle enforces all invariants that could cause undefined behavior or
 /// at least severe logic bugs when broken, by only exposing a safe
 /// [`register`](Handle::register) function to obtain a keyboard hook handle.
-///
-/// Also handles the cleanup of the message queue associated with the [`ManagedHook`].
 pub struct Handle {
-    // Used to keep the managed hook alive until the handle gets destroyed.
-    #[allow(dead_code)]
-    hook: ManagedHook,
-    message_queue_thread: u32,
+    context: Arc<Context>,
 }
 
 impl Handle {
-    /// Tries to register a keyboard hook with its associated event processor
-    /// and starts a message queue to process the messages.
+    /// Registers a context with its associated event processor and starts the
+    /// native hook if it isn't running yet. Contexts with a higher priority
+    /// see each event first, see [`dispatch`](crate::dispatch). Every
+    /// processed event is reported to the observer if there is one. The kill
+    /// switch is checked before the event processor sees any event. Text keys
+    /// of replacements are simulated according to the text output. The
+    /// context can switch to any of the profiles.
     ///
     /// # Errors
     ///
-    /// Registration can fail if another keyboard hook is currently already
-    /// registered or if the windows [`set hook`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-setwindowshookexw)
+    /// Registration can fail if the windows [`set hook`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-setwindowshookexw)
     /// call fails.
     pub fn register(
         associated_event_processor: EventProcessor,
+        kill_switch: KillSwitch,
+        observer: Option<ObserverSender>,
+        priority: i32,
+        text_output: TextOutput,
+        profiles: Profiles,
     ) -> Result<Self, HandleError> {
-        let (keyboard_hook_sender, keyboard_hook_receiver) = mpsc::channel();
+        let context = Arc::new(Context::new(
+            associated_event_processor,
+            kill_switch,
+            observer,
+            priority,
+            text_output,
+            profiles,
+        ));
+
+        let mut hook_thread = HOOK_THREAD
+            .lock()
+            .expect("Global hook doesn't panic so it can't poison the mutex");
 
-        let message_queue = thread::spawn(move || {
+        // The hook stops by itself once every context disabled itself.
+        if hook_thread.as_ref().is_none_or(HookThread::is_finished) {
+            if let Some(finished) = hook_thread.take() {
+                finished.stop();
+            }
+
+            *hook_thread = Some(HookThread::spawn()?);
+        }
+
+        let mut contexts = CONTEXTS
+            .lock()
+            .expect("Global hook doesn't panic so it can't poison the mutex");
+
+        // Contexts with the same priority are asked in registration order.
+        let index = contexts
+            .iter()
+            .position(|it| it.priority() < priority)
+            .unwrap_or(contexts.len());
+        contexts.insert(index, Arc::clone(&context));
+
+        Ok(Self { context })
+    }
+
+    /// Snapshot of the usage statistics collected by the associated event
+    /// processor.
+    pub fn statistics(&self) -> Statistics {
+        self.context.statistics()
+    }
+
+    /// Snapshot of the time the raw keyboard input hook took for each event
+    /// the associated event processor saw since it was registered.
+    pub fn latency(&self) -> Latency {
+        self.context.latency()
+    }
+
+    /// Switches the associated event processor to the profile.
+    ///
+    /// # Errors
+    ///
+    /// If the context doesn't know a profile with the name.
+    pub fn switch_profile(&self, name: &str) -> Result<(), ProfileError> {
+        self.context.switch_profile(name)
+    }
+
+    /// Pauses or resumes the associated event processor.
+    pub fn set_paused(&self, paused: bool) {
+        self.context.set_paused(paused);
+    }
+
+    /// Whether the associated event processor passes every event through.
+    pub fn is_paused(&self) -> bool {
+        self.context.is_paused()
+    }
+
+    /// Name of the profile the associated event processor uses if it switched
+    /// to one.
+    pub fn active_profile(&self) -> Option<String> {
+        self.context.active_profile()
+    }
+
+    /// Whether the associated event processor switched to a profile at all.
+    pub fn has_switched_profile(&self) -> bool {
+        self.context.has_switched_profile()
+    }
+
+    /// Why the context disabled itself if it did. The handle still has to be
+    /// dropped to release the remaining resources.
+    pub fn disabled(&self) -> Option<DisableReason> {
+        self.context.disabled()
+    }
+}
+
+/// Removes the context and stops the native hook if it was the last one.
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let mut hook_thread = HOOK_THREAD
+            .lock()
+            .expect("Global hook doesn't panic so it can't poison the mutex");
+
+        let mut contexts = CONTEXTS
+            .lock()
+            .expect("Global hook doesn't panic so it can't poison the mutex");
+        contexts.retain(|it| !Arc::ptr_eq(it, &self.context));
+        let last = contexts.is_empty();
+        drop(contexts);
+
+        if last {
+            if let Some(hook_thread) = hook_thread.take() {
+                hook_thread.stop();
+            }
+        }
+
+        drop(hook_thread);
+
+        // Simulated inputs are marked and thus ignored by the hook if it's
+        // still running for other contexts.
+        unsafe {
+            release_pressed_keys(&self.context);
+            release_mouse(&self.context);
+        }
+
+        info!("Hook latency {}", self.context.latency());
+    }
+}
+
+/// Thread that registered the native hook and runs the message queue it
+/// needs. The hook is unregistered from the same thread once the message
+/// queue stops. The pointer thread for mouse keys runs alongside it.
+struct HookThread {
+    thread: JoinHandle<()>,
+    thread_id: u32,
+    pointer: pointer::Pointer,
+}
+
+impl HookThread {
+    /// Starts the thread and waits until the native hook is registered.
+    fn spawn() -> Result<Self, HandleError> {
+        let (registered_sender, registered_receiver) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
             // Important: The hook has to be registered from the same thread in
-            // which the message queue is running. That's why there is a need
-            // to explicitly send the handle to the main thread.
-            let _ = keyboard_hook_sender
-                .send(ManagedHook::register(associated_event_processor));
-            drop(keyboard_hook_sender);
+            // which the message queue is running.
+            let hook = match register_hook() {
+                Ok(hook) => hook,
+                Err(error) => {
+                    let _ = registered_sender.send(Err(error));
+                    return;
+                }
+            };
+
+            // Make sure the message queue exists before anyone tries to stop
+            // it.
+            //
+            // See https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-peekmessagew
+            let mut message = MSG::default();
+            unsafe {
+                PeekMessageW(
+                    ptr::addr_of_mut!(message),
+                    None,
+                    0,
+                    0,
+                    PM_NOREMOVE,
+                )
+            };
+
+            let _ = registered_sender.send(Ok(()));
+            drop(registered_sender);
 
             start_message_queue();
+
+            info!("Unregister global raw keyboard listener hook");
+
+            // See https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-unhookwindowshookex
+            let result = unsafe { UnhookWindowsHookEx(hook) };
+
+            info!("Unregister global raw keyboard listener result: {result:?}");
         });
 
         let thread_id = {
             // How to convert std library handle to windows-rs handle: https://stackoverflow.com/a/73574560
-            let thread_handle = HANDLE(message_queue.as_raw_handle() as isize);
+            let thread_handle = HANDLE(thread.as_raw_handle() as isize);
 
             // See https://learn.microsoft.com/en-us/windows/win32/api/processthreadsapi/nf-processthreadsapi-getthreadid
             unsafe { GetThreadId(thread_handle) }
         };
 
-        let keyboard_hook = keyboard_hook_receiver.recv().expect(
-            "Should not drop the sender before receiving the keyboard hook.",
+        let registered = registered_receiver.recv().expect(
+            "Should not drop the sender before the hook is registered.",
         );
 
-        // Prevent a message queue from not being stopped when the keyboard hook
-        // registration fails.
-        if keyboard_hook.is_err() {
-            Self::stop_message_queue(thread_id);
+        if let Err(error) = registered {
+            let _ = thread.join();
+            return Err(error);
         }
 
         Ok(Self {
-            hook: keyboard_hook?,
-            message_queue_thread: thread_id,
+            thread,
+            thread_id,
+            pointer: pointer::Pointer::spawn(),
         })
     }
 
-    /// Internal function used to terminate the message queue safely.
-    fn stop_message_queue(thread_id: u32) {
-        info!("Stop message queue {}", thread_id);
+    /// Whether the message queue already stopped by itself.
+    fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Terminates the message queue which unregisters the hook and waits for
+    /// the thread to finish.
+    fn stop(self) {
+        info!("Stop message queue {}", self.thread_id);
 
         // See post thread message https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-postthreadmessagew
         let result = unsafe {
-            PostThreadMessageW(thread_id, WM_APP + 1, WPARAM(0), LPARAM(0))
+            PostThreadMessageW(self.thread_id, WM_APP + 1, WPARAM(0), LPARAM(0))
         };
 
         info!("Stop message queue result {result:?}");
+
+        let _ = self.thread.join();
+        self.pointer.stop();
     }
 }
 
-/// Terminate the message queue associated with the raw hook.
-impl Drop for Handle {
-    fn drop(&mut self) {
-        Self::stop_message_queue(self.message_queue_thread);
+/// Registers the raw keyboard input hook for the current thread.
+fn register_hook() -> Result<HHOOK, HandleError> {
+    info!("Register global keyboard listener hook.");
+
+    let register_result = unsafe {
+        // See https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-setwindowshookexw
+        SetWindowsHookExW(
+            WH_KEYBOARD_LL,
+            Some(raw_keyboard_input_hook),
+            HMODULE(0),
+            0,
+        )
+    };
+
+    match register_result {
+        Ok(hook) => {
+            info!(
+                "Successfully registered the global keyboard listener hook ({hook:?})."
+            );
+            Ok(hook)
+        }
+        Err(error) => Err(HandleError::RegistrationFailed(format!(
+            "Trying to register a global keyboard listener failed: {} ({})",
+            error.message().to_string_lossy(),
+            error.code()
+        ))),
     }
 }
 
@@ -157,167 +365,227 @@ fn start_message_queue() {
     );
 }
 
-/// Wrapper around a native hook handle that manages the resources associated
-/// with it such as the event processor and makes sure they get cleaned up when
-/// this hook gets destroyed.
+/// Contexts of all registered handles sorted by descending priority. The
+/// native hook doesn't carry any user data so this is the only way to reach
+/// them from the raw keyboard input hook.
+static CONTEXTS: Mutex<Vec<Arc<Context>>> = Mutex::new(vec![]);
+
+/// Thread running the native hook while at least one handle is registered.
+/// Always locked before [`CONTEXTS`].
+static HOOK_THREAD: Mutex<Option<HookThread>> = Mutex::new(None);
+
+/// See microsoft documentation on [lowlevelkeyboardproc](https://learn.microsoft.com/en-us/windows/win32/winmsg/lowlevelkeyboardproc).
 ///
-/// Without additionally starting a message queue registering this hook won't do
-/// anything. See [`Handle::register`]
-struct ManagedHook(HHOOK);
+/// Times each call from receiving the event until returning, the actual work
+/// is done by [`handle_raw_keyboard_input`]. The latency is recorded for every
+/// context that processed the event and the event is logged together with its
+/// timings after the work is done.
+unsafe extern "system" fn raw_keyboard_input_hook(
+    code: i32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    let mut stopwatch = Stopwatch::start();
+    let (result, dispatch) =
+        handle_raw_keyboard_input(code, wparam, lparam, &mut stopwatch);
 
-impl ManagedHook {
-    /// Tries to register a keyboard hook with the event processor.
-    ///
-    /// # Errors
-    ///
-    /// Registration can fail if another keyboard hook is currently already
-    /// registered or if the windows [`set hook`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-setwindowshookexw)
-    /// call fails.
-    pub fn register(
-        associated_event_processor: EventProcessor,
-    ) -> Result<Self, HandleError> {
-        info!("Register global keyboard listener hook.");
+    let sample = stopwatch.stop();
 
-        let mut keyboard_hook_event_processor = EVENT_PROCESSOR
-            .lock()
-            .expect("Global hook doesn't panic so it can't poison the mutex");
+    if let Some(dispatch) = dispatch {
+        for context in &dispatch.processed_by {
+            context.record_latency(&sample);
+        }
 
-        if keyboard_hook_event_processor.is_some() {
-            return Err(HandleError::AnotherHookIsAlreadyInstalled);
+        if let Some(event_record) = dispatch.event_record {
+            logging::log_event(event_record, &sample);
         }
+    }
 
-        keyboard_hook_event_processor.replace(associated_event_processor);
+    result
+}
 
-        let register_result = unsafe {
-            // See https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-setwindowshookexw
-            SetWindowsHookExW(
-                WH_KEYBOARD_LL,
-                Some(raw_keyboard_input_hook),
-                HMODULE(0),
-                0,
-            )
-        };
+/// Translates, dispatches and applies the response to one raw keyboard event
+/// while timing each stage with the stopwatch. Also returns the outcome of the
+/// dispatch if there was one.
+unsafe fn handle_raw_keyboard_input(
+    code: i32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    stopwatch: &mut Stopwatch,
+) -> (LRESULT, Option<dispatch::Dispatch>) {
+    let default_behavior = || CallNextHookEx(HHOOK(0), code, wparam, lparam);
+
+    // As documented we can't handle any events that have a code lower than zero.
+    // We should instead pass them to the next hook and return their result.
+    if code < 0 {
+        return (default_behavior(), None);
+    }
+
+    // See https://learn.microsoft.com/en-us/windows/win32/winmsg/lowlevelkeyboardproc#lparam-in
+    let event_pointer: *const KBDLLHOOKSTRUCT = mem::transmute(lparam);
+
+    // The hook also receives the events it causes itself.
+    if translation::is_own_input(&*event_pointer) {
+        return (default_behavior(), None);
+    }
 
-        match register_result {
-            Ok(hook) => {
-                info!(
-                    "Successfully registered the global keyboard listener hook ({hook:?})."
-                );
-                Ok(Self(hook))
+    let event =
+        translation::to_abstract_event(wparam.0 as u32, &*event_pointer);
+    stopwatch.lap(Stage::Translation);
+
+    let contexts = CONTEXTS
+        .lock()
+        .expect("Raw keyboard input hook never panics and thus never poisons this mutex.")
+        .clone();
+
+    let dispatch = dispatch::dispatch(
+        &contexts,
+        event,
+        Some((*event_pointer).vkCode as u16),
+        translation::foreground_application,
+    );
+    stopwatch.lap(Stage::Processing);
+
+    let result = match (dispatch.response, &dispatch.responder) {
+        // Only the kill switch responds without a context and it blocks.
+        (ResponseAction::Block | ResponseAction::SwitchProfile(_), _)
+        | (ResponseAction::ReplaceWith(_) | ResponseAction::Mouse(..), None) => {
+            LRESULT(1)
+        }
+        (ResponseAction::ReplaceWith(key_combination), Some(responder)) => {
+            for input in translation::to_native_input_events(
+                key_combination,
+                dispatch.text_output,
+            )
+            .into_iter()
+            .flatten()
+            {
+                send_input(responder, input);
             }
-            Err(error) => {
-                // Remove the global event processor when registration fails.
-                let _ = keyboard_hook_event_processor.take();
-
-                Err(HandleError::RegistrationFailed(format!(
-                    "Trying to register a global keyboard listener failed: {} ({})",
-                    error.message().to_string_lossy(),
-                    error.code()
-                )))
+
+            stopwatch.lap(Stage::Output);
+            LRESULT(1)
+        }
+        (ResponseAction::Mouse(mouse_action, action), Some(responder)) => {
+            apply_mouse_action(responder, mouse_action, action);
+
+            stopwatch.lap(Stage::Output);
+            LRESULT(1)
+        }
+        (ResponseAction::DoNothing, _) => {
+            // Any of the contexts might block the key up later on.
+            for context in &dispatch.processed_by {
+                context
+                    .held_input()
+                    .track_key((*event_pointer).vkCode as u16, event.action);
             }
+
+            default_behavior()
         }
-    }
-}
+    };
 
-/// Unregisters the hook and makes sure all relevant resources get cleaned up.
-impl Drop for ManagedHook {
-    fn drop(&mut self) {
-        info!("Unregister global raw keyboard listener hook");
+    for (context, mouse_action) in &dispatch.ended_late {
+        apply_mouse_action(context, *mouse_action, Action::Release);
+    }
 
-        let result = unsafe {
-            // See https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-unhookwindowshookex
-            UnhookWindowsHookEx(self.0)
-        };
+    // Gives the keyboard back right away, the other contexts see the key downs
+    // again if they are still held.
+    for context in &dispatch.disabled {
+        release_pressed_keys(context);
+        release_mouse(context);
+    }
 
-        // Drop associated event processor
-        EVENT_PROCESSOR
-            .lock()
-            .expect("Global hook doesn't panic so it can't poison the mutex")
-            .take();
-
-        // Safety: Being able to lock the event processor means the keyboard
-        // input hook has finished it's last execution and won't get called
-        // another time because it was unregistered and thus accessing
-        // CURRENTLY_WRITING is safe.
-        //
-        // See UnhookWindowsHookEx documentation above for guarantee remark.
-        unsafe { CURRENTLY_WRITING = false };
-
-        info!("Unregister global raw keyboard listener result: {result:?}",);
+    // Nothing is left for the message queue to do.
+    if !dispatch.disabled.is_empty()
+        && contexts.iter().all(|context| context.disabled().is_some())
+    {
+        PostThreadMessageW(
+            GetCurrentThreadId(),
+            WM_APP + 1,
+            WPARAM(0),
+            LPARAM(0),
+        );
     }
+
+    (result, Some(dispatch))
 }
 
-/// The event processor currently associated with the raw keyboard input hook.
-static EVENT_PROCESSOR: Mutex<Option<EventProcessor>> = Mutex::new(None);
+/// Simulates the input and tracks the key for the context if the input was
+/// actually sent.
+unsafe fn send_input(context: &Context, input: INPUT) {
+    if SendInput(&[input], mem::size_of::<INPUT>() as i32) != 1 {
+        return;
+    }
 
-/// The raw keyboard input hook also receives events that it causes. This flag
-/// is used to ignore any events that occur while sending input events.
-static mut CURRENTLY_WRITING: bool = false;
+    if let Some((key_code, action)) = translation::input_key_code(&input) {
+        context.held_input().track_key(key_code, action);
+    }
+}
 
-/// See microsoft documentation on [lowlevelkeyboardproc](https://learn.microsoft.com/en-us/windows/win32/winmsg/lowlevelkeyboardproc).
-unsafe extern "system" fn raw_keyboard_input_hook(
-    code: i32,
-    wparam: WPARAM,
-    lparam: LPARAM,
-) -> LRESULT {
-    let default_behavior = || CallNextHookEx(HHOOK(0), code, wparam, lparam);
+/// Simulates a key up for every key the context holds. The hook ignores these
+/// key ups because they carry the [`translation::INPUT_MARKER`].
+unsafe fn release_pressed_keys(context: &Context) {
+    let pressed_keys = mem::take(&mut context.held_input().keys);
 
-    // As documented we can't handle any events that have a code lower than zero.
-    // We should instead pass them to the next hook and return their result.
-    if code < 0 {
-        return default_behavior();
+    if !pressed_keys.is_empty() {
+        info!("Release pressed keys {pressed_keys:?}");
     }
 
-    // Safety: The raw keyboard_input_hook is always called from the same thread
-    // that registered it and [`Handle::register`] takes care of ensuring only
-    // one raw keyboard input hook gets registered which guarantees exclusive
-    // access to CURRENTLY_WRITING.
-    if unsafe { CURRENTLY_WRITING } {
-        return default_behavior();
+    for key_code in pressed_keys {
+        SendInput(
+            &[translation::key_code_release_input(key_code)],
+            mem::size_of::<INPUT>() as i32,
+        );
     }
+}
 
-    let mut event_processor = EVENT_PROCESSOR
-        .lock()
-        .expect("Raw keyboard input hook never panics and thus never poisons this mutex.");
+/// Starts or ends the mouse action of the context. Movements are handed to the
+/// [`pointer`](pointer) thread, everything else is simulated right away.
+unsafe fn apply_mouse_action(
+    context: &Context,
+    mouse_action: MouseAction,
+    action: Action,
+) {
+    if let MouseAction::Move(direction) = mouse_action {
+        match action {
+            Action::Press => pointer::start_moving(context, direction),
+            Action::Release => pointer::stop_moving(context, direction),
+        }
 
-    if event_processor.is_none() {
-        error!("Invalid global state for raw keyboard input hook. (No associated event processor)");
-        return default_behavior();
+        return;
     }
 
-    // Safety: We do the check right above and return early if the even processor is none.
-    let event_processor = event_processor.as_mut().unwrap_unchecked();
+    let Some(input) = translation::mouse_action_to_input(mouse_action, action)
+    else {
+        return;
+    };
 
-    // See https://learn.microsoft.com/en-us/windows/win32/winmsg/lowlevelkeyboardproc#lparam-in
-    let event_pointer: *const KBDLLHOOKSTRUCT = mem::transmute(lparam);
+    if SendInput(&[input], mem::size_of::<INPUT>() as i32) != 1 {
+        return;
+    }
 
-    let event =
-        translation::to_abstract_event(wparam.0 as u32, &*event_pointer);
-    let change_request = event_processor.process(event);
+    if let MouseAction::Click(button) = mouse_action {
+        context.held_input().track_button(button, action);
+    }
+}
 
-    info!("{event:?} => {change_request:?}");
+/// Stops the pointer movement of the context and simulates a button up for
+/// every button it holds.
+unsafe fn release_mouse(context: &Context) {
+    pointer::stop_all(context);
 
-    match change_request {
-        ResponseAction::Block => LRESULT(1),
-        ResponseAction::ReplaceWith(key_combination) => {
-            // Safety: See above
-            unsafe {
-                CURRENTLY_WRITING = true;
-            }
+    let pressed_buttons = mem::take(&mut context.held_input().buttons);
 
-            for input in translation::to_native_input_events(key_combination)
-                .into_iter()
-                .flatten()
-            {
-                SendInput(&[input], mem::size_of::<INPUT>() as i32);
-            }
+    if !pressed_buttons.is_empty() {
+        info!("Release pressed mouse buttons {pressed_buttons:?}");
+    }
 
-            // Safety: See above
-            unsafe {
-                CURRENTLY_WRITING = false;
-            }
-            LRESULT(1)
+    for button in pressed_buttons {
+        if let Some(input) = translation::mouse_action_to_input(
+            MouseAction::Click(button),
+            Action::Release,
+        ) {
+            SendInput(&[input], mem::size_of::<INPUT>() as i32);
         }
-        ResponseAction::DoNothing => default_behavior(),
     }
 }
@@ -0,0 +1,154 @@
+//! Thread that moves the pointer while any [`mouse movement`](crate::mouse::MouseAction::Move)
+//! is held. It runs next to the message queue of the native hook and sleeps
+//! while there is nothing to move.
+//!
+//! Each context keeps its own [`movement`](crate::dispatch::HeldInput), the
+//! pointer moves by the sum of the movements of all registered contexts.
+
+use std::{
+    mem,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use windows::Win32::UI::Input::KeyboardAndMouse::{SendInput, INPUT};
+
+use log::info;
+
+use crate::{
+    dispatch::Context,
+    mouse::{Direction, MOVEMENT_INTERVAL},
+};
+
+use super::{translation, CONTEXTS};
+
+struct State {
+    stopped: bool,
+}
+
+/// Locked while checking the movements so that no change gets lost, see
+/// [`changed`].
+static STATE: Mutex<State> = Mutex::new(State { stopped: false });
+
+/// Wakes the thread up whenever the state or any movement changes.
+static CHANGED: Condvar = Condvar::new();
+
+fn state() -> MutexGuard<'static, State> {
+    STATE
+        .lock()
+        .expect("Pointer thread doesn't panic so it can't poison the mutex")
+}
+
+/// Handle of the running pointer thread.
+pub struct Pointer {
+    thread: JoinHandle<()>,
+}
+
+impl Pointer {
+    pub fn spawn() -> Self {
+        state().stopped = false;
+
+        Self {
+            thread: thread::spawn(run),
+        }
+    }
+
+    /// Stops moving and waits for the thread to finish.
+    pub fn stop(self) {
+        state().stopped = true;
+
+        CHANGED.notify_all();
+        let _ = self.thread.join();
+    }
+}
+
+pub fn start_moving(context: &Context, direction: Direction) {
+    context.held_input().movement.start(direction);
+    changed();
+}
+
+pub fn stop_moving(context: &Context, direction: Direction) {
+    context.held_input().movement.stop(direction);
+    changed();
+}
+
+pub fn stop_all(context: &Context) {
+    context.held_input().movement.stop_all();
+    changed();
+}
+
+/// Wakes the thread up after a movement changed. Waits until the thread
+/// checked the movements or sleeps so that it can't miss the change.
+fn changed() {
+    let _state = state();
+    CHANGED.notify_all();
+}
+
+/// Contexts of all registered handles, see [`CONTEXTS`].
+fn contexts() -> Vec<Arc<Context>> {
+    CONTEXTS
+        .lock()
+        .expect("Global hook doesn't panic so it can't poison the mutex")
+        .clone()
+}
+
+fn is_moving() -> bool {
+    contexts()
+        .iter()
+        .any(|context| context.held_input().movement.is_moving())
+}
+
+/// Advances the movements of all contexts and returns the sum of the pixels
+/// the pointer has to move.
+fn tick(elapsed: Duration) -> (i32, i32) {
+    contexts().iter().fold((0, 0), |(x, y), context| {
+        let (dx, dy) = context.held_input().movement.tick(elapsed);
+        (x + dx, y + dy)
+    })
+}
+
+fn run() {
+    info!("Running pointer thread.");
+
+    let mut state = state();
+    let mut last_tick = Instant::now();
+
+    while !state.stopped {
+        if !is_moving() {
+            state = CHANGED.wait(state).expect(
+                "Pointer thread doesn't panic so it can't poison the mutex",
+            );
+            last_tick = Instant::now();
+            continue;
+        }
+
+        state = CHANGED
+            .wait_timeout(state, MOVEMENT_INTERVAL)
+            .expect("Pointer thread doesn't panic so it can't poison the mutex")
+            .0;
+
+        let now = Instant::now();
+        let (dx, dy) = tick(now - last_tick);
+        last_tick = now;
+
+        if (dx, dy) == (0, 0) {
+            continue;
+        }
+
+        // Don't block the keyboard hook while sending the input.
+        drop(state);
+
+        // See https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-sendinput
+        unsafe {
+            SendInput(
+                &[translation::mouse_move_input(dx, dy)],
+                mem::size_of::<INPUT>() as i32,
+            );
+        }
+
+        state = self::state();
+    }
+
+    info!("Stopped pointer thread.");
+}
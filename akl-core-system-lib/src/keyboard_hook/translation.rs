@@ -3,28 +3,80 @@
 
 use windows::Win32::UI::{
     Input::KeyboardAndMouse::{
-        GetKeyboardLayout, GetKeyboardState, ToUnicodeEx, INPUT, INPUT_0,
-        INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS, KEYEVENTF_KEYUP,
-        KEYEVENTF_UNICODE, VIRTUAL_KEY,
+        GetKeyboardLayout, GetKeyboardState, ToUnicodeEx, VkKeyScanExW, INPUT,
+        INPUT_0, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT, KEYBD_EVENT_FLAGS,
+        KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, KEYEVENTF_UNICODE,
+        MOUSEEVENTF_HWHEEL, MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP,
+        MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_MOVE,
+        MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_WHEEL,
+        MOUSEINPUT, MOUSE_EVENT_FLAGS, VIRTUAL_KEY, VK_CONTROL, VK_MENU,
+        VK_SHIFT,
     },
     WindowsAndMessaging::{
-        GetMessageExtraInfo, KBDLLHOOKSTRUCT, WM_KEYDOWN, WM_KEYUP,
-        WM_SYSKEYDOWN, WM_SYSKEYUP,
+        GetClassNameW, GetForegroundWindow, GetWindowThreadProcessId,
+        InternalGetWindowText, KBDLLHOOKSTRUCT, LLKHF_EXTENDED, WHEEL_DELTA,
+        WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP,
     },
 };
 
 use crate::{
+    application::Application,
     event::{Action, Event},
-    key::{Key, KeyCombination, VirtualKey},
+    key::{Key, KeyCombination, TextOutput, VirtualKey},
+    mouse::{Direction, MouseAction, MouseButton},
 };
 
+/// Extra information attached to every simulated input so that the keyboard
+/// hook can recognize and ignore the events it caused itself, no matter from
+/// which thread they were sent.
+pub const INPUT_MARKER: usize = 0x414B_4C00;
+
+/// Whether the event was caused by an input of this library.
+pub fn is_own_input(event: &KBDLLHOOKSTRUCT) -> bool {
+    event.dwExtraInfo == INPUT_MARKER
+}
+
+/// Class name and title of the window in the foreground, both are empty if
+/// there is none.
+pub fn foreground_application() -> Application {
+    // Class names are limited to 256 characters, longer titles are cut off
+    // which only matters for extremely long document names.
+    let mut class = [0u16; 256];
+    let mut title = [0u16; 512];
+
+    // Unlike `GetWindowTextW` this never sends a message to the window, which
+    // would wait for the application to respond.
+    //
+    // See https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-internalgetwindowtext
+    let (class_length, title_length) = unsafe {
+        let window = GetForegroundWindow();
+
+        (
+            GetClassNameW(window, &mut class),
+            InternalGetWindowText(window, &mut title),
+        )
+    };
+
+    let text = |buffer: &[u16], length: i32| {
+        String::from_utf16_lossy(
+            &buffer[..usize::try_from(length).unwrap_or(0)],
+        )
+    };
+
+    Application {
+        class: text(&class, class_length),
+        title: text(&title, title_length),
+    }
+}
+
 /// Translates the windows native keyboard input event to an abstract platform
 /// independent [`event`](crate::event::Event) which can further be processed
 /// by an [`event processor`](crate::event::EventProcessor).
 ///
 /// See also [`to_character`] which is used if the parsing of a [`virtual key`](crate::key::VirtualKey)
-/// fails and the [`unicode replacement character`](https://compart.com/en/unicode/U+FFFD)
-/// which is set as the event key if that also fails.
+/// fails and the [`raw key`](Key::Raw) with the native virtual key code which
+/// is set as the event key if that also fails. Keys that produce text can
+/// still be [dispatched](crate::dispatch::dispatch) as raw keys.
 pub fn to_abstract_event(action: u32, event: &KBDLLHOOKSTRUCT) -> Event {
     let action = match action {
         WM_KEYDOWN | WM_SYSKEYDOWN=> Action::Press,
@@ -32,13 +84,20 @@ pub fn to_abstract_event(action: u32, event: &KBDLLHOOKSTRUCT) -> Event {
         _ => unreachable!("See https://learn.microsoft.com/en-us/windows/win32/winmsg/lowlevelkeyboardproc#wparam-in"),
     };
 
-    // Try to translate the character from the keyboard event or use the unicode
-    // replacement character "�" (https://compart.com/en/unicode/U+FFFD).
-    let key = TryInto::<VirtualKey>::try_into(VIRTUAL_KEY(event.vkCode as u16))
-        .map_or_else(
-            |_| to_character(event).unwrap_or('\u{FFFD}').into(),
-            Into::into,
-        );
+    // Try to translate the character from the keyboard event or keep the native
+    // key code so that different unknown keys can still be told apart.
+    // The extended flag tells keys like `NumpadEnter` apart from `Return`.
+    let key = VirtualKey::from_windows_key(
+        VIRTUAL_KEY(event.vkCode as u16),
+        event.flags.contains(LLKHF_EXTENDED),
+    )
+    .map_or_else(
+        |_| {
+            to_character(event)
+                .map_or(Key::Raw(event.vkCode as u16), Into::into)
+        },
+        Into::into,
+    );
 
     Event { action, key }
 }
@@ -106,68 +165,125 @@ fn to_character(event: &KBDLLHOOKSTRUCT) -> Option<char> {
 
 /// Translate a key combination to native input events that can be send using
 /// the [`SendInput`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-sendinput)
-/// windows api method.
+/// windows api method. [Text keys](`Key::Text`) are typed according to the
+/// [`text output`](TextOutput).
 ///
-/// Unfortunately a [text key](`Key::Text`) can not be simulated with only one
-/// native input event if this api should be able to handle all possible values
-/// that the char type can represent.
+/// Unfortunately a text key can not be simulated with only one native input
+/// event, either because the character needs two utf-16 code units or because
+/// the key that produces it needs up to three modifiers (Shift, Control and
+/// Alt) in the keyboard layout of the foreground window.
 ///
 /// Futhermore each `key down` event needs a corresponding `key up` event to
 /// correctly simulate a key press, so the number of required events doubles
-/// again landing on 16 total possible events that are sent for each key
+/// again landing on 32 total possible events that are sent for each key
 /// combination.
 pub fn to_native_input_events(
     key_combination: KeyCombination,
-) -> [Option<INPUT>; 16] {
-    let mut inputs: [Option<INPUT>; 16] = [None; 16];
+    text_output: TextOutput,
+) -> [Option<INPUT>; 32] {
+    let mut inputs: [Option<INPUT>; 32] = [None; 32];
     let mut down_input_counter = 0;
-    let mut up_input_counter = 15;
+    let mut up_input_counter = 31;
+
+    // Key ups are sent in reverse order so that the keys pressed first are
+    // released last.
+    let mut press = |down_input: INPUT, up_input: INPUT| {
+        inputs[down_input_counter] = Some(down_input);
+        down_input_counter += 1;
+
+        inputs[up_input_counter] = Some(up_input);
+        up_input_counter -= 1;
+    };
 
-    Into::<[Option<Key>; 4]>::into(&key_combination)
+    for key in Into::<[Option<Key>; 4]>::into(&key_combination)
         .iter()
         .flatten()
-        .for_each(|key| match *key {
+    {
+        match *key {
             Key::Text(character) => {
-                // key down events
-                let (down_input, maybe_down_input) =
-                    character_to_input(character, InputAction::KeyDown);
-
-                inputs[down_input_counter] = Some(down_input);
-                down_input_counter += 1;
-
-                if maybe_down_input.is_some() {
-                    inputs[down_input_counter] = maybe_down_input;
-                    down_input_counter += 1;
+                if text_output == TextOutput::Layout {
+                    if let Some(layout_keys) =
+                        character_to_layout_keys(character)
+                    {
+                        for key_code in layout_keys.into_iter().flatten() {
+                            press(
+                                key_code_input(key_code, InputAction::KeyDown),
+                                key_code_input(key_code, InputAction::KeyUp),
+                            );
+                        }
+
+                        continue;
+                    }
                 }
 
-                // key up events
+                let (down_input, maybe_down_input) =
+                    character_to_input(character, InputAction::KeyDown);
                 let (up_input, maybe_up_input) =
                     character_to_input(character, InputAction::KeyUp);
 
-                inputs[up_input_counter] = Some(up_input);
-                up_input_counter -= 1;
+                press(down_input, up_input);
 
-                if maybe_up_input.is_some() {
-                    inputs[up_input_counter] = maybe_up_input;
-                    up_input_counter -= 1;
+                if let (Some(down_input), Some(up_input)) =
+                    (maybe_down_input, maybe_up_input)
+                {
+                    press(down_input, up_input);
                 }
             }
-            Key::Virtual(virtual_key) => {
-                inputs[down_input_counter] = Some(virtual_key_to_input(
-                    virtual_key,
-                    InputAction::KeyDown,
-                ));
-                down_input_counter += 1;
-
-                inputs[up_input_counter] =
-                    Some(virtual_key_to_input(virtual_key, InputAction::KeyUp));
-                up_input_counter -= 1;
-            }
-        });
+            Key::Virtual(virtual_key) => press(
+                virtual_key_to_input(virtual_key, InputAction::KeyDown),
+                virtual_key_to_input(virtual_key, InputAction::KeyUp),
+            ),
+            Key::Raw(key_code) => press(
+                key_code_input(key_code, InputAction::KeyDown),
+                key_code_input(key_code, InputAction::KeyUp),
+            ),
+        }
+    }
 
     inputs
 }
 
+/// Looks up the key that produces the character in the keyboard layout of the
+/// foreground window with [VkKeyScanEx](https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-vkkeyscanexw).
+/// Returns the virtual key codes of the required modifiers followed by the key
+/// itself or `None` if the layout doesn't have the character.
+fn character_to_layout_keys(character: char) -> Option<[Option<u16>; 4]> {
+    let mut encoded_code_points_buffer = [0u16; 2];
+
+    // Characters outside of the basic multilingual plane aren't on any key.
+    let [code_point] = character.encode_utf16(&mut encoded_code_points_buffer)
+    else {
+        return None;
+    };
+
+    // The layout is chosen per thread and the hook thread doesn't receive the
+    // text, the application in the foreground does. Without a foreground
+    // window the thread id is zero which falls back to the hook thread.
+    //
+    // See https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getwindowthreadprocessid
+    // and https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getkeyboardlayout
+    let keyboard_layout = unsafe {
+        GetKeyboardLayout(GetWindowThreadProcessId(GetForegroundWindow(), None))
+    };
+    let result = unsafe { VkKeyScanExW(*code_point, keyboard_layout) };
+
+    // Both bytes are -1 if the character has no key.
+    if result == -1 {
+        return None;
+    }
+
+    let [key_code, shift_state] = result.to_le_bytes();
+    let modifier =
+        |bit: u8, key: VIRTUAL_KEY| (shift_state & bit != 0).then_some(key.0);
+
+    Some([
+        modifier(0b001, VK_SHIFT),
+        modifier(0b010, VK_CONTROL),
+        modifier(0b100, VK_MENU),
+        Some(u16::from(key_code)),
+    ])
+}
+
 /// Safe representation of key up and key down events that is also used to
 /// create the correct [`dwFlags`](https://learn.microsoft.com/en-us/windows/win32/api/winuser/ns-winuser-keybdinput)
 /// for each action.
@@ -195,6 +311,12 @@ impl InputAction {
 
 /// Creates a native keyboard input with the action for the virtual key.
 fn virtual_key_to_input(key: VirtualKey, input_action: InputAction) -> INPUT {
+    let mut flags = input_action.to_flags();
+
+    if key.is_extended() {
+        flags |= KEYEVENTF_EXTENDEDKEY;
+    }
+
     // See also:
     // https://learn.microsoft.com/en-us/windows/win32/api/winuser/ns-winuser-input
     // https://learn.microsoft.com/en-us/windows/win32/api/winuser/ns-winuser-keybdinput
@@ -203,7 +325,29 @@ fn virtual_key_to_input(key: VirtualKey, input_action: InputAction) -> INPUT {
         Anonymous: INPUT_0 {
             ki: KEYBDINPUT {
                 wVk: key.into(),
-                dwExtraInfo: unsafe { GetMessageExtraInfo() }.0 as usize,
+                dwExtraInfo: INPUT_MARKER,
+                dwFlags: flags,
+                ..Default::default()
+            },
+        },
+    }
+}
+
+/// Creates a native keyboard input that releases the key with the native
+/// virtual key code, which also works for keys that produce text.
+pub fn key_code_release_input(key_code: u16) -> INPUT {
+    key_code_input(key_code, InputAction::KeyUp)
+}
+
+/// Creates a native keyboard input with the action for the native virtual key
+/// code.
+fn key_code_input(key_code: u16, input_action: InputAction) -> INPUT {
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VIRTUAL_KEY(key_code),
+                dwExtraInfo: INPUT_MARKER,
                 dwFlags: input_action.to_flags(),
                 ..Default::default()
             },
@@ -211,6 +355,85 @@ fn virtual_key_to_input(key: VirtualKey, input_action: InputAction) -> INPUT {
     }
 }
 
+/// Native virtual key code and action of a simulated keyboard input. `None` for
+/// unicode input which doesn't press any virtual key.
+pub fn input_key_code(input: &INPUT) -> Option<(u16, Action)> {
+    if input.r#type != INPUT_KEYBOARD {
+        return None;
+    }
+
+    let keyboard_input = unsafe { input.Anonymous.ki };
+
+    if keyboard_input.wVk.0 == 0 {
+        return None;
+    }
+
+    let action = if keyboard_input.dwFlags.contains(KEYEVENTF_KEYUP) {
+        Action::Release
+    } else {
+        Action::Press
+    };
+
+    Some((keyboard_input.wVk.0, action))
+}
+
+/// Creates the native mouse input that starts or ends the click or scrolls.
+/// `None` for movements which are applied by the [`pointer`](super::pointer)
+/// thread and for releasing a scroll which doesn't do anything.
+pub fn mouse_action_to_input(
+    mouse_action: MouseAction,
+    action: Action,
+) -> Option<INPUT> {
+    let wheel_delta = WHEEL_DELTA as i32;
+
+    let (flags, data) = match (mouse_action, action) {
+        (MouseAction::Move(_), _)
+        | (MouseAction::Scroll(_), Action::Release) => return None,
+        (MouseAction::Click(button), Action::Press) => match button {
+            MouseButton::Left => (MOUSEEVENTF_LEFTDOWN, 0),
+            MouseButton::Middle => (MOUSEEVENTF_MIDDLEDOWN, 0),
+            MouseButton::Right => (MOUSEEVENTF_RIGHTDOWN, 0),
+        },
+        (MouseAction::Click(button), Action::Release) => match button {
+            MouseButton::Left => (MOUSEEVENTF_LEFTUP, 0),
+            MouseButton::Middle => (MOUSEEVENTF_MIDDLEUP, 0),
+            MouseButton::Right => (MOUSEEVENTF_RIGHTUP, 0),
+        },
+        // See https://learn.microsoft.com/en-us/windows/win32/api/winuser/ns-winuser-mouseinput#members
+        (MouseAction::Scroll(direction), Action::Press) => match direction {
+            Direction::Up => (MOUSEEVENTF_WHEEL, wheel_delta),
+            Direction::Down => (MOUSEEVENTF_WHEEL, -wheel_delta),
+            Direction::Left => (MOUSEEVENTF_HWHEEL, -wheel_delta),
+            Direction::Right => (MOUSEEVENTF_HWHEEL, wheel_delta),
+        },
+    };
+
+    Some(mouse_input(flags, 0, 0, data))
+}
+
+/// Creates a native mouse input that moves the pointer relative to its
+/// current position.
+pub fn mouse_move_input(dx: i32, dy: i32) -> INPUT {
+    mouse_input(MOUSEEVENTF_MOVE, dx, dy, 0)
+}
+
+fn mouse_input(flags: MOUSE_EVENT_FLAGS, dx: i32, dy: i32, data: i32) -> INPUT {
+    // See https://learn.microsoft.com/en-us/windows/win32/api/winuser/ns-winuser-mouseinput
+    INPUT {
+        r#type: INPUT_MOUSE,
+        Anonymous: INPUT_0 {
+            mi: MOUSEINPUT {
+                dx,
+                dy,
+                mouseData: data,
+                dwFlags: flags,
+                dwExtraInfo: INPUT_MARKER,
+                ..Default::default()
+            },
+        },
+    }
+}
+
 /// Creates native keyboard inputs needed to simulate pressing or releasing
 /// the character.
 ///
@@ -230,7 +453,7 @@ fn character_to_input(
                 wVk: VIRTUAL_KEY(0),
                 wScan: encoded_code_points[0],
                 dwFlags: input_action.to_unicode_flags(),
-                dwExtraInfo: unsafe { GetMessageExtraInfo() }.0 as usize,
+                dwExtraInfo: INPUT_MARKER,
                 ..Default::default()
             },
         },
@@ -244,7 +467,7 @@ fn character_to_input(
                     wVk: VIRTUAL_KEY(0),
                     wScan: encoded_code_points[1],
                     dwFlags: input_action.to_unicode_flags(),
-                    dwExtraInfo: unsafe { GetMessageExtraInfo() }.0 as usize,
+                    dwExtraInfo: INPUT_MARKER,
                     ..Default::default()
                 },
             },
@@ -258,6 +481,8 @@ fn character_to_input(
 
 #[cfg(test)]
 mod tests {
+    use windows::Win32::UI::Input::KeyboardAndMouse::VK_RETURN;
+
     use super::*;
 
     #[test]
@@ -281,7 +506,10 @@ mod tests {
         macro_rules! test_event_generation {
             ($($key: expr $(,)?)*) => {
                 let number_of_keys = <[()]>::len(&[$(replace_expr!($key ())),*]);
-                let inputs = to_native_input_events(kc!($($key), *));
+                let inputs = to_native_input_events(
+                    kc!($($key), *),
+                    TextOutput::Unicode,
+                );
 
                 assert_eq!(
                     inputs
@@ -313,6 +541,18 @@ mod tests {
         test_event_generation!(KEY_A, KEY_ESCAPE, KEY_B, KEY_RETURN);
     }
 
+    #[test]
+    fn test_layout_fallback() {
+        // Not on any key so it has to be sent as two unicode code units.
+        let combination = [Key::Text('😀')].as_slice().try_into().unwrap();
+        let inputs = to_native_input_events(combination, TextOutput::Layout);
+
+        assert_eq!(4, inputs.iter().flatten().count());
+        assert!(inputs.iter().flatten().all(|input| unsafe {
+            input.Anonymous.ki.dwFlags.contains(KEYEVENTF_UNICODE)
+        }));
+    }
+
     #[test]
     fn test_virtual_key_to_input() {
         macro_rules! test_virtual_key_to_input {
@@ -331,6 +571,33 @@ mod tests {
 
         test_virtual_key_to_input!(VirtualKey::CapsLock, InputAction::KeyDown);
         test_virtual_key_to_input!(VirtualKey::CapsLock, InputAction::KeyUp);
+
+        let input =
+            virtual_key_to_input(VirtualKey::NumpadEnter, InputAction::KeyDown);
+        let keyboard_input = unsafe { input.Anonymous.ki };
+        assert_eq!(VK_RETURN, keyboard_input.wVk);
+        assert_eq!(KEYEVENTF_EXTENDEDKEY, keyboard_input.dwFlags);
+    }
+
+    #[test]
+    fn test_input_key_code() {
+        let key_code = VirtualKey::LShift.to_windows_key();
+
+        assert!(matches!(
+            input_key_code(&virtual_key_to_input(
+                VirtualKey::LShift,
+                InputAction::KeyDown
+            )),
+            Some((code, Action::Press)) if code == key_code
+        ));
+        assert!(matches!(
+            input_key_code(&key_code_release_input(key_code)),
+            Some((code, Action::Release)) if code == key_code
+        ));
+        assert!(input_key_code(
+            &character_to_input('a', InputAction::KeyDown).0
+        )
+        .is_none());
     }
 
     #[test]
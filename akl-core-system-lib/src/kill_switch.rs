@@ -0,0 +1,128 @@
+//! Emergency combination that disables the virtual layer no matter how it is
+//! configured.
+//!
+//! A bad configuration can make the keyboard unusable, for example by using an
+//! essential key as switch key. The keyboard hook therefore passes every event
+//! to the [`KillSwitch`] before the [`event processor`](crate::event::EventProcessor)
+//! sees it and disables the virtual layer as soon as the whole combination is
+//! pressed.
+//!
+//! The event processor uses the same tracking to detect its
+//! [`pause combination`](crate::Configuration::pause_combination).
+
+use crate::{
+    event::{Action, Event},
+    key::{Key, KeyCombination, VirtualKey},
+};
+
+/// Combination that is used unless another one is configured.
+pub fn default_combination() -> KeyCombination {
+    [
+        Key::Virtual(VirtualKey::LControl),
+        Key::Virtual(VirtualKey::LAlt),
+        Key::Virtual(VirtualKey::LShift),
+        Key::Virtual(VirtualKey::Escape),
+    ]
+    .as_slice()
+    .try_into()
+    .expect("Static key combination should always be valid.")
+}
+
+/// Tracks which keys are held down independently of the event processor to
+/// detect the kill switch combination.
+#[derive(Debug, Clone)]
+pub struct KillSwitch {
+    combination: KeyCombination,
+    pressed: Vec<Key>,
+}
+
+impl KillSwitch {
+    pub fn new(combination: KeyCombination) -> Self {
+        Self {
+            combination,
+            pressed: vec![],
+        }
+    }
+
+    /// Whether the key is part of the combination.
+    pub fn contains(&self, key: Key) -> bool {
+        <[Option<Key>; 4]>::from(&self.combination).contains(&Some(key))
+    }
+
+    /// Tracks the event and returns `true` if it completes the combination.
+    /// Holding the last key doesn't trigger the kill switch again.
+    pub fn process(&mut self, event: Event) -> bool {
+        match event.action {
+            Action::Press => {
+                if self.pressed.contains(&event.key) {
+                    return false;
+                }
+
+                self.pressed.push(event.key);
+
+                let keys: [Option<Key>; 4] = (&self.combination).into();
+
+                self.contains(event.key)
+                    && keys
+                        .iter()
+                        .flatten()
+                        .all(|key| self.pressed.contains(key))
+            }
+            Action::Release => {
+                self.pressed.retain(|key| *key != event.key);
+                false
+            }
+        }
+    }
+}
+
+impl Default for KillSwitch {
+    fn default() -> Self {
+        Self::new(default_combination())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: impl Into<Key>) -> Event {
+        Event {
+            action: Action::Press,
+            key: key.into(),
+        }
+    }
+
+    fn release(key: impl Into<Key>) -> Event {
+        Event {
+            action: Action::Release,
+            key: key.into(),
+        }
+    }
+
+    #[test]
+    fn test_kill_switch() {
+        let mut kill_switch = KillSwitch::default();
+
+        assert!(!kill_switch.process(press(VirtualKey::LControl)));
+        assert!(!kill_switch.process(press(VirtualKey::LAlt)));
+        assert!(!kill_switch.process(press('a')));
+        assert!(!kill_switch.process(press(VirtualKey::LShift)));
+        assert!(kill_switch.process(press(VirtualKey::Escape)));
+
+        // Auto repeat of the last key.
+        assert!(!kill_switch.process(press(VirtualKey::Escape)));
+
+        assert!(!kill_switch.process(release(VirtualKey::LAlt)));
+        assert!(!kill_switch.process(press('b')));
+        assert!(kill_switch.process(press(VirtualKey::LAlt)));
+    }
+
+    #[test]
+    fn test_order_doesnt_matter() {
+        let mut kill_switch = KillSwitch::new("LControl+k".parse().unwrap());
+
+        assert!(!kill_switch.process(press('k')));
+        assert!(kill_switch.process(press(VirtualKey::LControl)));
+    }
+}
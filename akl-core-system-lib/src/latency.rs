@@ -0,0 +1,349 @@
+//! Measures how long the keyboard hook takes for each event.
+//!
+//! Windows silently removes low level hooks which take longer than
+//! `LowLevelHooksTimeout` so it's important to know how close the hook gets to
+//! that limit. Every backend times the stages of handling one event with a
+//! [`Stopwatch`] and [`records`](Latency::record) the result in fixed size
+//! [`histograms`](Histogram) so that measuring never allocates.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Number of linear sub buckets for each power of two. Four sub buckets mean
+/// that each reported value is at most 25% larger than the actual one.
+const SUB_BUCKETS: usize = 4;
+
+/// Values below [`SUB_BUCKETS`] get their own bucket, every power of two above
+/// that is split into [`SUB_BUCKETS`] buckets.
+const BUCKETS: usize = SUB_BUCKETS + (64 - 2) * SUB_BUCKETS;
+
+/// Log-linear histogram of durations with nanosecond resolution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: [0; BUCKETS],
+            count: 0,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    /// Adds the duration to the histogram.
+    pub fn record(&mut self, duration: Duration) {
+        let nanoseconds =
+            u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+
+        self.buckets[Self::bucket_index(nanoseconds)] += 1;
+        self.count += 1;
+        self.max = self.max.max(nanoseconds);
+    }
+
+    /// Number of recorded durations.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Longest recorded duration.
+    #[must_use]
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+
+    /// Smallest duration that is at least as long as `percentile` percent of
+    /// all recorded durations. Reports the upper bound of the bucket the
+    /// percentile falls into but never more than [`max`](Self::max).
+    #[must_use]
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let rank = ((percentile / 100.0 * self.count as f64).ceil() as u64)
+            .clamp(1, self.count);
+
+        let mut seen = 0;
+
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket;
+
+            if seen >= rank {
+                return Duration::from_nanos(
+                    Self::bucket_upper_bound(index).min(self.max),
+                );
+            }
+        }
+
+        self.max()
+    }
+
+    /// Summary of the median, 99th percentile and maximum.
+    #[must_use]
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            count: self.count,
+            p50: self.percentile(50.0),
+            p99: self.percentile(99.0),
+            max: self.max(),
+        }
+    }
+
+    fn bucket_index(value: u64) -> usize {
+        if value < SUB_BUCKETS as u64 {
+            #[allow(clippy::cast_possible_truncation)]
+            return value as usize;
+        }
+
+        // Position of the highest set bit, at least two because of the check
+        // above.
+        let exponent = 63 - value.leading_zeros() as usize;
+        #[allow(clippy::cast_possible_truncation)]
+        let sub_bucket = (value >> (exponent - 2)) as usize & (SUB_BUCKETS - 1);
+
+        SUB_BUCKETS + (exponent - 2) * SUB_BUCKETS + sub_bucket
+    }
+
+    fn bucket_upper_bound(index: usize) -> u64 {
+        if index < SUB_BUCKETS {
+            return index as u64;
+        }
+
+        let exponent = (index - SUB_BUCKETS) / SUB_BUCKETS + 2;
+        let sub_bucket = ((index - SUB_BUCKETS) % SUB_BUCKETS) as u64;
+        let width = 1u64 << (exponent - 2);
+
+        ((SUB_BUCKETS as u64 + sub_bucket) * width).saturating_add(width - 1)
+    }
+}
+
+/// Median, 99th percentile and maximum of a [`Histogram`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "p50 {:?}, p99 {:?}, max {:?} ({} events)",
+            self.p50, self.p99, self.max, self.count
+        )
+    }
+}
+
+/// The stages of handling one event in the keyboard hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Translating the native event into an [`Event`](crate::event::Event).
+    Translation,
+    /// Running the [`event processor`](crate::event::EventProcessor) and
+    /// reporting the result to the observer.
+    Processing,
+    /// Simulating the replacement, `SendInput` on windows.
+    Output,
+}
+
+/// Durations of all stages of one event that were timed by a [`Stopwatch`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    translation: Option<Duration>,
+    processing: Option<Duration>,
+    output: Option<Duration>,
+    total: Duration,
+}
+
+impl Sample {
+    /// Time it took to translate the event, `None` if it was ignored.
+    #[must_use]
+    pub fn translation(&self) -> Option<Duration> {
+        self.translation
+    }
+
+    #[must_use]
+    pub fn processing(&self) -> Option<Duration> {
+        self.processing
+    }
+
+    /// Time it took to simulate the replacement, `None` if there was none.
+    #[must_use]
+    pub fn output(&self) -> Option<Duration> {
+        self.output
+    }
+
+    /// Time from receiving the event until returning from the hook.
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+}
+
+/// Times the stages of handling one event. Started as soon as the hook
+/// receives the event.
+pub struct Stopwatch {
+    start: Instant,
+    last_lap: Instant,
+    sample: Sample,
+}
+
+impl Stopwatch {
+    #[must_use]
+    pub fn start() -> Self {
+        let now = Instant::now();
+
+        Self {
+            start: now,
+            last_lap: now,
+            sample: Sample::default(),
+        }
+    }
+
+    /// Ends the stage which started at the previous lap or the start of the
+    /// stopwatch.
+    pub fn lap(&mut self, stage: Stage) {
+        let now = Instant::now();
+        let duration = Some(now - self.last_lap);
+
+        match stage {
+            Stage::Translation => self.sample.translation = duration,
+            Stage::Processing => self.sample.processing = duration,
+            Stage::Output => self.sample.output = duration,
+        }
+
+        self.last_lap = now;
+    }
+
+    /// Stops the stopwatch right before the hook returns.
+    #[must_use]
+    pub fn stop(mut self) -> Sample {
+        self.sample.total = self.start.elapsed();
+        self.sample
+    }
+}
+
+/// Histograms for every stage and the whole time spent in the hook.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Latency {
+    pub translation: Histogram,
+    pub processing: Histogram,
+    pub output: Histogram,
+    pub total: Histogram,
+}
+
+impl Latency {
+    /// Records all timed stages of the sample. Samples without translation
+    /// belong to events the hook ignored (such as its own simulated input) and
+    /// are skipped.
+    pub fn record(&mut self, sample: &Sample) {
+        let Some(translation) = sample.translation else {
+            return;
+        };
+
+        self.translation.record(translation);
+
+        if let Some(processing) = sample.processing {
+            self.processing.record(processing);
+        }
+
+        if let Some(output) = sample.output {
+            self.output.record(output);
+        }
+
+        self.total.record(sample.total);
+    }
+}
+
+impl fmt::Display for Latency {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "total: {}; translation: {}; processing: {}; output: {}",
+            self.total.summary(),
+            self.translation.summary(),
+            self.processing.summary(),
+            self.output.summary()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_bounds() {
+        let mut previous_upper_bound = None;
+
+        for index in 0..BUCKETS {
+            let upper_bound = Histogram::bucket_upper_bound(index);
+
+            assert_eq!(index, Histogram::bucket_index(upper_bound));
+
+            if let Some(previous) = previous_upper_bound {
+                assert_eq!(index, Histogram::bucket_index(previous + 1));
+            }
+
+            previous_upper_bound = Some(upper_bound);
+        }
+
+        assert_eq!(u64::MAX, Histogram::bucket_upper_bound(BUCKETS - 1));
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut histogram = Histogram::default();
+
+        assert_eq!(LatencySummary::default(), histogram.summary());
+
+        for microseconds in 1..=100 {
+            histogram.record(Duration::from_micros(microseconds));
+        }
+
+        let summary = histogram.summary();
+
+        assert_eq!(100, summary.count);
+        assert_eq!(Duration::from_micros(100), summary.max);
+
+        // Reported values are at most 25% above the actual ones.
+        assert!(summary.p50 >= Duration::from_micros(50));
+        assert!(summary.p50 <= Duration::from_micros(63));
+        assert!(summary.p99 >= Duration::from_micros(99));
+        assert!(summary.p99 <= Duration::from_micros(100));
+    }
+
+    #[test]
+    fn test_stopwatch() {
+        let mut latency = Latency::default();
+
+        // Events that were ignored by the hook aren't recorded.
+        latency.record(&Stopwatch::start().stop());
+        assert_eq!(0, latency.total.count());
+
+        let mut stopwatch = Stopwatch::start();
+        stopwatch.lap(Stage::Translation);
+        stopwatch.lap(Stage::Processing);
+        latency.record(&stopwatch.stop());
+
+        assert_eq!(1, latency.total.count());
+        assert_eq!(1, latency.processing.count());
+        assert_eq!(0, latency.output.count());
+    }
+}
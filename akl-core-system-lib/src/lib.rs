@@ -16,20 +16,55 @@
 //! virtual layer needs. The akl.common package and ffi module are highly
 //! coupled and have to be modified together whenever one of them changes.
 //! Unfortunately this is also the case for the [`virtual key`](crate::key::VirtualKey).
+//!
+//! # Configuration files
+//!
+//! The crate is also built as a rust library so that tools can embed it. With
+//! the `config-serde` feature the [`config_format`](crate::config_format)
+//! module reads and writes configurations as toml, json or yaml and the
+//! [`compose`](crate::compose) module reads toml files that include other
+//! files. The application loads such files through the ffi as well.
 #![warn(clippy::pedantic)]
 #![allow(clippy::module_name_repetitions, rustdoc::private_intra_doc_links)]
 
+mod application;
+#[cfg(feature = "config-serde")]
+pub mod compose;
+#[cfg(feature = "config-serde")]
+pub mod config_format;
+mod debug_protocol;
+mod dispatch;
 mod event;
 mod ffi;
 mod key;
 mod keyboard_hook;
+mod kill_switch;
+mod latency;
+mod logging;
+mod mouse;
+mod observer;
+mod profile;
+mod scenario;
+mod statistics;
+mod watchdog;
 
-use std::collections;
+use std::{collections, mem, path::PathBuf};
 
 use thiserror::Error;
 
-use key::{Key, KeyCombination};
+use application::ApplicationMappings;
+use dispatch::DisableReason;
+use event::EventProcessor;
+use key::{Key, KeyCombination, TextOutput};
 use keyboard_hook::{Handle as KeyboardHookHandle, HandleError};
+use kill_switch::KillSwitch;
+use latency::Latency;
+#[cfg(debug_assertions)]
+use logging::{LogSink, LoggingConfiguration};
+use mouse::MouseAction;
+use observer::Observer;
+use profile::{ProfileError, Profiles};
+use statistics::{Statistics, StatisticsError};
 
 /// Represents any errors that can occur while interacting with the virtual
 /// layer.
@@ -43,64 +78,138 @@ pub enum AklError {
     AlreadyStopped,
     #[error("{0}")]
     KeyboardHookError(#[from] HandleError),
+    #[error("{0}")]
+    StatisticsError(#[from] StatisticsError),
+    #[error("Akl was disabled because {0} events in a row took too long to process.")]
+    WatchdogDisabled(u32),
+    #[error("{0}")]
+    ProfileError(#[from] ProfileError),
 }
 
 /// Configuration that is needed for the virtual layer to work.
-#[derive(Default, Clone)]
+///
+/// With the `config-serde` feature it can be read and written as toml, json or
+/// yaml, see the [`config_format`](crate::config_format) module.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "config-serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Configuration {
     /// Key that when pressed makes the virtual layer start to listen for key
     /// bindings and block all events from reaching any windows.
+    #[cfg_attr(
+        feature = "config-serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
     pub switch_key: Option<Key>,
     /// Default key combination that is invoked when the switch key is pressed
     /// and released without executing any key bindings.
+    #[cfg_attr(
+        feature = "config-serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
     pub default_combination: Option<KeyCombination>,
+    /// Combination that pauses the virtual layer and resumes it when pressed
+    /// again, which works without the switch key. While paused every other
+    /// event is passed through unchanged. Like the kill switch it's the same
+    /// for the whole virtual layer, switching profiles keeps the combination
+    /// of the configuration the layer was started with.
+    #[cfg_attr(
+        feature = "config-serde",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub pause_combination: Option<KeyCombination>,
     /// Defines the target and replacement key bindings which are matched
     /// against while the switch key is pressed.
+    #[cfg_attr(
+        feature = "config-serde",
+        serde(serialize_with = "config_format::sorted")
+    )]
     pub mappings: collections::HashMap<KeyCombination, KeyCombination>,
+    /// Like [`mappings`](Self::mappings) but the target is replaced with a
+    /// [`mouse action`](crate::mouse::MouseAction).
+    #[cfg_attr(
+        feature = "config-serde",
+        serde(serialize_with = "config_format::sorted")
+    )]
+    pub mouse_mappings: collections::HashMap<KeyCombination, MouseAction>,
+    /// Like [`mappings`](Self::mappings) but the target switches to the
+    /// [`profile`](crate::profile) with the name.
+    #[cfg_attr(
+        feature = "config-serde",
+        serde(
+            serialize_with = "config_format::sorted",
+            skip_serializing_if = "collections::HashMap::is_empty"
+        )
+    )]
+    pub profile_mappings: collections::HashMap<KeyCombination, String>,
+    /// Mappings that take precedence over [`mappings`](Self::mappings) while
+    /// a matching window is in the foreground, the first matching set wins.
+    /// See the [`application`](crate::application) module.
+    #[cfg_attr(
+        feature = "config-serde",
+        serde(skip_serializing_if = "Vec::is_empty")
+    )]
+    pub application_mappings: Vec<ApplicationMappings>,
+    /// How replacements simulate text keys.
+    pub text_output: TextOutput,
 }
 
 /// High level abstraction over the interactions with the underlying platform
 /// specific virtual layer.
 pub struct AnotherKeyboardLayer {
     pub configuration: Configuration,
+    /// Named configurations the virtual layer can switch between while it is
+    /// running, see [`switch_profile`](Self::switch_profile). Changes only
+    /// reach the running layer once it is started again.
+    pub profiles: Profiles,
+    /// Combination that immediately disables the virtual layer together with
+    /// every other running one. Kept separate from the configuration so that
+    /// it works even if the configuration is broken. See the
+    /// [`kill_switch`](crate::kill_switch) module.
+    pub kill_switch: KeyCombination,
+    /// Decides which of several running virtual layers sees an event first,
+    /// higher values come first. See the [`dispatch`](crate::dispatch) module.
+    pub priority: i32,
     keyboard_hook_handle: Option<KeyboardHookHandle>,
+    /// Whether the next start begins paused, the running layer keeps its own.
+    paused: bool,
+    observer: Option<Observer>,
+    /// Statistics of all previous runs that aren't in the statistics file
+    /// yet, the running layer keeps its own.
+    statistics: Statistics,
+    /// Statistics the statistics file held when it was set or last saved.
+    persisted_statistics: Statistics,
+    statistics_file: Option<PathBuf>,
 }
 
 impl AnotherKeyboardLayer {
     /// Creates a new akl that has to be configured before [`starting`](Self::start())
     /// it. Information about the configuration [`here`](crate::Configuration).
     fn new() -> Self {
+        // Keeps the debug server working out of the box for debug builds as
+        // long as logging wasn't configured explicitly.
         #[cfg(debug_assertions)]
-        {
-            use log::LevelFilter;
-            use simplelog::{ConfigBuilder, ThreadLogMode, WriteLogger};
-
-            if let Ok(connection) =
-                std::net::TcpStream::connect("127.0.0.1:7777")
-            {
-                let config = {
-                    match ConfigBuilder::new()
-                        .set_thread_level(LevelFilter::Error)
-                        .set_thread_mode(ThreadLogMode::Both)
-                        .set_target_level(LevelFilter::Error)
-                        .set_time_offset_to_local()
-                    {
-                        Ok(config_builder) | Err(config_builder) => {
-                            config_builder.build()
-                        }
-                    }
-                };
-
-                // Errors if the global logger is already initialized. Can be
-                // ignored safely without any consequences.
-                let _ =
-                    WriteLogger::init(LevelFilter::Trace, config, connection);
-            }
+        if !logging::is_configured() {
+            let _ = logging::configure(Some(&LoggingConfiguration {
+                sink: LogSink::Tcp(logging::DEBUG_SERVER_ADDRESS.to_owned()),
+                level: log::LevelFilter::Trace,
+            }));
         }
 
         Self {
             configuration: Configuration::default(),
+            profiles: Profiles::default(),
+            kill_switch: kill_switch::default_combination(),
+            priority: 0,
             keyboard_hook_handle: Option::default(),
+            paused: false,
+            observer: Option::default(),
+            statistics: Statistics::default(),
+            persisted_statistics: Statistics::default(),
+            statistics_file: Option::default(),
         }
     }
 
@@ -112,10 +221,38 @@ impl AnotherKeyboardLayer {
         self.configuration.switch_key.is_none()
     }
 
-    /// Checks if the native platform specific virtual layer is running.
+    /// Checks if the native platform specific virtual layer is running. This
+    /// is no longer the case after it disabled itself, see
+    /// [`check_health`](Self::check_health).
     #[must_use]
     pub fn is_running(&self) -> bool {
-        self.keyboard_hook_handle.is_some()
+        self.running_keyboard_hook_handle().is_some()
+    }
+
+    fn running_keyboard_hook_handle(&self) -> Option<&KeyboardHookHandle> {
+        self.keyboard_hook_handle
+            .as_ref()
+            .filter(|handle| handle.disabled().is_none())
+    }
+
+    /// Checks whether the virtual layer disabled itself because of an error.
+    /// Being disabled by the kill switch isn't an error.
+    ///
+    /// # Errors
+    ///
+    /// - [`AklError::WatchdogDisabled`] => If processing events took too long
+    ///   too often in a row
+    pub fn check_health(&self) -> Result<(), AklError> {
+        match self
+            .keyboard_hook_handle
+            .as_ref()
+            .and_then(KeyboardHookHandle::disabled)
+        {
+            Some(DisableReason::Watchdog { overruns }) => {
+                Err(AklError::WatchdogDisabled(overruns))
+            }
+            Some(DisableReason::KillSwitch) | None => Ok(()),
+        }
     }
 
     /// Starts the native virtual layer with a copy of the configuration.
@@ -126,6 +263,8 @@ impl AnotherKeyboardLayer {
     /// returns `true`
     /// - [`AklError::AlreadyRunning`] => If [`is_running()`](Self::is_running())
     /// returns `true`
+    /// - [`AklError::ProfileError`] => If a profile has no switch key or a
+    ///   profile mapping switches to a profile that doesn't exist
     pub fn start(&mut self) -> Result<(), AklError> {
         if self.is_not_configured() {
             return Err(AklError::NotConfigured);
@@ -135,28 +274,218 @@ impl AnotherKeyboardLayer {
             return Err(AklError::AlreadyRunning);
         }
 
-        // Configuration is valid so .into() won't panic.
+        self.profiles.validate(&self.configuration)?;
+
+        // Only a handle that disabled itself can be left.
+        self.release_keyboard_hook();
+
+        // Configuration is valid so this won't panic.
+        let mut event_processor = EventProcessor::with_profiles(
+            self.configuration.clone(),
+            &self.profiles,
+        );
+        event_processor.set_paused(self.paused);
+
         self.keyboard_hook_handle = Some(KeyboardHookHandle::register(
-            self.configuration.clone().into(),
+            event_processor,
+            KillSwitch::new(self.kill_switch),
+            self.observer.as_ref().map(Observer::sender),
+            self.priority,
+            self.configuration.text_output,
+            self.profiles.clone(),
         )?);
 
         Ok(())
     }
 
-    /// Stops the currently running native virtual layer.
+    /// Makes the profile the current configuration. A running virtual layer
+    /// switches to it right away and keeps blocking the keys that are still
+    /// held down until they are released.
+    ///
+    /// # Errors
+    ///
+    /// - [`AklError::ProfileError`] => If there is no such profile, it has no
+    ///   switch key or it was saved after the running layer was started
+    pub fn switch_profile(&mut self, name: &str) -> Result<(), AklError> {
+        // The running layer must not switch to a profile that can't become
+        // the configuration as well.
+        self.profiles.check(name)?;
+
+        if let Some(handle) = self.running_keyboard_hook_handle() {
+            handle.switch_profile(name)?;
+        }
+
+        self.configuration = self.profiles.activate(name)?.clone();
+
+        Ok(())
+    }
+
+    /// Pauses the virtual layer so that it passes every event through except
+    /// the [`pause combination`](Configuration::pause_combination), or
+    /// resumes it. Unlike [`stop`](Self::stop) the native virtual layer stays
+    /// registered and keeps its state. Keys held down on the virtual layer
+    /// stay blocked until they are released.
+    pub fn set_paused(&mut self, paused: bool) {
+        if let Some(handle) = self.running_keyboard_hook_handle() {
+            handle.set_paused(paused);
+        }
+
+        self.paused = paused;
+    }
+
+    /// Whether the virtual layer is paused. The running virtual layer might
+    /// have been toggled with the pause combination.
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.running_keyboard_hook_handle()
+            .map_or(self.paused, KeyboardHookHandle::is_paused)
+    }
+
+    /// Name of the active profile. The running virtual layer might have
+    /// switched it with a profile mapping. `None` if no profile was activated
+    /// yet.
+    #[must_use]
+    pub fn active_profile(&self) -> Option<String> {
+        match self.running_keyboard_hook_handle() {
+            Some(handle) => handle.active_profile(),
+            None => self.profiles.active().map(str::to_owned),
+        }
+    }
+
+    /// Replaces the observer which gets notified about every processed event
+    /// and the response to it and once the virtual layer disabled itself.
+    /// `None` removes the current observer.
+    ///
+    /// # Errors
+    ///
+    /// - [`AklError::AlreadyRunning`] => If [`is_running()`](Self::is_running())
+    ///   returns `true`
+    pub fn set_observer(
+        &mut self,
+        observer: Option<Observer>,
+    ) -> Result<(), AklError> {
+        if self.is_running() {
+            return Err(AklError::AlreadyRunning);
+        }
+
+        self.observer = observer;
+
+        Ok(())
+    }
+
+    /// Stops the currently running native virtual layer. Also releases the
+    /// remaining resources of a virtual layer that disabled itself.
     ///
     /// # Errors
     ///
     /// - [`AklError::AlreadyStopped`] => If [`is_running`](Self::is_running())
     /// returns `false`
     pub fn stop(&mut self) -> Result<(), AklError> {
-        if !self.is_running() {
+        let was_running = self.is_running();
+
+        self.release_keyboard_hook();
+
+        if !was_running {
             return Err(AklError::AlreadyStopped);
         }
 
-        let handle = self.keyboard_hook_handle.take();
+        Ok(())
+    }
+
+    /// Drops the keyboard hook handle if there is one and keeps its
+    /// statistics and the profile it switched to.
+    fn release_keyboard_hook(&mut self) {
+        let Some(handle) = self.keyboard_hook_handle.take() else {
+            return;
+        };
+
+        self.statistics.merge(&handle.statistics());
+        self.paused = handle.is_paused();
+
+        // Changes of the configuration since the start are kept unless the
+        // running layer switched to a profile, the next start continues with
+        // the profile it ended with. A profile that can't be activated anymore
+        // keeps the configuration as it is.
+        if let (true, Some(name)) =
+            (handle.has_switched_profile(), handle.active_profile())
+        {
+            if let Ok(configuration) = self.profiles.activate(&name) {
+                self.configuration = configuration.clone();
+            }
+        }
+
         drop(handle);
 
+        // Stopping itself was successful, failing to persist the statistics
+        // shouldn't change that. See `save_statistics` to handle this error.
+        let _ = self.save_statistics();
+    }
+
+    /// Snapshot of the usage statistics over all runs including the currently
+    /// running one.
+    #[must_use]
+    pub fn statistics(&self) -> Statistics {
+        let mut statistics = self.persisted_statistics.clone();
+        statistics.merge(&self.statistics);
+
+        if let Some(handle) = &self.keyboard_hook_handle {
+            statistics.merge(&handle.statistics());
+        }
+
+        statistics
+    }
+
+    /// Snapshot of how long the keyboard hook took for each event since the
+    /// virtual layer was started. Empty if it isn't running.
+    #[must_use]
+    pub fn latency(&self) -> Latency {
+        self.keyboard_hook_handle
+            .as_ref()
+            .map(KeyboardHookHandle::latency)
+            .unwrap_or_default()
+    }
+
+    /// Loads the statistics persisted in the file in place of the ones of the
+    /// previous file, statistics that weren't saved yet are kept. From now on
+    /// the statistics are saved to this file every time the virtual layer is
+    /// stopped. `None` stops persisting the statistics and setting the same
+    /// file again does nothing.
+    ///
+    /// # Errors
+    ///
+    /// - [`AklError::StatisticsError`] => If the file exists but can't be read
+    ///   or parsed
+    pub fn set_statistics_file(
+        &mut self,
+        path: Option<PathBuf>,
+    ) -> Result<(), AklError> {
+        if path == self.statistics_file {
+            return Ok(());
+        }
+
+        self.persisted_statistics = match &path {
+            Some(path) => Statistics::load(path)?,
+            None => Statistics::default(),
+        };
+        self.statistics_file = path;
+
+        Ok(())
+    }
+
+    /// Writes the current statistics to the statistics file if one is set.
+    ///
+    /// # Errors
+    ///
+    /// - [`AklError::StatisticsError`] => If the file can't be written
+    pub fn save_statistics(&mut self) -> Result<(), AklError> {
+        let Some(path) = &self.statistics_file else {
+            return Ok(());
+        };
+
+        self.statistics().save(path)?;
+        self.persisted_statistics
+            .merge(&mem::take(&mut self.statistics));
+
         Ok(())
     }
 }
@@ -165,8 +494,6 @@ impl AnotherKeyboardLayer {
 /// sure any resources associated with the native virtual layer get released.
 impl Drop for AnotherKeyboardLayer {
     fn drop(&mut self) {
-        if self.is_running() {
-            let _ = self.stop();
-        }
+        let _ = self.stop();
     }
 }
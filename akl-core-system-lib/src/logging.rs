@@ -0,0 +1,629 @@
+//! Runtime configurable logging so that logs can also be collected from
+//! release builds.
+//!
+//! A single dispatching logger is installed the first time logging gets
+//! [`configured`](configure) and forwards all records to the current
+//! [`sink`](LogSink). Until then and after logging is turned off again every
+//! log call is skipped right away because the maximum level is `Off`.
+//!
+//! Note that the [`Trace`](LevelFilter::Trace) level contains every processed
+//! key event. The tcp sink sends structured
+//! [`records`](crate::debug_protocol::Record) instead of plain lines so that
+//! the debug server can show the state of the virtual layer.
+//!
+//! Since events are logged from within the keyboard hook no sink ever writes
+//! to its destination directly. Complete lines are put into a bounded
+//! [`queue`](LOG_QUEUE_CAPACITY) instead and a separate writer thread takes
+//! them out. If the destination can't keep up, e.g. because the debug server
+//! stopped reading, the queue fills up and further lines are dropped instead
+//! of delaying any input.
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    net::TcpStream,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        mpsc::{self, SyncSender},
+        Mutex, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use simplelog::{ConfigBuilder, ThreadLogMode, WriteLogger};
+use thiserror::Error;
+
+use crate::{
+    debug_protocol::{self, EventRecord, LogRecord, Timings},
+    event::{Event, EventProcessor, ResponseAction},
+    latency::Sample,
+};
+
+/// Address of the debug server. See `debug-server.rs`.
+pub const DEBUG_SERVER_ADDRESS: &str = "127.0.0.1:7777";
+
+/// Size at which a log file gets rotated.
+pub const MAX_LOG_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Number of rotated log files (`<path>.1`, `<path>.2`, ...) that are kept in
+/// addition to the current one.
+pub const MAX_ROTATED_LOG_FILES: usize = 3;
+
+/// Maximum number of lines that are waiting for the writer thread at the same
+/// time before new ones are dropped.
+pub const LOG_QUEUE_CAPACITY: usize = 1024;
+
+/// How long the writer thread waits for a tcp connection to accept a line
+/// before giving up on it.
+pub const TCP_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Destination for all log records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogSink {
+    /// Plain lines on the standard error output. This is also the sink to
+    /// use when running as a service, the service wrapper or the console the
+    /// program was started from decides where the lines end up.
+    Stderr,
+    /// File which is rotated when it gets bigger than [`MAX_LOG_FILE_SIZE`].
+    File(PathBuf),
+    /// Tcp connection, usually to the debug server.
+    Tcp(String),
+}
+
+#[derive(Error, Debug)]
+pub enum LoggingError {
+    #[error(
+        "Unknown log sink \"{0}\" (valid: stderr, file:<path>, tcp:<address>)."
+    )]
+    UnknownSink(String),
+    #[error("Couldn't open the log sink: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Parses `stderr`, `file:<path>` or `tcp:<address>`.
+/// A `tcp` without address connects to the
+/// [`debug server`](DEBUG_SERVER_ADDRESS).
+impl FromStr for LogSink {
+    type Err = LoggingError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.split_once(':') {
+            None if raw == "stderr" => Ok(Self::Stderr),
+            None if raw == "tcp" => Ok(Self::Tcp(DEBUG_SERVER_ADDRESS.into())),
+            Some(("file", path)) if !path.is_empty() => {
+                Ok(Self::File(path.into()))
+            }
+            Some(("tcp", address)) if !address.is_empty() => {
+                Ok(Self::Tcp(address.into()))
+            }
+            _ => Err(LoggingError::UnknownSink(raw.to_owned())),
+        }
+    }
+}
+
+/// Which sink log records are written to and which levels are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggingConfiguration {
+    pub sink: LogSink,
+    pub level: LevelFilter,
+}
+
+/// A logger that writes plain lines or one that writes structured records.
+enum Sink {
+    Text(Box<dyn Log>),
+    Structured(StructuredLogger),
+}
+
+impl Sink {
+    fn logger(&self) -> &dyn Log {
+        match self {
+            Self::Text(logger) => logger.as_ref(),
+            Self::Structured(logger) => logger,
+        }
+    }
+}
+
+/// The logger that is currently receiving records from the dispatcher.
+static SINK: RwLock<Option<Sink>> = RwLock::new(None);
+
+/// Forwards every record to the current [`SINK`].
+struct Dispatcher;
+
+static DISPATCHER: Dispatcher = Dispatcher;
+
+impl Log for Dispatcher {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if let Ok(sink) = SINK.read() {
+            if let Some(sink) = sink.as_ref() {
+                sink.logger().log(record);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(sink) = SINK.read() {
+            if let Some(sink) = sink.as_ref() {
+                sink.logger().flush();
+            }
+        }
+    }
+}
+
+/// Replaces the current sink and level, `None` turns logging off. Can be
+/// called any number of times.
+///
+/// # Errors
+///
+/// - [`LoggingError::Io`] => If the file can't be opened or the tcp connection
+///   can't be established, the previous configuration stays active
+pub fn configure(
+    configuration: Option<&LoggingConfiguration>,
+) -> Result<(), LoggingError> {
+    // Fails if the dispatcher is already installed which is expected for
+    // every call but the first.
+    let _ = log::set_logger(&DISPATCHER);
+
+    let sink = configuration.map(create_sink).transpose()?;
+    let level = configuration.map_or(LevelFilter::Off, |it| it.level);
+
+    let mut current_sink = SINK
+        .write()
+        .expect("Loggers don't panic so they can't poison the lock.");
+
+    if let Some(previous_sink) = current_sink.take() {
+        previous_sink.logger().flush();
+    }
+
+    *current_sink = sink;
+    log::set_max_level(level);
+
+    Ok(())
+}
+
+/// Whether any sink is configured at the moment.
+pub fn is_configured() -> bool {
+    SINK.read().is_ok_and(|sink| sink.is_some())
+}
+
+/// Describes the processed event and the resulting state of the virtual layer
+/// for [`log_event`]. Only worth calling if the `Trace` level is enabled.
+pub fn event_record(
+    event: Event,
+    response: ResponseAction,
+    event_processor: &EventProcessor,
+) -> EventRecord {
+    EventRecord {
+        action: event.action.to_string(),
+        key: event.key.to_string(),
+        response: response.to_string(),
+        layer_active: event_processor.is_layer_active(),
+        layer_keys: event_processor
+            .layer_keys()
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        timings: Timings::default(),
+    }
+}
+
+/// Logs the event with the timings of the sample on the `Trace` level. Sinks
+/// for the debug server receive the whole record, all others a single line.
+/// Only queues the record, so it is safe to call from the keyboard hook.
+pub fn log_event(mut record: EventRecord, sample: &Sample) {
+    let nanoseconds = |duration: std::time::Duration| {
+        u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+    };
+
+    record.timings = Timings {
+        translation: sample.translation().map(nanoseconds),
+        processing: sample.processing().map(nanoseconds),
+        output: sample.output().map(nanoseconds),
+        total: nanoseconds(sample.total()),
+    };
+
+    let Ok(sink) = SINK.read() else {
+        return;
+    };
+
+    match sink.as_ref() {
+        Some(Sink::Structured(logger)) if logger.level >= Level::Trace => {
+            logger.write(&debug_protocol::Record::Event(record));
+        }
+        Some(Sink::Text(logger)) => logger.log(
+            &Record::builder()
+                .level(Level::Trace)
+                .target(module_path!())
+                .args(format_args!(
+                    "{} {} => {} ({:?})",
+                    record.action,
+                    record.key,
+                    record.response,
+                    sample.total()
+                ))
+                .build(),
+        ),
+        _ => {}
+    }
+}
+
+fn create_sink(
+    configuration: &LoggingConfiguration,
+) -> Result<Sink, LoggingError> {
+    let config = match ConfigBuilder::new()
+        .set_thread_level(LevelFilter::Error)
+        .set_thread_mode(ThreadLogMode::Both)
+        .set_target_level(LevelFilter::Error)
+        .set_time_offset_to_local()
+    {
+        Ok(config_builder) | Err(config_builder) => config_builder.build(),
+    };
+
+    let level = configuration.level;
+
+    Ok(match &configuration.sink {
+        LogSink::Stderr => Sink::Text(WriteLogger::new(
+            level,
+            config,
+            QueuedWriter::new(io::stderr()),
+        )),
+        LogSink::File(path) => Sink::Text(WriteLogger::new(
+            level,
+            config,
+            QueuedWriter::new(RotatingFile::open(
+                path.clone(),
+                MAX_LOG_FILE_SIZE,
+            )?),
+        )),
+        LogSink::Tcp(address) => {
+            let stream = TcpStream::connect(address)?;
+            stream.set_write_timeout(Some(TCP_WRITE_TIMEOUT))?;
+
+            Sink::Structured(StructuredLogger::new(
+                level,
+                QueuedWriter::new(stream),
+            ))
+        }
+    })
+}
+
+/// Collects everything that is written until a line is complete and hands
+/// it to a writer thread without ever blocking. The line is dropped if
+/// [`LOG_QUEUE_CAPACITY`] lines are already waiting.
+///
+/// Dropping the writer waits until all queued lines are written.
+struct QueuedWriter {
+    line: Vec<u8>,
+    sender: Option<SyncSender<Vec<u8>>>,
+    writer_thread: Option<JoinHandle<()>>,
+}
+
+impl QueuedWriter {
+    /// Starts the writer thread which writes every line to the destination in
+    /// the order they were queued.
+    fn new(mut destination: impl Write + Send + 'static) -> Self {
+        let (sender, receiver) =
+            mpsc::sync_channel::<Vec<u8>>(LOG_QUEUE_CAPACITY);
+
+        let writer_thread = thread::spawn(move || {
+            for line in receiver {
+                // There is nowhere left to report a failing log sink.
+                let _ = destination.write_all(&line);
+                let _ = destination.flush();
+            }
+        });
+
+        Self {
+            line: Vec::new(),
+            sender: Some(sender),
+            writer_thread: Some(writer_thread),
+        }
+    }
+
+    fn queue_line(&mut self) {
+        let line = std::mem::take(&mut self.line);
+
+        if let (false, Some(sender)) = (line.is_empty(), self.sender.as_ref()) {
+            let _ = sender.try_send(line);
+        }
+    }
+}
+
+impl Write for QueuedWriter {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buffer);
+
+        if buffer.ends_with(b"\n") {
+            self.queue_line();
+        }
+
+        Ok(buffer.len())
+    }
+
+    /// Queues an incomplete line, it doesn't wait until it is written.
+    fn flush(&mut self) -> io::Result<()> {
+        self.queue_line();
+        Ok(())
+    }
+}
+
+/// Stops the writer thread after all remaining lines are written.
+impl Drop for QueuedWriter {
+    fn drop(&mut self) {
+        self.queue_line();
+        drop(self.sender.take());
+
+        if let Some(writer_thread) = self.writer_thread.take() {
+            let _ = writer_thread.join();
+        }
+    }
+}
+
+/// Writes every record as one line of json as described in the
+/// [`debug_protocol`] module.
+struct StructuredLogger {
+    level: LevelFilter,
+    stream: Mutex<QueuedWriter>,
+}
+
+impl StructuredLogger {
+    fn new(level: LevelFilter, stream: QueuedWriter) -> Self {
+        Self {
+            level,
+            stream: Mutex::new(stream),
+        }
+    }
+
+    fn write(&self, record: &debug_protocol::Record) {
+        let Ok(mut line) = serde_json::to_vec(record) else {
+            return;
+        };
+        line.push(b'\n');
+
+        if let Ok(mut stream) = self.stream.lock() {
+            let _ = stream.write_all(&line);
+        }
+    }
+}
+
+impl Log for StructuredLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        self.write(&debug_protocol::Record::Log(LogRecord {
+            level: record.level().to_string(),
+            target: record.target().to_owned(),
+            message: record.args().to_string(),
+        }));
+    }
+
+    fn flush(&self) {
+        if let Ok(mut stream) = self.stream.lock() {
+            let _ = stream.flush();
+        }
+    }
+}
+
+/// Log file that gets moved to `<path>.1` once it reaches the maximum size.
+/// Older files are moved up by one until [`MAX_ROTATED_LOG_FILES`] is reached.
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    /// Only `None` while rotating because windows can't rename open files.
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64) -> io::Result<Self> {
+        let file = File::options().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            max_size,
+            file: Some(file),
+            size,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    /// Starts a new file after moving the current and rotated ones up by one
+    /// index. If a file can't be moved, e.g. because a log viewer holds it on
+    /// Windows, the current file is kept and rotating is retried once another
+    /// `max_size` bytes were written to it.
+    fn rotate(&mut self) -> io::Result<()> {
+        drop(self.file.take());
+
+        let moved = self.move_rotated_files();
+        *self = Self::open(self.path.clone(), self.max_size)?;
+
+        if moved.is_err() {
+            self.size = 0;
+        }
+
+        Ok(())
+    }
+
+    fn move_rotated_files(&self) -> io::Result<()> {
+        for index in (1..MAX_ROTATED_LOG_FILES).rev() {
+            let from = self.rotated_path(index);
+
+            if from.exists() {
+                fs::rename(from, self.rotated_path(index + 1))?;
+            }
+        }
+
+        fs::rename(&self.path, self.rotated_path(1))
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buffer.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        let Some(file) = self.file.as_mut() else {
+            return Err(io::ErrorKind::NotFound.into());
+        };
+
+        let written = file.write(buffer)?;
+        self.size += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().map_or(Ok(()), Write::flush)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_sink_parsing() {
+        assert_eq!(LogSink::Stderr, "stderr".parse().unwrap());
+        assert_eq!(
+            LogSink::File("C:/akl.log".into()),
+            "file:C:/akl.log".parse().unwrap()
+        );
+        assert_eq!(
+            LogSink::Tcp(DEBUG_SERVER_ADDRESS.into()),
+            "tcp".parse().unwrap()
+        );
+        assert_eq!(
+            LogSink::Tcp("[::1]:7000".into()),
+            "tcp:[::1]:7000".parse().unwrap()
+        );
+        assert!("file:".parse::<LogSink>().is_err());
+        assert!("syslog".parse::<LogSink>().is_err());
+    }
+
+    /// Destination that blocks until the test releases it.
+    struct BlockedWriter {
+        release: Arc<Mutex<()>>,
+        written: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Write for BlockedWriter {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            let _release = self.release.lock().unwrap();
+            self.written.lock().unwrap().extend_from_slice(buffer);
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_queued_writer_never_blocks() {
+        let release = Arc::new(Mutex::new(()));
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let guard = release.lock().unwrap();
+
+        let mut writer = QueuedWriter::new(BlockedWriter {
+            release: Arc::clone(&release),
+            written: Arc::clone(&written),
+        });
+
+        // Only complete lines are queued, the rest is dropped once the queue
+        // is full while the destination is blocked.
+        write!(writer, "first ").unwrap();
+        writeln!(writer, "line").unwrap();
+
+        for index in 0..LOG_QUEUE_CAPACITY * 2 {
+            writeln!(writer, "{index}").unwrap();
+        }
+
+        drop(guard);
+        drop(writer);
+
+        let written =
+            String::from_utf8(written.lock().unwrap().clone()).unwrap();
+        let lines = written.lines().collect::<Vec<_>>();
+
+        assert_eq!(Some(&"first line"), lines.first());
+        assert!(lines.len() <= LOG_QUEUE_CAPACITY + 2);
+        assert!(lines.windows(2).skip(1).all(|pair| {
+            pair[0].parse::<usize>().unwrap() < pair[1].parse().unwrap()
+        }));
+    }
+
+    #[test]
+    fn test_rotating_file() {
+        let directory = std::env::temp_dir()
+            .join(format!("akl-rotating-file-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let path = directory.join("akl.log");
+        let mut file = RotatingFile::open(path.clone(), 10).unwrap();
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n", "fifth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!("fifth\n", fs::read_to_string(&path).unwrap());
+        assert_eq!(
+            "fourth\n",
+            fs::read_to_string(file.rotated_path(1)).unwrap()
+        );
+        assert_eq!(
+            "second\n",
+            fs::read_to_string(file.rotated_path(MAX_ROTATED_LOG_FILES))
+                .unwrap()
+        );
+        assert!(!file.rotated_path(MAX_ROTATED_LOG_FILES + 1).exists());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_rotating_file_failure() {
+        let directory = std::env::temp_dir()
+            .join(format!("akl-rotating-file-failure-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let path = directory.join("akl.log");
+        let mut file = RotatingFile::open(path.clone(), 10).unwrap();
+
+        // A non-empty directory can't be replaced by the rotated file.
+        let blocked = file.rotated_path(MAX_ROTATED_LOG_FILES);
+        fs::create_dir_all(blocked.join("held")).unwrap();
+        fs::write(file.rotated_path(MAX_ROTATED_LOG_FILES - 1), "").unwrap();
+
+        for line in ["first\n", "second\n", "third\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(
+            "first\nsecond\nthird\n",
+            fs::read_to_string(&path).unwrap()
+        );
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+//! Mouse keys that move the pointer, click and scroll from the virtual layer.
+//!
+//! A mouse mapping replaces its target key combination with a [`MouseAction`]
+//! instead of another key combination. Pressing the target starts the action
+//! and releasing it ends it, this way buttons can be held for dragging and the
+//! pointer keeps moving as long as the target is held. [`Movement`] turns the
+//! held directions into relative pointer movement with acceleration which the
+//! keyboard hook applies every [`MOVEMENT_INTERVAL`].
+
+use std::{f64::consts::FRAC_1_SQRT_2, fmt, str::FromStr, time::Duration};
+
+use thiserror::Error;
+
+/// Time between two pointer movements while any direction is held.
+pub const MOVEMENT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Pixels per second right after a direction was pressed.
+const INITIAL_SPEED: f64 = 200.0;
+
+/// Pixels per second the speed increases each second while moving.
+const ACCELERATION: f64 = 1200.0;
+
+/// Pixels per second the speed can't exceed.
+const MAX_SPEED: f64 = 2000.0;
+
+/// Error returned for names that aren't a [`MouseAction`].
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("Couldn't parse \"{0}\" as a mouse action (valid: Move<Direction>, Click<Button>, Scroll<Direction>).")]
+pub struct MouseActionParsingError(String);
+
+/// Direction of a pointer movement or scroll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    const ALL: [Self; 4] = [Self::Up, Self::Down, Self::Left, Self::Right];
+
+    /// Horizontal and vertical component, the y axis points down like screen
+    /// coordinates do.
+    fn vector(self) -> (i32, i32) {
+        match self {
+            Self::Up => (0, -1),
+            Self::Down => (0, 1),
+            Self::Left => (-1, 0),
+            Self::Right => (1, 0),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, formatter)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+impl MouseButton {
+    const ALL: [Self; 3] = [Self::Left, Self::Middle, Self::Right];
+}
+
+impl fmt::Display for MouseButton {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, formatter)
+    }
+}
+
+/// Output of a mouse mapping, written as `MoveLeft`, `ClickRight`,
+/// `ScrollDown`, etc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseAction {
+    /// Moves the pointer while the target is held.
+    Move(Direction),
+    /// Holds the button down while the target is held.
+    Click(MouseButton),
+    /// Scrolls one step for every press including auto repeat.
+    Scroll(Direction),
+}
+
+impl MouseAction {
+    /// Whether auto repeat of the target should repeat the action.
+    pub fn repeats(self) -> bool {
+        matches!(self, Self::Scroll(_))
+    }
+}
+
+impl fmt::Display for MouseAction {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Move(direction) => write!(formatter, "Move{direction}"),
+            Self::Click(button) => write!(formatter, "Click{button}"),
+            Self::Scroll(direction) => write!(formatter, "Scroll{direction}"),
+        }
+    }
+}
+
+impl FromStr for MouseAction {
+    type Err = MouseActionParsingError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let parse_direction = |name: &str| {
+            Direction::ALL
+                .into_iter()
+                .find(|direction| direction.to_string() == name)
+        };
+
+        let action = if let Some(direction) = raw.strip_prefix("Move") {
+            parse_direction(direction).map(Self::Move)
+        } else if let Some(button) = raw.strip_prefix("Click") {
+            MouseButton::ALL
+                .into_iter()
+                .find(|it| it.to_string() == button)
+                .map(Self::Click)
+        } else if let Some(direction) = raw.strip_prefix("Scroll") {
+            parse_direction(direction).map(Self::Scroll)
+        } else {
+            None
+        };
+
+        action.ok_or_else(|| MouseActionParsingError(raw.to_owned()))
+    }
+}
+
+/// Tracks the held directions and calculates how far the pointer moves.
+#[derive(Debug, Clone)]
+pub struct Movement {
+    held: Vec<Direction>,
+    moving_for: Duration,
+    /// Fractions of a pixel that didn't add up to a whole one yet.
+    remainder: (f64, f64),
+}
+
+impl Movement {
+    pub const fn new() -> Self {
+        Self {
+            held: vec![],
+            moving_for: Duration::ZERO,
+            remainder: (0.0, 0.0),
+        }
+    }
+
+    pub fn start(&mut self, direction: Direction) {
+        if !self.held.contains(&direction) {
+            self.held.push(direction);
+        }
+    }
+
+    /// Stops moving in the direction, the acceleration starts over once no
+    /// direction is held anymore.
+    pub fn stop(&mut self, direction: Direction) {
+        self.held.retain(|it| *it != direction);
+
+        if self.held.is_empty() {
+            self.stop_all();
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn is_moving(&self) -> bool {
+        !self.held.is_empty()
+    }
+
+    /// Current speed in pixels per second.
+    pub fn speed(&self) -> f64 {
+        ACCELERATION
+            .mul_add(self.moving_for.as_secs_f64(), INITIAL_SPEED)
+            .min(MAX_SPEED)
+    }
+
+    /// Advances the movement by the elapsed time and returns the whole pixels
+    /// the pointer has to move horizontally and vertically.
+    // Rounding to whole pixels is intended and the remainder is kept.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn tick(&mut self, elapsed: Duration) -> (i32, i32) {
+        if !self.is_moving() {
+            return (0, 0);
+        }
+
+        let (x, y) = self.held.iter().fold((0, 0), |(x, y), direction| {
+            let (dx, dy) = direction.vector();
+            (x + dx, y + dy)
+        });
+
+        let mut distance = self.speed() * elapsed.as_secs_f64();
+        self.moving_for += elapsed;
+
+        // Moving diagonally shouldn't be faster.
+        if x != 0 && y != 0 {
+            distance *= FRAC_1_SQRT_2;
+        }
+
+        self.remainder.0 += f64::from(x) * distance;
+        self.remainder.1 += f64::from(y) * distance;
+
+        let (dx, dy) = (self.remainder.0.trunc(), self.remainder.1.trunc());
+        self.remainder.0 -= dx;
+        self.remainder.1 -= dy;
+
+        (dx as i32, dy as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mouse_action() {
+        for action in [
+            MouseAction::Move(Direction::Left),
+            MouseAction::Click(MouseButton::Middle),
+            MouseAction::Scroll(Direction::Down),
+        ] {
+            assert_eq!(Ok(action), action.to_string().parse());
+        }
+
+        assert!("MoveSideways".parse::<MouseAction>().is_err());
+        assert!("Left".parse::<MouseAction>().is_err());
+    }
+
+    #[test]
+    fn test_movement() {
+        let mut movement = Movement::new();
+        assert_eq!((0, 0), movement.tick(MOVEMENT_INTERVAL));
+
+        movement.start(Direction::Right);
+        assert_eq!((2, 0), movement.tick(MOVEMENT_INTERVAL));
+
+        // Accelerates while held.
+        let (slow, _) = movement.tick(MOVEMENT_INTERVAL);
+        let (fast, _) = movement.tick(Duration::from_secs(1));
+        assert!(fast > slow * 100);
+
+        // Opposite directions cancel each other out.
+        movement.start(Direction::Left);
+        assert_eq!((0, 0), movement.tick(MOVEMENT_INTERVAL));
+
+        movement.stop(Direction::Left);
+        movement.stop(Direction::Right);
+        assert!(!movement.is_moving());
+
+        // The acceleration starts over.
+        movement.start(Direction::Down);
+        assert_eq!((0, 2), movement.tick(MOVEMENT_INTERVAL));
+    }
+}
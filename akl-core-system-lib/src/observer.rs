@@ -0,0 +1,190 @@
+//! Reports every processed event and the response to it to an observer without
+//! slowing down the keyboard hook. The observer also learns when the virtual
+//! layer disables itself.
+//!
+//! The keyboard hook only ever [`tries`](ObserverSender::notify) to put the
+//! notification into a bounded queue, a separate delivery thread takes them out
+//! and calls the actual callback. If the observer can't keep up the queue fills
+//! up and further notifications are dropped instead of delaying any input.
+
+use std::{
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    dispatch::DisableReason,
+    event::{Event, ResponseAction},
+};
+
+/// Maximum number of notifications that are waiting for delivery at the same
+/// time before new ones are dropped.
+pub const OBSERVER_QUEUE_CAPACITY: usize = 256;
+
+/// Something the observer gets notified about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notification {
+    /// The event was processed and responded to.
+    Processed(Event, ResponseAction),
+    /// The virtual layer disabled itself and passes every event through.
+    Disabled(DisableReason),
+}
+
+/// Owns the delivery thread which calls the callback for each notification.
+///
+/// Dropping the observer waits until every [`ObserverSender`] is dropped and
+/// all queued notifications are delivered.
+pub struct Observer {
+    sender: Option<SyncSender<Notification>>,
+    delivery_thread: Option<JoinHandle<()>>,
+}
+
+impl Observer {
+    /// Starts the delivery thread which calls the callback for every
+    /// notification in the order they were sent.
+    pub fn new(callback: impl Fn(Notification) + Send + 'static) -> Self {
+        let (sender, receiver) =
+            mpsc::sync_channel::<Notification>(OBSERVER_QUEUE_CAPACITY);
+
+        let delivery_thread = thread::spawn(move || {
+            for notification in receiver {
+                callback(notification);
+            }
+        });
+
+        Self {
+            sender: Some(sender),
+            delivery_thread: Some(delivery_thread),
+        }
+    }
+
+    /// Creates a new sender which can be passed to the keyboard hook.
+    pub fn sender(&self) -> ObserverSender {
+        ObserverSender(
+            self.sender
+                .clone()
+                .expect("Sender is only taken while dropping the observer."),
+        )
+    }
+}
+
+/// Stops the delivery thread after all remaining notifications are delivered.
+impl Drop for Observer {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        if let Some(delivery_thread) = self.delivery_thread.take() {
+            let _ = delivery_thread.join();
+        }
+    }
+}
+
+/// Sending half of the queue that is used by the keyboard hook.
+#[derive(Clone)]
+pub struct ObserverSender(SyncSender<Notification>);
+
+impl ObserverSender {
+    /// Queues the notification without ever blocking. Returns `false` if the
+    /// notification was dropped because the queue is full.
+    pub fn notify(&self, event: Event, response: ResponseAction) -> bool {
+        self.0
+            .try_send(Notification::Processed(event, response))
+            .is_ok()
+    }
+
+    /// Queues the notification that the virtual layer disabled itself without
+    /// ever blocking. Unlike processed events it is never dropped, if the queue
+    /// is full a separate thread waits until there is room again.
+    pub fn notify_disabled(&self, reason: DisableReason) {
+        if let Err(TrySendError::Full(notification)) =
+            self.0.try_send(Notification::Disabled(reason))
+        {
+            let sender = self.0.clone();
+
+            thread::spawn(move || {
+                let _ = sender.send(notification);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc, Mutex};
+
+    use crate::event::Action;
+
+    use super::*;
+
+    const EVENT: Event = Event {
+        action: Action::Press,
+        key: crate::key::Key::Text('a'),
+    };
+
+    #[test]
+    fn test_delivery() {
+        let (delivered_sender, delivered_receiver) = mpsc::channel();
+
+        let observer = Observer::new(move |notification| {
+            let _ = delivered_sender.send(notification);
+        });
+
+        assert!(observer.sender().notify(EVENT, ResponseAction::Block));
+        observer.sender().notify_disabled(DisableReason::KillSwitch);
+
+        drop(observer);
+
+        assert_eq!(
+            vec![
+                Notification::Processed(EVENT, ResponseAction::Block),
+                Notification::Disabled(DisableReason::KillSwitch),
+            ],
+            delivered_receiver.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_slow_observer_never_blocks() {
+        // The callback blocks the delivery thread until the test releases it.
+        let release = Arc::new(Mutex::new(()));
+        let guard = release.lock().unwrap();
+        let (started_sender, started_receiver) = mpsc::channel();
+        let (delivered_sender, delivered_receiver) = mpsc::channel();
+
+        let observer = {
+            let release = Arc::clone(&release);
+
+            Observer::new(move |notification| {
+                let _ = started_sender.send(());
+                drop(release.lock().unwrap());
+                let _ = delivered_sender.send(notification);
+            })
+        };
+
+        let sender = observer.sender();
+
+        // First notification is taken by the delivery thread.
+        assert!(sender.notify(EVENT, ResponseAction::Block));
+        started_receiver.recv().unwrap();
+
+        for _ in 0..OBSERVER_QUEUE_CAPACITY {
+            assert!(sender.notify(EVENT, ResponseAction::Block));
+        }
+
+        assert!(!sender.notify(EVENT, ResponseAction::Block));
+
+        // Disabling isn't dropped even though the queue is full.
+        sender.notify_disabled(DisableReason::Watchdog { overruns: 3 });
+
+        drop(guard);
+        drop(sender);
+        drop(observer);
+
+        assert_eq!(
+            Some(Notification::Disabled(DisableReason::Watchdog {
+                overruns: 3
+            })),
+            delivered_receiver.iter().last()
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Named [`configurations`](Configuration) of one virtual layer of which at
+//! most one is active.
+//!
+//! A running virtual layer switches between its profiles without being
+//! restarted, either through the api or by pressing the target of a
+//! [`profile mapping`](Configuration::profile_mappings). The statistics keep
+//! counting across switches and keys that are still held down when switching
+//! stay blocked until they are released, see
+//! [`EventProcessor::switch_to`](crate::event::EventProcessor::switch_to).
+
+use thiserror::Error;
+
+use crate::Configuration;
+
+/// Name of the profile that holds the top level configuration of a
+/// configuration file, so that profile mappings can switch back to it.
+#[cfg(feature = "config-serde")]
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ProfileError {
+    #[error("There is no profile named \"{0}\".")]
+    Unknown(String),
+    #[error("The profile \"{0}\" has no switch key.")]
+    NotConfigured(String),
+}
+
+/// Named configurations in the order they were saved.
+#[derive(Debug, Default, Clone)]
+pub struct Profiles {
+    profiles: Vec<(String, Configuration)>,
+    active: Option<usize>,
+}
+
+impl Profiles {
+    /// Saves a copy of the configuration under the name, replacing the profile
+    /// with the same name if there is one.
+    pub fn save(&mut self, name: &str, configuration: Configuration) {
+        match self.position(name) {
+            Some(index) => self.profiles[index].1 = configuration,
+            None => self.profiles.push((name.to_owned(), configuration)),
+        }
+    }
+
+    /// Removes the profile with the name. Only returns `true` if there was
+    /// one.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let Some(index) = self.position(name) else {
+            return false;
+        };
+
+        self.profiles.remove(index);
+
+        self.active = match self.active {
+            Some(active) if active == index => None,
+            Some(active) if active > index => Some(active - 1),
+            active => active,
+        };
+
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.profiles.clear();
+        self.active = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Configuration> {
+        self.position(name).map(|index| &self.profiles[index].1)
+    }
+
+    /// Index of the profile with the name which stays the same until a
+    /// profile is removed.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.profiles.iter().position(|(it, _)| it == name)
+    }
+
+    /// Name of the active profile, `None` until a profile was activated.
+    pub fn active(&self) -> Option<&str> {
+        self.active.map(|index| self.profiles[index].0.as_str())
+    }
+
+    /// Position of the profile with the name if it can be
+    /// [`activated`](Self::activate).
+    ///
+    /// # Errors
+    ///
+    /// - [`ProfileError::Unknown`] => If there is no profile with the name
+    /// - [`ProfileError::NotConfigured`] => If the profile has no switch key
+    pub fn check(&self, name: &str) -> Result<usize, ProfileError> {
+        let index = self
+            .position(name)
+            .ok_or_else(|| ProfileError::Unknown(name.to_owned()))?;
+
+        if self.profiles[index].1.switch_key.is_none() {
+            return Err(ProfileError::NotConfigured(name.to_owned()));
+        }
+
+        Ok(index)
+    }
+
+    /// Activates the profile with the name and returns its configuration.
+    ///
+    /// # Errors
+    ///
+    /// See [`check`](Self::check).
+    pub fn activate(
+        &mut self,
+        name: &str,
+    ) -> Result<&Configuration, ProfileError> {
+        let index = self.check(name)?;
+        self.active = Some(index);
+
+        Ok(&self.profiles[index].1)
+    }
+
+    /// Activates the profile at the [`position`](Self::position) and returns
+    /// its configuration. Does nothing if there is no such profile.
+    pub fn activate_at(&mut self, index: usize) -> Option<&Configuration> {
+        let (_, configuration) = self.profiles.get(index)?;
+        self.active = Some(index);
+
+        Some(configuration)
+    }
+
+    /// Checks that every profile can be used by an event processor and that
+    /// the profile mappings of the profiles and the current configuration only
+    /// switch to existing profiles.
+    ///
+    /// # Errors
+    ///
+    /// - [`ProfileError::NotConfigured`] => If a profile has no switch key
+    /// - [`ProfileError::Unknown`] => If a profile mapping switches to a
+    ///   profile that doesn't exist
+    pub fn validate(
+        &self,
+        configuration: &Configuration,
+    ) -> Result<(), ProfileError> {
+        for (name, profile) in &self.profiles {
+            if profile.switch_key.is_none() {
+                return Err(ProfileError::NotConfigured(name.clone()));
+            }
+        }
+
+        let profile_mappings = self
+            .profiles
+            .iter()
+            .map(|(_, profile)| profile)
+            .chain([configuration])
+            .flat_map(|profile| profile.profile_mappings.values());
+
+        for name in profile_mappings {
+            if self.position(name).is_none() {
+                return Err(ProfileError::Unknown(name.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections;
+
+    use crate::key::VirtualKey;
+
+    use super::*;
+
+    fn configuration(switch_key: VirtualKey) -> Configuration {
+        Configuration {
+            switch_key: Some(switch_key.into()),
+            ..Configuration::default()
+        }
+    }
+
+    #[test]
+    fn test_profiles() {
+        let mut profiles = Profiles::default();
+        profiles.save("coding", configuration(VirtualKey::CapsLock));
+        profiles.save("gaming", configuration(VirtualKey::Tab));
+        profiles.save("writing", Configuration::default());
+
+        assert_eq!(None, profiles.active());
+        assert_eq!(
+            Some(VirtualKey::Tab.into()),
+            profiles.activate("gaming").unwrap().switch_key
+        );
+        assert_eq!(Some("gaming"), profiles.active());
+
+        assert_eq!(
+            Err(ProfileError::NotConfigured("writing".to_owned())),
+            profiles.activate("writing").map(|_| ())
+        );
+        assert_eq!(Ok(0), profiles.check("coding"));
+        assert_eq!(Some("gaming"), profiles.active());
+        assert_eq!(
+            Err(ProfileError::Unknown("reading".to_owned())),
+            profiles.activate("reading").map(|_| ())
+        );
+
+        // Saving again keeps the position.
+        profiles.save("coding", configuration(VirtualKey::Escape));
+        assert_eq!(Some(0), profiles.position("coding"));
+
+        // The active profile stays active when others are removed.
+        assert!(profiles.remove("coding"));
+        assert!(!profiles.remove("coding"));
+        assert_eq!(Some("gaming"), profiles.active());
+        assert_eq!(
+            Some(VirtualKey::Tab.into()),
+            profiles.activate_at(0).map(|it| it.switch_key).unwrap()
+        );
+
+        assert!(profiles.remove("gaming"));
+        assert_eq!(None, profiles.active());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut profiles = Profiles::default();
+        profiles.save("coding", configuration(VirtualKey::CapsLock));
+
+        let mut current = configuration(VirtualKey::CapsLock);
+        current.profile_mappings = collections::HashMap::from([(
+            "LAlt+1".parse().unwrap(),
+            "coding".to_owned(),
+        )]);
+        assert_eq!(Ok(()), profiles.validate(&current));
+
+        current
+            .profile_mappings
+            .insert("LAlt+2".parse().unwrap(), "gaming".to_owned());
+        assert_eq!(
+            Err(ProfileError::Unknown("gaming".to_owned())),
+            profiles.validate(&current)
+        );
+
+        profiles.save("gaming", Configuration::default());
+        assert_eq!(
+            Err(ProfileError::NotConfigured("gaming".to_owned())),
+            profiles.validate(&current)
+        );
+    }
+}
@@ -0,0 +1,474 @@
+//! Small text format for describing how the [`event processor`](crate::event::EventProcessor)
+//! is expected to respond to a sequence of keyboard events.
+//!
+//! A scenario consists of `config:` lines that (re)configure a fresh event
+//! processor and event lines that each describe one event and the expected
+//! [`response`](crate::event::ResponseAction). Empty lines and everything after
+//! a `#` is ignored.
+//!
+//! ```text
+//! # The config line accepts the special "switch", "default" and "pause"
+//! # entries, every other entry is a mapping from target to replacement key
+//! # combination or mouse action.
+//! config: switch=CapsLock, default=Escape, h=LeftArrow, LControl+j=PageUp, m=ClickLeft
+//!
+//! press CapsLock => block
+//! press h => LeftArrow
+//! release h => block
+//! press m => press ClickLeft
+//! release m => release ClickLeft
+//! release CapsLock => block
+//! press a => nothing
+//! ```
+//!
+//! Keys and key combinations use the same syntax as the configuration file
+//! (`LControl+j`, `CapsLock`, `h`). Because `,` and `=` separate the entries of
+//! a config line they can't be used as keys inside of it.
+
+use std::{collections, fmt, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    event::{Action, Event, EventProcessor, ResponseAction},
+    key::{Key, KeyCombination, KeyParsingError, TextOutput},
+    mouse::{MouseAction, MouseActionParsingError},
+    Configuration,
+};
+
+/// Represents any errors that can occur while parsing or running a scenario.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ScenarioError {
+    #[error("Line {line}: {reason}")]
+    InvalidSyntax { line: usize, reason: String },
+    #[error("Line {line}: No switch key configured before the first event.")]
+    NotConfigured { line: usize },
+    #[error("{}", display_mismatches(.0))]
+    Mismatches(Vec<Mismatch>),
+}
+
+/// An event whose actual response differs from the expected one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub line: usize,
+    pub expected: ResponseAction,
+    pub actual: ResponseAction,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "Line {}: Expected \"{}\" but got \"{}\".",
+            self.line, self.expected, self.actual
+        )
+    }
+}
+
+/// Joins all mismatches so that each of them ends up on a separate line.
+fn display_mismatches(mismatches: &[Mismatch]) -> String {
+    mismatches
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A single parsed line of a scenario.
+#[derive(Debug, Clone)]
+enum Instruction {
+    Configure(Configuration),
+    Expect {
+        line: usize,
+        event: Event,
+        response: ResponseAction,
+    },
+}
+
+/// Parsed scenario that can be [`run`](Scenario::run) against an event
+/// processor.
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    instructions: Vec<Instruction>,
+}
+
+impl Scenario {
+    /// Processes all events of the scenario in order and compares the actual
+    /// responses with the expected ones.
+    ///
+    /// Until the scenario contains its first `config:` line the events are
+    /// processed according to the `fallback` configuration. Each `config:` line
+    /// replaces the event processor with a fresh one.
+    ///
+    /// # Errors
+    ///
+    /// - [`ScenarioError::NotConfigured`] => If an event has to be processed
+    ///   without any configured switch key
+    /// - [`ScenarioError::Mismatches`] => If any response differs from the
+    ///   expected one
+    pub fn run(&self, fallback: &Configuration) -> Result<(), ScenarioError> {
+        let mut event_processor: Option<EventProcessor> = fallback
+            .switch_key
+            .is_some()
+            .then(|| fallback.clone().into());
+
+        let mut mismatches = vec![];
+
+        for instruction in &self.instructions {
+            match instruction {
+                Instruction::Configure(configuration) => {
+                    event_processor = Some(configuration.clone().into());
+                }
+                Instruction::Expect {
+                    line,
+                    event,
+                    response,
+                } => {
+                    let Some(event_processor) = event_processor.as_mut() else {
+                        return Err(ScenarioError::NotConfigured {
+                            line: *line,
+                        });
+                    };
+
+                    let actual = event_processor.process(*event);
+
+                    if actual != *response {
+                        mismatches.push(Mismatch {
+                            line: *line,
+                            expected: *response,
+                            actual,
+                        });
+                    }
+                }
+            }
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(ScenarioError::Mismatches(mismatches))
+        }
+    }
+}
+
+/// Parses the text format described in the [`module`](self) documentation.
+impl FromStr for Scenario {
+    type Err = ScenarioError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let mut instructions = vec![];
+
+        for (index, raw_line) in raw.lines().enumerate() {
+            let line = index + 1;
+            let invalid =
+                |reason: String| ScenarioError::InvalidSyntax { line, reason };
+
+            let content = raw_line
+                .split_once('#')
+                .map_or(raw_line, |(content, _)| content)
+                .trim();
+
+            if content.is_empty() {
+                continue;
+            }
+
+            if let Some(entries) = content.strip_prefix("config:") {
+                instructions.push(Instruction::Configure(
+                    parse_configuration(entries).map_err(invalid)?,
+                ));
+                continue;
+            }
+
+            let (raw_event, raw_response) =
+                content.split_once("=>").ok_or_else(|| {
+                    invalid(format!("Expected \"<event> => <response>\" but got \"{content}\"."))
+                })?;
+
+            instructions.push(Instruction::Expect {
+                line,
+                event: parse_event(raw_event.trim()).map_err(invalid)?,
+                response: parse_response(raw_response.trim())
+                    .map_err(invalid)?,
+            });
+        }
+
+        Ok(Self { instructions })
+    }
+}
+
+/// Parses the comma separated entries of a `config:` line.
+fn parse_configuration(raw: &str) -> Result<Configuration, String> {
+    let mut switch_key = None;
+    let mut default_combination = None;
+    let mut pause_combination = None;
+    let mut mappings = collections::HashMap::new();
+    let mut mouse_mappings = collections::HashMap::new();
+
+    for entry in raw.split(',').map(str::trim).filter(|it| !it.is_empty()) {
+        let (name, value) = entry
+            .split_once('=')
+            .map(|(name, value)| (name.trim(), value.trim()))
+            .ok_or_else(|| {
+                format!("Expected \"<name>=<value>\" but got \"{entry}\".")
+            })?;
+
+        match name {
+            "switch" => switch_key = Some(parse_key(value)?),
+            "default" => default_combination = Some(parse_combination(value)?),
+            "pause" => pause_combination = Some(parse_combination(value)?),
+            target => {
+                let target = parse_combination(target)?;
+
+                if let Ok(mouse_action) = value.parse::<MouseAction>() {
+                    mouse_mappings.insert(target, mouse_action);
+                } else {
+                    mappings.insert(target, parse_combination(value)?);
+                }
+            }
+        }
+    }
+
+    if switch_key.is_none() {
+        return Err("A config line has to specify the switch key.".to_owned());
+    }
+
+    Ok(Configuration {
+        switch_key,
+        default_combination,
+        pause_combination,
+        mappings,
+        mouse_mappings,
+        profile_mappings: collections::HashMap::new(),
+        application_mappings: Vec::new(),
+        text_output: TextOutput::default(),
+    })
+}
+
+/// Parses `press <key>` or `release <key>`.
+fn parse_event(raw: &str) -> Result<Event, String> {
+    let (action, raw_key) = parse_action(raw)?;
+
+    Ok(Event {
+        action,
+        key: parse_key(raw_key)?,
+    })
+}
+
+/// Splits `press <rest>` or `release <rest>` into the action and the rest.
+fn parse_action(raw: &str) -> Result<(Action, &str), String> {
+    let (raw_action, rest) =
+        raw.split_once(char::is_whitespace).ok_or_else(|| {
+            format!("Expected \"<action> <key>\" but got \"{raw}\".")
+        })?;
+
+    let action = match raw_action {
+        "press" => Action::Press,
+        "release" => Action::Release,
+        _ => {
+            return Err(format!(
+                "Unknown action \"{raw_action}\" (valid: press, release)."
+            ))
+        }
+    };
+
+    Ok((action, rest.trim()))
+}
+
+/// Parses `block`, `nothing`, the key combination that should replace the
+/// event or the action of a mouse action (`press MoveLeft`).
+fn parse_response(raw: &str) -> Result<ResponseAction, String> {
+    match raw {
+        "block" => Ok(ResponseAction::Block),
+        "nothing" => Ok(ResponseAction::DoNothing),
+        mouse if mouse.contains(char::is_whitespace) => {
+            let (action, raw_mouse_action) = parse_action(mouse)?;
+            let mouse_action = raw_mouse_action
+                .parse()
+                .map_err(|error: MouseActionParsingError| error.to_string())?;
+
+            Ok(ResponseAction::Mouse(mouse_action, action))
+        }
+        combination => {
+            parse_combination(combination).map(ResponseAction::ReplaceWith)
+        }
+    }
+}
+
+/// Parses a key the same way the configuration file does.
+fn parse_key(raw: &str) -> Result<Key, String> {
+    raw.parse()
+        .map_err(|error: KeyParsingError| error.to_string())
+}
+
+/// Parses a key combination the same way the configuration file does.
+fn parse_combination(raw: &str) -> Result<KeyCombination, String> {
+    raw.parse()
+        .map_err(|error: KeyParsingError| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::key::VirtualKey;
+
+    use super::*;
+
+    #[test]
+    fn test_readme_scenario() {
+        // Same events as `test_event_processor` in the event module.
+        let scenario: Scenario = "
+            config: switch=Space, default=Return, t=a
+
+            press a => nothing
+            press Space => block
+            release a => nothing
+            press a => block
+            release a => block
+            release Space => Return
+
+            press Space => block
+            press t => a
+            release Space => block
+        "
+        .parse()
+        .expect("Scenario should be valid.");
+
+        assert_eq!(Ok(()), scenario.run(&Configuration::default()));
+    }
+
+    #[test]
+    fn test_fallback_configuration() {
+        let scenario: Scenario =
+            "press CapsLock => block\npress h => LeftArrow"
+                .parse()
+                .expect("Scenario should be valid.");
+
+        assert_eq!(
+            Err(ScenarioError::NotConfigured { line: 1 }),
+            scenario.run(&Configuration::default())
+        );
+
+        let fallback = Configuration {
+            switch_key: Some(VirtualKey::CapsLock.into()),
+            default_combination: None,
+            pause_combination: None,
+            mappings: collections::HashMap::from([(
+                parse_combination("h").unwrap(),
+                parse_combination("LeftArrow").unwrap(),
+            )]),
+            mouse_mappings: collections::HashMap::new(),
+            profile_mappings: collections::HashMap::new(),
+            application_mappings: Vec::new(),
+            text_output: TextOutput::default(),
+        };
+
+        assert_eq!(Ok(()), scenario.run(&fallback));
+    }
+
+    #[test]
+    fn test_mouse_mappings() {
+        let scenario: Scenario = "
+            config: switch=CapsLock, h=MoveLeft, m=ClickLeft, j=ScrollDown
+            press CapsLock => block
+            press h => press MoveLeft
+            release h => release MoveLeft
+
+            # Auto repeat only repeats scrolling.
+            press h => press MoveLeft
+            press h => block
+            press m => press ClickLeft
+            press m => block
+            press j => press ScrollDown
+            press j => press ScrollDown
+            release j => release ScrollDown
+
+            # Mouse actions end even if the switch key was released first.
+            release CapsLock => block
+            release m => release ClickLeft
+            press m => nothing
+        "
+        .parse()
+        .expect("Scenario should be valid.");
+
+        assert_eq!(Ok(()), scenario.run(&Configuration::default()));
+    }
+
+    #[test]
+    fn test_pause() {
+        let scenario: Scenario = "
+            config: switch=CapsLock, pause=LControl+p, h=LeftArrow
+
+            # Keys held down on the layer stay blocked after pausing.
+            press CapsLock => block
+            press LControl => block
+            press p => block
+            release p => block
+            release LControl => block
+            release CapsLock => block
+
+            press CapsLock => nothing
+            press h => nothing
+            release h => nothing
+            release CapsLock => nothing
+
+            press LControl => nothing
+            press p => block
+            release p => block
+            release LControl => nothing
+
+            press CapsLock => block
+            press h => LeftArrow
+        "
+        .parse()
+        .expect("Scenario should be valid.");
+
+        assert_eq!(Ok(()), scenario.run(&Configuration::default()));
+    }
+
+    #[test]
+    fn test_mismatches() {
+        let scenario: Scenario = "
+            config: switch=CapsLock, h=LeftArrow # comment
+            press CapsLock => nothing
+            press h => LeftArrow
+            press j => nothing
+        "
+        .parse()
+        .expect("Scenario should be valid.");
+
+        assert_eq!(
+            Err(ScenarioError::Mismatches(vec![
+                Mismatch {
+                    line: 3,
+                    expected: ResponseAction::DoNothing,
+                    actual: ResponseAction::Block,
+                },
+                Mismatch {
+                    line: 5,
+                    expected: ResponseAction::DoNothing,
+                    actual: ResponseAction::Block,
+                }
+            ])),
+            scenario.run(&Configuration::default())
+        );
+    }
+
+    #[test]
+    fn test_invalid_syntax() {
+        macro_rules! assert_invalid_line {
+            ($raw: expr, $line: expr) => {
+                assert!(matches!(
+                    $raw.parse::<Scenario>(),
+                    Err(ScenarioError::InvalidSyntax { line: $line, .. })
+                ));
+            };
+        }
+
+        assert_invalid_line!("config: h=LeftArrow", 1);
+        assert_invalid_line!("config: switch=CapsLock, h", 1);
+        assert_invalid_line!("\npress CapsLock", 2);
+        assert_invalid_line!("hold CapsLock => block", 1);
+        assert_invalid_line!("press NoKey => block", 1);
+        assert_invalid_line!("press a => a+b+c+d+e", 1);
+        assert_invalid_line!("press a => press MoveSideways", 1);
+    }
+}
@@ -0,0 +1,237 @@
+//! Usage statistics collected by the [`event processor`](crate::event::EventProcessor)
+//! to find out which mappings are actually used.
+//!
+//! Only counters are kept, the order in which keys were pressed is never
+//! stored. The statistics can be persisted to a small text file with one
+//! counter per line:
+//!
+//! ```text
+//! layer_activations 12
+//! default_combination_triggers 3
+//! mapping 7 LControl+j
+//! blocked 2 a
+//! ```
+
+use std::{collections, fmt, fs, io, path::Path, str::FromStr};
+
+use thiserror::Error;
+
+use crate::key::{Key, KeyCombination};
+
+/// Counters for how the virtual layer was used.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Statistics {
+    /// How often the switch key activated the virtual layer.
+    pub layer_activations: u64,
+    /// How often the default combination was simulated.
+    pub default_combination_triggers: u64,
+    /// How often each mapping fired, keyed by the target key combination.
+    pub mappings: collections::HashMap<KeyCombination, u64>,
+    /// How often each key was blocked while the virtual layer was active
+    /// without triggering any mapping.
+    pub blocked_key_presses: collections::HashMap<Key, u64>,
+}
+
+#[derive(Error, Debug)]
+pub enum StatisticsError {
+    #[error("Couldn't access the statistics file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Line {line} of the statistics file is invalid: {reason}")]
+    InvalidLine { line: usize, reason: String },
+}
+
+impl Statistics {
+    /// Adds all counters of `other` to these statistics.
+    pub fn merge(&mut self, other: &Self) {
+        self.layer_activations += other.layer_activations;
+        self.default_combination_triggers += other.default_combination_triggers;
+
+        for (target, count) in &other.mappings {
+            *self.mappings.entry(*target).or_default() += count;
+        }
+
+        for (key, count) in &other.blocked_key_presses {
+            *self.blocked_key_presses.entry(*key).or_default() += count;
+        }
+    }
+
+    /// Sum of how often any mapping fired.
+    #[must_use]
+    pub fn total_mapping_triggers(&self) -> u64 {
+        self.mappings.values().sum()
+    }
+
+    /// Sum of how often any key was blocked.
+    #[must_use]
+    pub fn total_blocked_key_presses(&self) -> u64 {
+        self.blocked_key_presses.values().sum()
+    }
+
+    /// Loads the statistics from the file or returns empty statistics if the
+    /// file doesn't exist yet.
+    ///
+    /// # Errors
+    ///
+    /// - [`StatisticsError::Io`] => If the file exists but can't be read
+    /// - [`StatisticsError::InvalidLine`] => If the content isn't valid
+    pub fn load(path: &Path) -> Result<Self, StatisticsError> {
+        match fs::read_to_string(path) {
+            Ok(raw) => raw.parse(),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Overwrites the file with these statistics.
+    ///
+    /// # Errors
+    ///
+    /// - [`StatisticsError::Io`] => If writing the file fails
+    pub fn save(&self, path: &Path) -> Result<(), StatisticsError> {
+        Ok(fs::write(path, self.to_string())?)
+    }
+}
+
+/// Writes the file format described in the [`module`](self) documentation.
+impl fmt::Display for Statistics {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(formatter, "layer_activations {}", self.layer_activations)?;
+        writeln!(
+            formatter,
+            "default_combination_triggers {}",
+            self.default_combination_triggers
+        )?;
+
+        for (target, count) in &self.mappings {
+            writeln!(formatter, "mapping {count} {target}")?;
+        }
+
+        for (key, count) in &self.blocked_key_presses {
+            writeln!(formatter, "blocked {count} {key}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses the file format described in the [`module`](self) documentation.
+impl FromStr for Statistics {
+    type Err = StatisticsError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let mut statistics = Self::default();
+
+        for (index, raw_line) in raw.lines().enumerate() {
+            let invalid = |reason: String| StatisticsError::InvalidLine {
+                line: index + 1,
+                reason,
+            };
+
+            if raw_line.trim().is_empty() {
+                continue;
+            }
+
+            let mut parts = raw_line.splitn(3, ' ');
+            let name = parts.next().unwrap_or_default();
+            let count = parts
+                .next()
+                .ok_or_else(|| invalid("Missing count.".to_owned()))?
+                .parse::<u64>()
+                .map_err(|error| invalid(error.to_string()))?;
+            let key = parts.next();
+
+            match (name, key) {
+                ("layer_activations", None) => {
+                    statistics.layer_activations = count;
+                }
+                ("default_combination_triggers", None) => {
+                    statistics.default_combination_triggers = count;
+                }
+                ("mapping", Some(target)) => {
+                    let target = target
+                        .parse()
+                        .map_err(|error| invalid(format!("{error}")))?;
+                    statistics.mappings.insert(target, count);
+                }
+                ("blocked", Some(key)) => {
+                    let key = key
+                        .parse()
+                        .map_err(|error| invalid(format!("{error}")))?;
+                    statistics.blocked_key_presses.insert(key, count);
+                }
+                _ => {
+                    return Err(invalid(format!("Unknown counter \"{name}\".")))
+                }
+            }
+        }
+
+        Ok(statistics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::key::VirtualKey;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let statistics = Statistics {
+            layer_activations: 12,
+            default_combination_triggers: 3,
+            mappings: collections::HashMap::from([
+                ("LControl+j".parse().unwrap(), 7),
+                ("h".parse().unwrap(), 1),
+            ]),
+            blocked_key_presses: collections::HashMap::from([
+                (Key::Text('='), 2),
+                (Key::Virtual(VirtualKey::Tab), 4),
+            ]),
+        };
+
+        assert_eq!(statistics, statistics.to_string().parse().unwrap());
+        assert_eq!(8, statistics.total_mapping_triggers());
+        assert_eq!(6, statistics.total_blocked_key_presses());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut first = Statistics {
+            layer_activations: 1,
+            mappings: collections::HashMap::from([("h".parse().unwrap(), 1)]),
+            ..Default::default()
+        };
+
+        let second = Statistics {
+            layer_activations: 2,
+            default_combination_triggers: 1,
+            mappings: collections::HashMap::from([
+                ("h".parse().unwrap(), 2),
+                ("j".parse().unwrap(), 1),
+            ]),
+            ..Default::default()
+        };
+
+        first.merge(&second);
+
+        assert_eq!(3, first.layer_activations);
+        assert_eq!(1, first.default_combination_triggers);
+        assert_eq!(Some(&3), first.mappings.get(&"h".parse().unwrap()));
+        assert_eq!(Some(&1), first.mappings.get(&"j".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_invalid_lines() {
+        assert!(matches!(
+            "layer_activations 1\nmapping x LeftArrow".parse::<Statistics>(),
+            Err(StatisticsError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            "presses 1".parse::<Statistics>(),
+            Err(StatisticsError::InvalidLine { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,101 @@
+//! Keeps a stalled keyboard hook from stalling every keystroke on the system.
+//!
+//! Each event gets a [time budget](EVENT_TIME_BUDGET). The keyboard hook only
+//! waits for the event processor until the budget is used up and passes events
+//! that exceed it through unchanged, taking back whatever the event processor
+//! already did with them. Once the budget was exceeded
+//! [too often in a row](MAX_CONSECUTIVE_OVERRUNS) the virtual layer disables
+//! itself and passes every following event through.
+
+use std::time::Duration;
+
+/// Time the keyboard hook may spend on one event, including waiting for the
+/// event processor.
+pub const EVENT_TIME_BUDGET: Duration = Duration::from_millis(50);
+
+/// Number of events in a row that may exceed the time budget before the
+/// virtual layer gets disabled.
+pub const MAX_CONSECUTIVE_OVERRUNS: u32 = 3;
+
+/// How the keyboard hook should carry on after the event that was checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The event was processed in time.
+    WithinBudget,
+    /// Pass the event through unchanged, the virtual layer keeps running.
+    Overrun,
+    /// Pass the event through and disable the virtual layer because too many
+    /// events in a row took too long.
+    Disable,
+}
+
+/// Counts how many events in a row exceeded the time budget.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    budget: Duration,
+    max_consecutive_overruns: u32,
+    consecutive_overruns: u32,
+}
+
+impl Watchdog {
+    pub const fn new(budget: Duration, max_consecutive_overruns: u32) -> Self {
+        Self {
+            budget,
+            max_consecutive_overruns,
+            consecutive_overruns: 0,
+        }
+    }
+
+    /// Time the keyboard hook may spend on one event.
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    /// Checks the time spent on an event once it is done.
+    pub fn check(&mut self, elapsed: Duration) -> Verdict {
+        if elapsed <= self.budget {
+            self.consecutive_overruns = 0;
+            return Verdict::WithinBudget;
+        }
+
+        self.consecutive_overruns += 1;
+
+        if self.consecutive_overruns >= self.max_consecutive_overruns {
+            Verdict::Disable
+        } else {
+            Verdict::Overrun
+        }
+    }
+
+    /// Number of events in a row that exceeded the budget.
+    pub fn consecutive_overruns(&self) -> u32 {
+        self.consecutive_overruns
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new(EVENT_TIME_BUDGET, MAX_CONSECUTIVE_OVERRUNS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog() {
+        let mut watchdog = Watchdog::new(Duration::from_millis(10), 2);
+        let fast = Duration::from_millis(1);
+        let slow = Duration::from_millis(11);
+
+        assert_eq!(Verdict::WithinBudget, watchdog.check(fast));
+        assert_eq!(Verdict::Overrun, watchdog.check(slow));
+
+        // Overruns have to happen in a row.
+        assert_eq!(Verdict::WithinBudget, watchdog.check(fast));
+        assert_eq!(Verdict::Overrun, watchdog.check(slow));
+        assert_eq!(Verdict::Disable, watchdog.check(slow));
+        assert_eq!(2, watchdog.consecutive_overruns());
+    }
+}